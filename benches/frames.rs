@@ -0,0 +1,94 @@
+//! Frame encoding, decoding and measured values extraction
+//!
+//! Run with `cargo bench`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use sps30::protocol::{self, FrameParser, Validation};
+use sps30::{CommandType, SpecialChars};
+
+/// Read Measured Values MISO Frame carrying the 40 bytes of values in float format
+const MEASURED_VALUES: [u8; 47] = [
+    0x7E, 0x00, 0x03, 0x00, 0x28, 0x40, 0xA0, 0x00, 0x00, 0x41, 0x20, 0x00, 0x00, 0x41, 0x48, 0x00,
+    0x00, 0x41, 0x50, 0x00, 0x00, 0x41, 0xF0, 0x00, 0x00, 0x42, 0x34, 0x00, 0x00, 0x42, 0x48, 0x00,
+    0x00, 0x42, 0x4C, 0x00, 0x00, 0x42, 0x50, 0x00, 0x00, 0x3F, 0x00, 0x00, 0x00, 0xE9, 0x7E,
+];
+
+/// Data of [`MEASURED_VALUES`]
+fn measured_data() -> &'static [u8] {
+    &MEASURED_VALUES[5..45]
+}
+
+fn checksum(c: &mut Criterion) {
+    let frame = &MEASURED_VALUES[1..45];
+    c.bench_function("compute_cksum", |b| {
+        b.iter(|| protocol::compute_cksum(black_box(frame)))
+    });
+}
+
+fn encode(c: &mut Criterion) {
+    let data = [0x00, 0x09, 0x3A, 0x80, 0x7E];
+    c.bench_function("encode_request", |b| {
+        b.iter(|| {
+            protocol::encode_request::<(), ()>(
+                CommandType::ReadWriteAutoCleaningInterval,
+                black_box(&data),
+            )
+        })
+    });
+    c.bench_function("encode_request_into", |b| {
+        let mut buf = [0; protocol::ENCODED_FRAME_SIZE];
+        b.iter(|| {
+            protocol::encode_request_into::<(), ()>(
+                CommandType::ReadWriteAutoCleaningInterval,
+                black_box(&data),
+                SpecialChars::default(),
+                &mut buf,
+            )
+        })
+    });
+}
+
+fn decode(c: &mut Criterion) {
+    c.bench_function("parse_response", |b| {
+        b.iter(|| {
+            protocol::parse_response::<(), ()>(
+                black_box(&MEASURED_VALUES),
+                CommandType::ReadMeasuredData,
+            )
+        })
+    });
+    c.bench_function("parse_response_into", |b| {
+        let mut buf = [0; protocol::FRAME_SIZE];
+        b.iter(|| {
+            protocol::parse_response_into::<(), ()>(
+                black_box(&MEASURED_VALUES),
+                CommandType::ReadMeasuredData,
+                SpecialChars::default(),
+                Validation::Strict,
+                &mut buf,
+            )
+            .map(|data| data.len())
+        })
+    });
+    c.bench_function("frame_parser", |b| {
+        let mut parser = FrameParser::new();
+        b.iter(|| {
+            black_box(&MEASURED_VALUES)
+                .iter()
+                .filter_map(|&byte| parser.feed_byte(byte))
+                .count()
+        })
+    });
+}
+
+fn measurement(c: &mut Criterion) {
+    c.bench_function("parse_measurement", |b| {
+        b.iter(|| protocol::parse_measurement::<(), ()>(black_box(measured_data())))
+    });
+    c.bench_function("parse_measurement_bits", |b| {
+        b.iter(|| protocol::parse_measurement_bits::<(), ()>(black_box(measured_data())))
+    });
+}
+
+criterion_group!(benches, checksum, encode, decode, measurement);
+criterion_main!(benches);
@@ -0,0 +1,43 @@
+// Reads float values, only the fallback `main` is built with the `no-float` feature
+#![cfg_attr(feature = "no-float", allow(dead_code, unused_imports))]
+
+use embedded_hal::delay::DelayNs;
+use sps30::{DeviceInfo, MeasurementFormat, Sps30};
+use std::env;
+use std::thread;
+use std::time::Duration;
+
+/// Delay sleeping the current thread
+struct ThreadDelay;
+
+impl DelayNs for ThreadDelay {
+    fn delay_ns(&mut self, ns: u32) {
+        thread::sleep(Duration::from_nanos(ns.into()));
+    }
+}
+
+#[cfg(feature = "no-float")]
+fn main() {
+    eprintln!("This example reads float values, build it without the `no-float` feature");
+}
+
+// usage: linux [serial device, /dev/ttyUSB0 by default]
+#[cfg(not(feature = "no-float"))]
+fn main() {
+    let path = env::args().nth(1).unwrap_or_else(|| "/dev/ttyUSB0".into());
+    let mut sps30 = Sps30::open_linux(&path).expect("cannot open serial port");
+
+    let serial_number = sps30.device_info(DeviceInfo::SerialNumber).unwrap();
+    println!("Serial Number = {}", serial_number);
+
+    let mut sps30 = sps30
+        .init_measuring(&mut ThreadDelay, MeasurementFormat::Float)
+        .map_err(|(e, _)| e)
+        .unwrap();
+    for res in sps30.measurements(ThreadDelay, 1000).take(10) {
+        let res = res.unwrap();
+        println!("Mass Concentration PM2.5 [μg/m³] {}", res.mass_pm2_5);
+    }
+
+    sps30.stop_measurement().map_err(|(e, _)| e).unwrap();
+}
@@ -1,67 +1,70 @@
-use rppal::uart::*;
-use sps30::{DeviceInfo, Sps30};
+// Reads float values, only the fallback `main` is built with the `no-float` feature
+#![cfg_attr(feature = "no-float", allow(dead_code, unused_imports))]
+
+use embedded_hal::delay::DelayNs;
+use rppal::uart::Result;
+use sps30::{DeviceInfo, MeasurementFormat, Sps30};
 use std::thread;
 use std::time::Duration;
 
+/// Delay sleeping the current thread
+struct ThreadDelay;
+
+impl DelayNs for ThreadDelay {
+    fn delay_ns(&mut self, ns: u32) {
+        thread::sleep(Duration::from_nanos(ns.into()));
+    }
+}
+
+#[cfg(feature = "no-float")]
+fn main() {
+    eprintln!("This example reads float values, build it without the `no-float` feature");
+}
+
 // activate uart in raspi-config
+#[cfg(not(feature = "no-float"))]
 fn main() {
     if let Err(e) = run() {
         eprintln!("Program exited early with error: {}", e);
     }
 }
 
+#[cfg(not(feature = "no-float"))]
 fn run() -> Result<()> {
-    // Configure UART
-    let mut serial = Uart::new(115_200, Parity::None, 8, 1)?;
-    serial.set_hardware_flow_control(false).unwrap();
-    serial.set_software_flow_control(false).unwrap();
-    serial.set_rts(false).unwrap();
-    serial.set_write_mode(true).unwrap();
-    serial.set_read_mode(1, Duration::new(0, 0)).unwrap();
-
-    let mut sps30 = Sps30::new(serial);
-    sps30.reset().unwrap();
-    thread::sleep(Duration::from_millis(10000));
-    sps30.start_measurement().unwrap();
-
-    for _ in 0..10 {
-        thread::sleep(Duration::from_millis(10000));
+    let sps30 = Sps30::open_rppal()?;
+    let mut sps30 = sps30
+        .init_measuring(&mut ThreadDelay, MeasurementFormat::Float)
+        .map_err(|(e, _)| e)
+        .unwrap();
 
-        let res = sps30.read_measurement().unwrap();
-        println!("Mass Concentration PM1.0 [μg/m³] {:?}", res[0]);
-        println!("Mass Concentration PM2.5 [μg/m³] {:?} ", res[1]);
-        println!("Mass Concentration PM4.0 [μg/m³] {}", res[2]);
-        println!("Mass Concentration PM10 [μg/m³] {}", res[3]);
-        println!("Number Concentration PM0.5 [#/cm³] {}", res[4]);
-        println!("Number Concentration PM1.0 [#/cm³] {}", res[5]);
-        println!("Number Concentration PM2.5 [#/cm³] {}", res[6]);
-        println!("Number Concentration PM4.0 [#/cm³] {}", res[7]);
-        println!("Number Concentration PM10 [#/cm³] {}", res[8]);
-        println!("Typical Particle Size [μm] {}", res[9]);
+    for res in sps30.measurements(ThreadDelay, 10000).take(10) {
+        let res = res.unwrap();
+        println!("Mass Concentration PM1.0 [μg/m³] {}", res.mass_pm1_0);
+        println!("Mass Concentration PM2.5 [μg/m³] {}", res.mass_pm2_5);
+        println!("Mass Concentration PM4.0 [μg/m³] {}", res.mass_pm4_0);
+        println!("Mass Concentration PM10 [μg/m³] {}", res.mass_pm10);
+        println!("Number Concentration PM0.5 [#/cm³] {}", res.nc_pm0_5);
+        println!("Number Concentration PM1.0 [#/cm³] {}", res.nc_pm1_0);
+        println!("Number Concentration PM2.5 [#/cm³] {}", res.nc_pm2_5);
+        println!("Number Concentration PM4.0 [#/cm³] {}", res.nc_pm4_0);
+        println!("Number Concentration PM10 [#/cm³] {}", res.nc_pm10);
+        println!("Typical Particle Size [μm] {}", res.typical_particle_size);
     }
 
     println!(
         "Clean interval= {}",
-        sps30.read_cleaning_interval().unwrap()
+        sps30.read_cleaning_interval().unwrap().as_secs()
     );
 
-    sps30.stop_measurement().unwrap();
+    let mut sps30 = sps30.stop_measurement().map_err(|(e, _)| e).unwrap();
 
-    let product_name =
-        String::from_utf8(sps30.device_info(DeviceInfo::ProductName).unwrap().to_vec()).unwrap();
+    let product_name = sps30.device_info(DeviceInfo::ProductName).unwrap();
     println!("Product Name = {}", product_name);
 
-    let serial_number = String::from_utf8(
-        sps30
-            .device_info(DeviceInfo::SerialNumber)
-            .unwrap()
-            .to_vec(),
-    )
-    .unwrap();
+    let serial_number = sps30.device_info(DeviceInfo::SerialNumber).unwrap();
     println!("Serial Number = {}", serial_number);
 
-    let article_code =
-        String::from_utf8(sps30.device_info(DeviceInfo::ArticleCode).unwrap().to_vec()).unwrap();
+    let article_code = sps30.device_info(DeviceInfo::ArticleCode).unwrap();
     println!("Article Code = {}", article_code);
 
     Ok(())
@@ -0,0 +1,17 @@
+//! Arbitrary line noise through the push style frame parser
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sps30::protocol::FrameParser;
+use sps30::CommandType;
+
+fuzz_target!(|input: &[u8]| {
+    let mut parser = FrameParser::new();
+    for &byte in input {
+        if let Some(frame) = parser.feed_byte(byte) {
+            assert!(frame.as_bytes().len() <= sps30::protocol::FRAME_SIZE);
+            let _ = frame.check::<(), ()>(CommandType::ReadMeasuredData);
+        }
+    }
+});
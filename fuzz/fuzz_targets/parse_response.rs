@@ -0,0 +1,56 @@
+//! MISO Frame as read from the wire through SHDLC decode, checksum and response checks,
+//! then every data parser
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sps30::protocol::{self, Validation};
+use sps30::{CommandType, SpecialChars};
+
+/// Commands a response is checked against, picked by the first input byte
+const COMMANDS: [CommandType; 11] = [
+    CommandType::StartMeasurement,
+    CommandType::StopMeasurement,
+    CommandType::ReadMeasuredData,
+    CommandType::ReadWriteAutoCleaningInterval,
+    CommandType::StartFanCleaning,
+    CommandType::DeviceInformation,
+    CommandType::Reset,
+    CommandType::Sleep,
+    CommandType::WakeUp,
+    CommandType::ReadVersion,
+    CommandType::ReadDeviceStatusRegister,
+];
+
+fuzz_target!(|input: &[u8]| {
+    let (&selector, frame) = match input.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+    let cmd_type = COMMANDS[usize::from(selector & 0x7F) % COMMANDS.len()];
+    let validation = if selector & 0x80 == 0 {
+        Validation::Strict
+    } else {
+        Validation::Lenient
+    };
+    let s_chars = SpecialChars::default();
+
+    let mut buf = [0; protocol::FRAME_SIZE];
+    let _ = protocol::parse_response_into::<(), ()>(frame, cmd_type, s_chars, validation, &mut buf);
+
+    let response =
+        match protocol::parse_response_with::<(), ()>(frame, cmd_type, s_chars, validation) {
+            Ok(response) => response,
+            Err(_) => return,
+        };
+    let data = response.data();
+    let _ = protocol::parse_measurement::<(), ()>(data);
+    let _ = protocol::parse_measurement_raw::<(), ()>(data);
+    let _ = protocol::parse_measurement_bits::<(), ()>(data);
+    let _ = protocol::parse_measurement_u16::<(), ()>(data);
+    let _ = protocol::parse_cleaning_interval::<(), ()>(data);
+    let _ = protocol::parse_device_info::<(), ()>(data);
+    let _ = protocol::parse_device_info_string::<(), ()>(data);
+    let _ = protocol::parse_versions::<(), ()>(data);
+    let _ = protocol::parse_device_status::<(), ()>(data);
+});
@@ -0,0 +1,38 @@
+//! Driver reading measured values from a serial port replaying arbitrary bytes
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sps30::{MeasurementFormat, Sps30, Transport};
+
+/// Transport replaying the fuzz input as MISO bytes, MOSI Frames are dropped
+struct Replay<'a> {
+    rx: &'a [u8],
+}
+
+impl Transport for Replay<'_> {
+    type WriteError = ();
+    type ReadError = ();
+
+    fn write_all(&mut self, _data: &[u8]) -> Result<(), ()> {
+        Ok(())
+    }
+
+    fn read_byte(&mut self) -> nb::Result<u8, ()> {
+        let (&byte, rest) = self.rx.split_first().ok_or(nb::Error::Other(()))?;
+        self.rx = rest;
+        Ok(byte)
+    }
+}
+
+fuzz_target!(|input: &[u8]| {
+    let mut sensor =
+        match Sps30::new(Replay { rx: input }).start_measurement(MeasurementFormat::Float) {
+            Ok(sensor) => sensor,
+            Err(_) => return,
+        };
+    // Every read consumes at least one byte, stop once the input is exhausted
+    while !sensor.serial_mut().rx.is_empty() {
+        let _ = sensor.read_measurement();
+    }
+});
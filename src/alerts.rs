@@ -0,0 +1,305 @@
+//! Threshold alerts on measured values
+//!
+//! [`AlertsU16`] checks values measured in integer format, without float math.
+
+use crate::{Measurement, MeasurementU16};
+use heapless::Vec;
+
+/// Condition raising an alert while a channel is above a level
+///
+/// The alert is raised once the value stayed above `level` for `debounce` consecutive
+/// checks, and cleared once it stayed below `level - hysteresis` as long.
+#[derive(Debug, Clone, Copy)]
+pub struct Threshold {
+    /// Value checked, e.g. `|m| m.mass_pm2_5`
+    channel: fn(&Measurement) -> f32,
+    /// Value above which the alert is raised
+    level: f32,
+    /// Margin below `level` for the alert to clear
+    hysteresis: f32,
+    /// Consecutive checks needed to raise or clear the alert
+    debounce: u16,
+}
+
+impl Threshold {
+    /// Alert while `channel` is above `level`, without hysteresis nor debouncing
+    pub fn above(channel: fn(&Measurement) -> f32, level: f32) -> Self {
+        Threshold {
+            channel,
+            level,
+            hysteresis: 0.0,
+            debounce: 1,
+        }
+    }
+
+    /// Clear the alert only once the value is below `level - hysteresis`
+    pub fn hysteresis(mut self, hysteresis: f32) -> Self {
+        self.hysteresis = hysteresis;
+        self
+    }
+
+    /// Raise or clear the alert only after `checks` consecutive checks, at least 1
+    pub fn debounce(mut self, checks: u16) -> Self {
+        self.debounce = checks.max(1);
+        self
+    }
+}
+
+/// Same as [`Threshold`], on values measured in integer format
+#[derive(Debug, Clone, Copy)]
+pub struct ThresholdU16 {
+    /// Value checked, e.g. `|m| m.mass_pm2_5`
+    channel: fn(&MeasurementU16) -> u16,
+    /// Value above which the alert is raised
+    level: u16,
+    /// Margin below `level` for the alert to clear
+    hysteresis: u16,
+    /// Consecutive checks needed to raise or clear the alert
+    debounce: u16,
+}
+
+impl ThresholdU16 {
+    /// Alert while `channel` is above `level`, without hysteresis nor debouncing
+    pub fn above(channel: fn(&MeasurementU16) -> u16, level: u16) -> Self {
+        ThresholdU16 {
+            channel,
+            level,
+            hysteresis: 0,
+            debounce: 1,
+        }
+    }
+
+    /// Clear the alert only once the value is below `level - hysteresis`
+    pub fn hysteresis(mut self, hysteresis: u16) -> Self {
+        self.hysteresis = hysteresis;
+        self
+    }
+
+    /// Raise or clear the alert only after `checks` consecutive checks, at least 1
+    pub fn debounce(mut self, checks: u16) -> Self {
+        self.debounce = checks.max(1);
+        self
+    }
+}
+
+/// Set of alerts, bit `i` is the alert of the `i`-th threshold added
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AlertFlags(pub u32);
+
+impl AlertFlags {
+    /// Alert of threshold `index` is raised
+    pub fn contains(self, index: usize) -> bool {
+        index < 32 && self.0 & (1 << index) != 0
+    }
+
+    /// No alert raised
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+/// State of a threshold
+#[derive(Debug, Clone, Copy)]
+struct Alert<T> {
+    /// Condition of the alert
+    threshold: T,
+    /// Alert raised
+    active: bool,
+    /// Consecutive checks asking to toggle `active`
+    pending: u16,
+}
+
+impl<T> Alert<T> {
+    /// Count a check asking to toggle the alert if `toggle`, toggling it after `debounce`
+    /// consecutive ones
+    fn update(&mut self, toggle: bool, debounce: u16) {
+        if toggle {
+            self.pending += 1;
+            if self.pending >= debounce {
+                self.active = !self.active;
+                self.pending = 0;
+            }
+        } else {
+            self.pending = 0;
+        }
+    }
+}
+
+/// Add `threshold` to `alerts`, returning its bit in [`AlertFlags`]
+fn add<T, const N: usize>(alerts: &mut Vec<Alert<T>, N>, threshold: T) -> Result<usize, T> {
+    let index = alerts.len();
+    if index >= 32 {
+        return Err(threshold);
+    }
+    alerts
+        .push(Alert {
+            threshold,
+            active: false,
+            pending: 0,
+        })
+        .map_err(|alert| alert.threshold)?;
+    Ok(index)
+}
+
+/// Alerts raised among `alerts`
+fn active<T>(alerts: &[Alert<T>]) -> AlertFlags {
+    let bits = alerts
+        .iter()
+        .enumerate()
+        .filter(|(_, alert)| alert.active)
+        .fold(0, |bits, (index, _)| bits | 1 << index);
+    AlertFlags(bits)
+}
+
+/// Clear `alerts`, keeping their thresholds
+fn reset<T>(alerts: &mut [Alert<T>]) {
+    for alert in alerts.iter_mut() {
+        alert.active = false;
+        alert.pending = 0;
+    }
+}
+
+/// Alerts over up to `N` thresholds, at most 32
+///
+/// Call [`Alerts::check`] with every new measurement, e.g. to drive a fan or a purifier.
+#[derive(Debug, Default)]
+pub struct Alerts<const N: usize> {
+    /// Thresholds, in the order they were added
+    alerts: Vec<Alert<Threshold>, N>,
+}
+
+impl<const N: usize> Alerts<N> {
+    /// No threshold yet
+    pub fn new() -> Self {
+        Alerts { alerts: Vec::new() }
+    }
+
+    /// Add a threshold, returning its bit in [`AlertFlags`]
+    ///
+    /// The threshold is given back if `N` or 32 thresholds were already added.
+    pub fn add(&mut self, threshold: Threshold) -> Result<usize, Threshold> {
+        add(&mut self.alerts, threshold)
+    }
+
+    /// Update the alerts with `measurement`, returning the ones raised
+    pub fn check(&mut self, measurement: &Measurement) -> AlertFlags {
+        for alert in self.alerts.iter_mut() {
+            let threshold = &alert.threshold;
+            let value = (threshold.channel)(measurement);
+            let toggle = if alert.active {
+                value < threshold.level - threshold.hysteresis
+            } else {
+                value > threshold.level
+            };
+            let debounce = threshold.debounce;
+            alert.update(toggle, debounce);
+        }
+        self.active()
+    }
+
+    /// Alerts raised by the last check
+    pub fn active(&self) -> AlertFlags {
+        active(&self.alerts)
+    }
+
+    /// Clear all alerts, keeping the thresholds
+    pub fn reset(&mut self) {
+        reset(&mut self.alerts)
+    }
+}
+
+/// Same as [`Alerts`], over [`ThresholdU16`] thresholds
+#[derive(Debug, Default)]
+pub struct AlertsU16<const N: usize> {
+    /// Thresholds, in the order they were added
+    alerts: Vec<Alert<ThresholdU16>, N>,
+}
+
+impl<const N: usize> AlertsU16<N> {
+    /// No threshold yet
+    pub fn new() -> Self {
+        AlertsU16 { alerts: Vec::new() }
+    }
+
+    /// Add a threshold, returning its bit in [`AlertFlags`]
+    ///
+    /// The threshold is given back if `N` or 32 thresholds were already added.
+    pub fn add(&mut self, threshold: ThresholdU16) -> Result<usize, ThresholdU16> {
+        add(&mut self.alerts, threshold)
+    }
+
+    /// Update the alerts with `measurement`, returning the ones raised
+    pub fn check(&mut self, measurement: &MeasurementU16) -> AlertFlags {
+        for alert in self.alerts.iter_mut() {
+            let threshold = &alert.threshold;
+            let value = (threshold.channel)(measurement);
+            let toggle = if alert.active {
+                value < threshold.level.saturating_sub(threshold.hysteresis)
+            } else {
+                value > threshold.level
+            };
+            let debounce = threshold.debounce;
+            alert.update(toggle, debounce);
+        }
+        self.active()
+    }
+
+    /// Alerts raised by the last check
+    pub fn active(&self) -> AlertFlags {
+        active(&self.alerts)
+    }
+
+    /// Clear all alerts, keeping the thresholds
+    pub fn reset(&mut self) {
+        reset(&mut self.alerts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pm2_5(value: f32) -> Measurement {
+        Measurement {
+            mass_pm2_5: value,
+            ..Measurement::default()
+        }
+    }
+
+    #[test]
+    fn hysteresis_and_debounce() {
+        let mut alerts = Alerts::<2>::new();
+        let threshold = Threshold::above(|m| m.mass_pm2_5, 35.0)
+            .hysteresis(5.0)
+            .debounce(2);
+        assert_eq!(alerts.add(threshold).ok(), Some(0));
+
+        assert!(alerts.check(&pm2_5(40.0)).is_empty());
+        assert!(alerts.check(&pm2_5(40.0)).contains(0));
+        // Below the level, not below the hysteresis
+        assert!(alerts.check(&pm2_5(32.0)).contains(0));
+        assert!(alerts.check(&pm2_5(32.0)).contains(0));
+        assert!(alerts.check(&pm2_5(20.0)).contains(0));
+        assert!(alerts.check(&pm2_5(20.0)).is_empty());
+    }
+
+    #[test]
+    fn integer_thresholds() {
+        let pm10 = |value| MeasurementU16 {
+            mass_pm10: value,
+            ..MeasurementU16::default()
+        };
+        let mut alerts = AlertsU16::<1>::new();
+        let threshold = ThresholdU16::above(|m| m.mass_pm10, 50).hysteresis(60);
+        assert_eq!(alerts.add(threshold).ok(), Some(0));
+        assert!(alerts.add(threshold).is_err());
+
+        assert!(alerts.check(&pm10(51)).contains(0));
+        // Hysteresis larger than the level, the alert never clears
+        assert!(alerts.check(&pm10(0)).contains(0));
+        alerts.reset();
+        assert!(alerts.active().is_empty());
+    }
+}
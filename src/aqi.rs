@@ -0,0 +1,416 @@
+//! Air Quality Index computed from mass concentrations
+//!
+//! Indexes are defined over averaged concentrations (24 hours for the US EPA AQI, 1 hour for
+//! the European CAQI), computing them from single measurements only gives an indication.
+//!
+//! The `_u16` functions take concentrations in whole μg/m³, as measured in integer format,
+//! and only use integer math.
+
+use crate::{Measurement, MeasurementU16};
+
+/// Pollutant an index is computed from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Pollutant {
+    /// Mass Concentration PM2.5
+    Pm2_5,
+    /// Mass Concentration PM10
+    Pm10,
+}
+
+/// Index standard
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AqiStandard {
+    /// US EPA Air Quality Index, 0 to 500
+    #[default]
+    UsEpa,
+    /// European Common Air Quality Index (hourly grid), 0 to 100 and above
+    Caqi,
+}
+
+/// Air Quality Index
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Aqi {
+    /// Index value, the highest of the pollutants' sub-indexes
+    pub value: u16,
+    /// Pollutant with the highest sub-index, PM2.5 if both are equal
+    pub dominant: Pollutant,
+}
+
+/// US EPA AQI category
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AqiCategory {
+    /// 0 to 50
+    Good,
+    /// 51 to 100
+    Moderate,
+    /// 101 to 150
+    UnhealthySensitive,
+    /// 151 to 200
+    Unhealthy,
+    /// 201 to 300
+    VeryUnhealthy,
+    /// 301 and above
+    Hazardous,
+}
+
+impl AqiCategory {
+    /// Category of a US EPA AQI value
+    pub fn from_us_aqi(value: u16) -> Self {
+        match value {
+            0..=50 => AqiCategory::Good,
+            51..=100 => AqiCategory::Moderate,
+            101..=150 => AqiCategory::UnhealthySensitive,
+            151..=200 => AqiCategory::Unhealthy,
+            201..=300 => AqiCategory::VeryUnhealthy,
+            _ => AqiCategory::Hazardous,
+        }
+    }
+
+    /// Color of the category as published by the EPA, as (red, green, blue)
+    pub fn rgb(self) -> (u8, u8, u8) {
+        match self {
+            AqiCategory::Good => (0, 228, 0),
+            AqiCategory::Moderate => (255, 255, 0),
+            AqiCategory::UnhealthySensitive => (255, 126, 0),
+            AqiCategory::Unhealthy => (255, 0, 0),
+            AqiCategory::VeryUnhealthy => (143, 63, 151),
+            AqiCategory::Hazardous => (126, 0, 35),
+        }
+    }
+}
+
+impl core::fmt::Display for AqiCategory {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let name = match self {
+            AqiCategory::Good => "Good",
+            AqiCategory::Moderate => "Moderate",
+            AqiCategory::UnhealthySensitive => "Unhealthy for Sensitive Groups",
+            AqiCategory::Unhealthy => "Unhealthy",
+            AqiCategory::VeryUnhealthy => "Very Unhealthy",
+            AqiCategory::Hazardous => "Hazardous",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Breakpoint of an index table: concentrations `c_low..=c_high` map to `i_low..=i_high`
+struct Breakpoint {
+    c_low: u32,
+    c_high: u32,
+    i_low: u16,
+    i_high: u16,
+}
+
+const fn bp(c_low: u32, c_high: u32, i_low: u16, i_high: u16) -> Breakpoint {
+    Breakpoint {
+        c_low,
+        c_high,
+        i_low,
+        i_high,
+    }
+}
+
+/// US EPA PM2.5 breakpoints (2024 revision), concentrations in 0.1 μg/m³
+const US_PM2_5: [Breakpoint; 6] = [
+    bp(0, 90, 0, 50),
+    bp(91, 354, 51, 100),
+    bp(355, 554, 101, 150),
+    bp(555, 1254, 151, 200),
+    bp(1255, 2254, 201, 300),
+    bp(2255, 3254, 301, 500),
+];
+
+/// US EPA PM10 breakpoints, concentrations in μg/m³
+const US_PM10: [Breakpoint; 6] = [
+    bp(0, 54, 0, 50),
+    bp(55, 154, 51, 100),
+    bp(155, 254, 101, 150),
+    bp(255, 354, 151, 200),
+    bp(355, 424, 201, 300),
+    bp(425, 604, 301, 500),
+];
+
+/// CAQI PM2.5 hourly grid, concentrations in μg/m³
+const CAQI_PM2_5: [Breakpoint; 4] = [
+    bp(0, 15, 0, 25),
+    bp(15, 30, 25, 50),
+    bp(30, 55, 50, 75),
+    bp(55, 110, 75, 100),
+];
+
+/// CAQI PM10 hourly grid, concentrations in μg/m³
+const CAQI_PM10: [Breakpoint; 4] = [
+    bp(0, 25, 0, 25),
+    bp(25, 50, 25, 50),
+    bp(50, 90, 50, 75),
+    bp(90, 180, 75, 100),
+];
+
+/// Linear interpolation of the truncated concentration `c` in `table`
+///
+/// Concentrations above the table are reported as its highest index.
+fn interpolate(table: &[Breakpoint], c: u32) -> u16 {
+    let last = &table[table.len() - 1];
+    let bp = table.iter().find(|bp| c <= bp.c_high).unwrap_or(last);
+    scale(bp, c.min(bp.c_high))
+}
+
+/// Index of the concentration `c`, at least `bp.c_low`, on the segment of `bp`
+///
+/// Rounded to the nearest integer, saturating at u16::MAX.
+fn scale(bp: &Breakpoint, c: u32) -> u16 {
+    let span = u64::from(bp.c_high - bp.c_low);
+    let offset = u64::from(bp.i_high - bp.i_low) * u64::from(c - bp.c_low);
+    let index = u64::from(bp.i_low) + (2 * offset + span) / (2 * span);
+    index.min(u64::from(u16::MAX)) as u16
+}
+
+/// US EPA AQI sub-index of a PM2.5 concentration in μg/m³
+///
+/// The concentration is truncated to 0.1 μg/m³, negative values count as 0.
+pub fn us_aqi_pm2_5(concentration: f32) -> u16 {
+    interpolate(&US_PM2_5, (concentration * 10.0) as u32)
+}
+
+/// US EPA AQI sub-index of a PM10 concentration in μg/m³
+///
+/// The concentration is truncated to 1 μg/m³, negative values count as 0.
+pub fn us_aqi_pm10(concentration: f32) -> u16 {
+    interpolate(&US_PM10, concentration as u32)
+}
+
+/// US EPA AQI of PM2.5 and PM10 concentrations in μg/m³
+pub fn us_aqi(pm2_5: f32, pm10: f32) -> Aqi {
+    highest(us_aqi_pm2_5(pm2_5), us_aqi_pm10(pm10))
+}
+
+/// US EPA AQI of PM2.5 and PM10 concentrations in whole μg/m³
+pub fn us_aqi_u16(pm2_5: u16, pm10: u16) -> Aqi {
+    highest(
+        interpolate(&US_PM2_5, u32::from(pm2_5) * 10),
+        interpolate(&US_PM10, u32::from(pm10)),
+    )
+}
+
+/// Linear interpolation of the concentration `c` in the continuous `table`
+///
+/// Concentrations above the table extend its last segment, negative ones count as 0.
+fn extrapolate(table: &[Breakpoint], c: f32) -> u16 {
+    let last = &table[table.len() - 1];
+    let c = c.max(0.0);
+    let bp = table
+        .iter()
+        .find(|bp| c <= bp.c_high as f32)
+        .unwrap_or(last);
+    let ratio = f32::from(bp.i_high - bp.i_low) / (bp.c_high - bp.c_low) as f32;
+    // Rounded to the nearest integer, saturating at u16::MAX
+    (f32::from(bp.i_low) + ratio * (c - bp.c_low as f32) + 0.5) as u16
+}
+
+/// European CAQI sub-index of an hourly PM2.5 concentration in μg/m³
+pub fn caqi_pm2_5(concentration: f32) -> u16 {
+    extrapolate(&CAQI_PM2_5, concentration)
+}
+
+/// European CAQI sub-index of an hourly PM10 concentration in μg/m³
+pub fn caqi_pm10(concentration: f32) -> u16 {
+    extrapolate(&CAQI_PM10, concentration)
+}
+
+/// European CAQI of PM2.5 and PM10 concentrations in μg/m³
+pub fn caqi(pm2_5: f32, pm10: f32) -> Aqi {
+    highest(caqi_pm2_5(pm2_5), caqi_pm10(pm10))
+}
+
+/// European CAQI of PM2.5 and PM10 concentrations in whole μg/m³
+pub fn caqi_u16(pm2_5: u16, pm10: u16) -> Aqi {
+    let extrapolate = |table: &[Breakpoint], c: u16| {
+        let c = u32::from(c);
+        let last = &table[table.len() - 1];
+        scale(table.iter().find(|bp| c <= bp.c_high).unwrap_or(last), c)
+    };
+    highest(
+        extrapolate(&CAQI_PM2_5, pm2_5),
+        extrapolate(&CAQI_PM10, pm10),
+    )
+}
+
+/// Index of PM2.5 and PM10 concentrations in μg/m³, as defined by `standard`
+pub fn aqi(standard: AqiStandard, pm2_5: f32, pm10: f32) -> Aqi {
+    match standard {
+        AqiStandard::UsEpa => us_aqi(pm2_5, pm10),
+        AqiStandard::Caqi => caqi(pm2_5, pm10),
+    }
+}
+
+/// Index of PM2.5 and PM10 concentrations in whole μg/m³, as defined by `standard`
+pub fn aqi_u16(standard: AqiStandard, pm2_5: u16, pm10: u16) -> Aqi {
+    match standard {
+        AqiStandard::UsEpa => us_aqi_u16(pm2_5, pm10),
+        AqiStandard::Caqi => caqi_u16(pm2_5, pm10),
+    }
+}
+
+/// Number of hourly means NowCast is computed over
+pub const NOWCAST_HOURS: usize = 12;
+
+/// EPA NowCast of a PM concentration, from hourly means in μg/m³, most recent first
+///
+/// Only the first [`NOWCAST_HOURS`] means are used, `None` marks a missing hour. Recent
+/// hours weigh more the more the concentration varies, so the result follows rapid changes
+/// while being comparable to 24 hours averages, e.g. by passing it to [`us_aqi_pm2_5`].
+/// Returns `None` if less than 2 of the 3 most recent hours are available.
+pub fn nowcast(hourly_means: impl IntoIterator<Item = Option<f32>>) -> Option<f32> {
+    let mut hours = [None; NOWCAST_HOURS];
+    for (hour, mean) in hours.iter_mut().zip(hourly_means) {
+        *hour = mean;
+    }
+    if hours[..3].iter().filter(|mean| mean.is_some()).count() < 2 {
+        return None;
+    }
+
+    let (min, max) = hours
+        .iter()
+        .flatten()
+        .fold((f32::MAX, f32::MIN), |(min, max), &c| {
+            (min.min(c), max.max(c))
+        });
+    let weight = if max > 0.0 { (min / max).max(0.5) } else { 1.0 };
+
+    let mut factor = 1.0;
+    let mut sum = 0.0;
+    let mut weights = 0.0;
+    for hour in hours.iter() {
+        if let Some(c) = hour {
+            sum += factor * c;
+            weights += factor;
+        }
+        factor *= weight;
+    }
+    Some(sum / weights)
+}
+
+/// Index from the PM2.5 and PM10 sub-indexes
+fn highest(pm2_5: u16, pm10: u16) -> Aqi {
+    if pm10 > pm2_5 {
+        Aqi {
+            value: pm10,
+            dominant: Pollutant::Pm10,
+        }
+    } else {
+        Aqi {
+            value: pm2_5,
+            dominant: Pollutant::Pm2_5,
+        }
+    }
+}
+
+impl Measurement {
+    /// US EPA AQI of the PM2.5 and PM10 mass concentrations, see [`us_aqi`]
+    pub fn us_aqi(&self) -> Aqi {
+        us_aqi(self.mass_pm2_5, self.mass_pm10)
+    }
+
+    /// Index of the PM2.5 and PM10 mass concentrations, as defined by `standard`
+    pub fn aqi(&self, standard: AqiStandard) -> Aqi {
+        aqi(standard, self.mass_pm2_5, self.mass_pm10)
+    }
+}
+
+impl MeasurementU16 {
+    /// US EPA AQI of the PM2.5 and PM10 mass concentrations, see [`us_aqi_u16`]
+    pub fn us_aqi(&self) -> Aqi {
+        us_aqi_u16(self.mass_pm2_5, self.mass_pm10)
+    }
+
+    /// Index of the PM2.5 and PM10 mass concentrations, as defined by `standard`
+    pub fn aqi(&self, standard: AqiStandard) -> Aqi {
+        aqi_u16(standard, self.mass_pm2_5, self.mass_pm10)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn us_breakpoints() {
+        assert_eq!(us_aqi_pm2_5(0.0), 0);
+        assert_eq!(us_aqi_pm2_5(9.0), 50);
+        assert_eq!(us_aqi_pm2_5(9.09), 50);
+        assert_eq!(us_aqi_pm2_5(35.4), 100);
+        assert_eq!(us_aqi_pm2_5(-1.0), 0);
+        assert_eq!(us_aqi_pm2_5(1000.0), 500);
+        assert_eq!(us_aqi_pm10(54.9), 50);
+        assert_eq!(us_aqi_pm10(100.0), 73);
+    }
+
+    #[test]
+    fn us_dominant_pollutant() {
+        assert_eq!(
+            us_aqi(12.0, 200.0),
+            Aqi {
+                value: 123,
+                dominant: Pollutant::Pm10
+            }
+        );
+        assert_eq!(us_aqi(40.0, 20.0).dominant, Pollutant::Pm2_5);
+    }
+
+    #[test]
+    fn nowcast_weights_recent_hours() {
+        // Steady concentration
+        assert_eq!(nowcast([Some(10.0); 12].iter().copied()), Some(10.0));
+        // Weight of 0.5, the 2 hours weigh 1 and 0.5
+        assert_eq!(
+            nowcast([Some(40.0), Some(10.0)].iter().copied()),
+            Some(30.0)
+        );
+        assert_eq!(nowcast([Some(40.0), None, None].iter().copied()), None);
+    }
+
+    #[test]
+    fn us_categories() {
+        assert_eq!(AqiCategory::from_us_aqi(50), AqiCategory::Good);
+        assert_eq!(
+            AqiCategory::from_us_aqi(101),
+            AqiCategory::UnhealthySensitive
+        );
+        assert_eq!(AqiCategory::from_us_aqi(500), AqiCategory::Hazardous);
+        assert_eq!(AqiCategory::Unhealthy.rgb(), (255, 0, 0));
+    }
+
+    #[test]
+    fn caqi_grid() {
+        assert_eq!(caqi_pm2_5(15.0), 25);
+        assert_eq!(caqi_pm2_5(42.5), 63);
+        assert_eq!(caqi_pm10(180.0), 100);
+        assert_eq!(caqi_pm10(270.0), 125);
+        assert_eq!(aqi(AqiStandard::Caqi, 10.0, 70.0).dominant, Pollutant::Pm10);
+    }
+
+    #[test]
+    fn integer_indexes_match_float_ones() {
+        for pm2_5 in 0..400 {
+            for &pm10 in &[0, 54, 100, 200, 270, 700] {
+                let (c2_5, c10) = (f32::from(pm2_5), f32::from(pm10));
+                assert_eq!(us_aqi_u16(pm2_5, pm10), us_aqi(c2_5, c10));
+                assert_eq!(caqi_u16(pm2_5, pm10), caqi(c2_5, c10));
+            }
+        }
+        let measurement = MeasurementU16 {
+            mass_pm2_5: 12,
+            mass_pm10: 200,
+            ..MeasurementU16::default()
+        };
+        assert_eq!(measurement.us_aqi().value, 123);
+    }
+}
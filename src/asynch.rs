@@ -0,0 +1,415 @@
+//! Async UART driver
+//!
+//! Enabled with the `async` feature, built on `embedded-io-async` so it can run inside
+//! async executors (e.g. embassy) without blocking while waiting for MISO Frames.
+
+use crate::commands::Command;
+#[cfg(not(feature = "no-float"))]
+use crate::iter::{EMPTY_POLL_MS, MAX_EMPTY_WAIT_MS, MIN_INTERVAL_MS};
+use crate::protocol::{self, FrameSync, ResponseFrame, Validation};
+#[cfg(feature = "cleaning")]
+use crate::AutoCleanInterval;
+#[cfg(not(feature = "no-float"))]
+use crate::Measurement;
+use crate::{
+    CommandType, DeviceStatus, Error, Health, MeasurementFormat, MeasurementU16, NoDelay,
+    SpecialChars, Versions, MAX_BUFFER, WAKE_UP_TIME_MS,
+};
+#[cfg(feature = "device-info")]
+use crate::{DeviceIdentity, DeviceInfo, InfoString};
+use arrayvec::ArrayVec;
+use embedded_hal_async::delay::DelayNs;
+use embedded_io_async::{Read, ReadExactError, Write};
+#[cfg(not(feature = "no-float"))]
+use futures_util::stream::{self, Stream};
+
+/// Async Sps30 driver
+///
+/// `DELAY` waits for the device to wake up, see [`Sps30Async::with_auto_wake`].
+#[derive(Debug, Default)]
+pub struct Sps30Async<SERIAL, DELAY = NoDelay> {
+    /// The concrete async Serial device implementation.
+    serial: SERIAL,
+    /// Device put in sleep mode by [`Sps30Async::sleep`]
+    asleep: bool,
+    /// Delay used to wake the device up before commands sent while asleep, if enabled
+    wake_delay: Option<DELAY>,
+}
+
+impl DelayNs for NoDelay {
+    async fn delay_ns(&mut self, _ns: u32) {}
+}
+
+impl<SERIAL, E> Sps30Async<SERIAL>
+where
+    SERIAL: Read<Error = E> + Write<Error = E>,
+{
+    /// Create new instance of the Sps30 device
+    pub fn new(serial: SERIAL) -> Self {
+        Sps30Async {
+            serial,
+            asleep: false,
+            wake_delay: None,
+        }
+    }
+}
+
+impl<SERIAL, DELAY, E> Sps30Async<SERIAL, DELAY>
+where
+    SERIAL: Read<Error = E> + Write<Error = E>,
+    DELAY: DelayNs,
+{
+    /// Wake the device up before sending a command while it sleeps, disabled by default
+    ///
+    /// The driver tracks the mode set by [`Sps30Async::sleep`] and [`Sps30Async::wake_up`],
+    /// so commands sent while asleep don't fail but wake the device up first, then wait
+    /// 5ms with `delay` for it to accept commands.
+    pub fn with_auto_wake<D: DelayNs>(self, delay: D) -> Sps30Async<SERIAL, D> {
+        Sps30Async {
+            serial: self.serial,
+            asleep: self.asleep,
+            wake_delay: Some(delay),
+        }
+    }
+
+    /// Destroy the driver and return the serial port
+    pub fn release(self) -> SERIAL {
+        self.serial
+    }
+
+    /// Read a complete frame from serial, start and stop flags included
+    ///
+    /// Bytes before the start flag are discarded, back to back flags restart the frame.
+    /// No more than MAX_BUFFER=100 u8 will be accumulated, nor discarded.
+    async fn read_uart_data(
+        &mut self,
+    ) -> Result<ArrayVec<[u8; protocol::ENCODED_FRAME_SIZE]>, Error<E, E>> {
+        let mut output = ArrayVec::<[u8; protocol::ENCODED_FRAME_SIZE]>::new();
+        let mut discarded = 0;
+
+        loop {
+            let mut byte = [0; 1];
+            match self.serial.read_exact(&mut byte).await {
+                Ok(()) => match protocol::sync_byte(&mut output, byte[0], 0x7e, MAX_BUFFER) {
+                    FrameSync::Complete => return Ok(output),
+                    FrameSync::Pending => {}
+                    FrameSync::Discarded => {
+                        discarded += 1;
+                        if discarded > MAX_BUFFER {
+                            return Err(Error::InvalidFrame);
+                        }
+                    }
+                    FrameSync::Overflow => return Err(Error::InvalidFrame),
+                },
+                Err(ReadExactError::UnexpectedEof) => return Err(Error::InvalidFrame),
+                Err(ReadExactError::Other(e)) => return Err(Error::SerialR(nb::Error::Other(e))),
+            }
+        }
+    }
+
+    /// Send a command and check the MISO Frame received in response
+    async fn transceive(
+        &mut self,
+        cmd_type: CommandType,
+        data: &[u8],
+    ) -> Result<ResponseFrame, Error<E, E>> {
+        if self.asleep && self.wake_delay.is_some() && cmd_type != CommandType::WakeUp {
+            self.wake_up().await?;
+            if let Some(delay) = self.wake_delay.as_mut() {
+                delay.delay_ms(WAKE_UP_TIME_MS).await;
+            }
+        }
+        self.send_raw_command(cmd_type as u8, data).await
+    }
+
+    /// Send `command` carrying `data` and interpret the data of its response
+    async fn execute<T>(
+        &mut self,
+        command: Command<T, E, E>,
+        data: &[u8],
+    ) -> Result<T, Error<E, E>> {
+        let response = self.transceive(command.cmd, data).await?;
+        (command.parse)(response.data())
+    }
+
+    /// Send command byte `cmd` carrying `data`, e.g. a new or undocumented command
+    ///
+    /// Framing, checksum and MISO Frame checks are performed, interpreting the data of the
+    /// response is left to the caller.
+    pub async fn send_raw_command(
+        &mut self,
+        cmd: u8,
+        data: &[u8],
+    ) -> Result<ResponseFrame, Error<E, E>> {
+        let s_chars = SpecialChars::default();
+        let request = protocol::encode_raw_request(0x00, cmd, data, s_chars)?;
+        self.serial
+            .write_all(&request)
+            .await
+            .map_err(Error::SerialW)?;
+        self.serial.flush().await.map_err(Error::SerialW)?;
+
+        let mut frame = self.read_uart_data().await?;
+        protocol::parse_raw_response_in_place(&mut frame, 0x00, cmd, s_chars, Validation::Strict)
+    }
+
+    /// Start measuring
+    ///
+    /// Measured values are produced in `format`, read them with `read_measurement` for
+    /// [`MeasurementFormat::Float`] or `read_measurement_u16` for
+    /// [`MeasurementFormat::UnsignedInteger16`].
+    pub async fn start_measurement(
+        &mut self,
+        format: MeasurementFormat,
+    ) -> Result<(), Error<E, E>> {
+        self.execute(Command::START_MEASUREMENT, &[0x01, format as u8])
+            .await
+    }
+
+    /// Stop measuring
+    pub async fn stop_measurement(&mut self) -> Result<(), Error<E, E>> {
+        self.execute(Command::STOP_MEASUREMENT, &[]).await
+    }
+
+    /// Read measuring
+    #[cfg(not(feature = "no-float"))]
+    pub async fn read_measurement(&mut self) -> Result<Measurement, Error<E, E>> {
+        self.execute(Command::READ_MEASUREMENT, &[]).await
+    }
+
+    /// Stream of measured values, read every `interval_ms` using `delay`
+    ///
+    /// Same as [`Sps30::measurements`](crate::Sps30::measurements): the interval is at least
+    /// 1s and empty results are skipped, yielding [`Error::Timeout`] when no values come for
+    /// 10s. The stream never ends, pin it (e.g. with `core::pin::pin!`) to poll it with
+    /// `StreamExt::next`.
+    #[cfg(not(feature = "no-float"))]
+    pub fn measurement_stream<'a, D>(
+        &'a mut self,
+        delay: D,
+        interval_ms: u32,
+    ) -> impl Stream<Item = Result<Measurement, Error<E, E>>> + 'a
+    where
+        D: DelayNs + 'a,
+    {
+        let interval_ms = interval_ms.max(MIN_INTERVAL_MS);
+        stream::unfold(
+            (self, delay, true),
+            move |(sps30, mut delay, first)| async move {
+                if !first {
+                    delay.delay_ms(interval_ms).await;
+                }
+                let mut waited_ms = 0;
+                loop {
+                    let result = match sps30.read_measurement().await {
+                        Err(Error::EmptyResult) if waited_ms < MAX_EMPTY_WAIT_MS => {
+                            delay.delay_ms(EMPTY_POLL_MS).await;
+                            waited_ms += EMPTY_POLL_MS;
+                            continue;
+                        }
+                        Err(Error::EmptyResult) => Err(Error::Timeout),
+                        result => result,
+                    };
+                    return Some((result, (sps30, delay, false)));
+                }
+            },
+        )
+    }
+
+    /// Read measuring into `measurement`, left untouched on failure
+    ///
+    /// Same as `read_measurement`, without returning the measured values by copy
+    #[cfg(not(feature = "no-float"))]
+    pub async fn read_measurement_into(
+        &mut self,
+        measurement: &mut Measurement,
+    ) -> Result<(), Error<E, E>> {
+        let bytes = self.read_measurement_raw().await?;
+        measurement.set_from_bytes(&bytes);
+        Ok(())
+    }
+
+    /// Read measuring, returning the 40 bytes payload as sent by the device
+    ///
+    /// The response is checked but the values, big-endian IEEE754 floats, are not converted.
+    pub async fn read_measurement_raw(&mut self) -> Result<[u8; 40], Error<E, E>> {
+        self.execute(Command::READ_MEASUREMENT_RAW, &[]).await
+    }
+
+    /// Read measuring, for measurement started with [`MeasurementFormat::UnsignedInteger16`]
+    ///
+    /// Mass concentrations in μg/m³, number concentrations in #/cm³ and typical particle
+    /// size in nm, see [`MeasurementU16`]
+    pub async fn read_measurement_u16(&mut self) -> Result<MeasurementU16, Error<E, E>> {
+        self.execute(Command::READ_MEASUREMENT_U16, &[]).await
+    }
+
+    #[cfg(feature = "cleaning")]
+    /// Read cleaning interval
+    pub async fn read_cleaning_interval(&mut self) -> Result<AutoCleanInterval, Error<E, E>> {
+        self.execute(Command::READ_CLEANING_INTERVAL, &[0x00]).await
+    }
+
+    #[cfg(feature = "cleaning")]
+    /// Write cleaning interval
+    pub async fn write_cleaning_interval(
+        &mut self,
+        interval: AutoCleanInterval,
+    ) -> Result<(), Error<E, E>> {
+        let mut data = [0; 5];
+        data[1..].copy_from_slice(&interval.as_secs().to_be_bytes());
+        self.execute(Command::WRITE_CLEANING_INTERVAL, &data).await
+    }
+
+    #[cfg(feature = "cleaning")]
+    /// Disable the fan auto cleaning, writing a cleaning interval of 0
+    pub async fn disable_auto_cleaning(&mut self) -> Result<(), Error<E, E>> {
+        self.write_cleaning_interval(AutoCleanInterval::DISABLED)
+            .await
+    }
+
+    /// Read Device Status Register (firmware 2.2 or newer), clearing it if `clear`
+    pub async fn read_device_status(&mut self, clear: bool) -> Result<DeviceStatus, Error<E, E>> {
+        self.execute(Command::READ_DEVICE_STATUS, &[u8::from(clear)])
+            .await
+    }
+
+    /// Summarize the health of the device from its status register, without clearing it
+    pub async fn health_check(&mut self) -> Result<Health, Error<E, E>> {
+        self.read_device_status(false).await.map(Health::from)
+    }
+
+    #[cfg(feature = "fan-clean")]
+    /// Start fan cleaning
+    pub async fn start_fan_cleaning(&mut self) -> Result<(), Error<E, E>> {
+        self.execute(Command::START_FAN_CLEANING, &[]).await
+    }
+
+    #[cfg(feature = "device-info")]
+    /// Get info
+    ///
+    /// Return the info as an ASCII string, without the trailing NULs
+    pub async fn device_info(&mut self, info: DeviceInfo) -> Result<InfoString, Error<E, E>> {
+        self.execute(Command::DEVICE_INFORMATION, &[info as u8])
+            .await
+    }
+
+    #[cfg(feature = "device-info")]
+    /// Read Product Name, Article Code and Serial Number
+    pub async fn device_identity(&mut self) -> Result<DeviceIdentity, Error<E, E>> {
+        Ok(DeviceIdentity {
+            product_name: self.device_info(DeviceInfo::ProductName).await?,
+            article_code: self.device_info(DeviceInfo::ArticleCode).await?,
+            serial_number: self.device_info(DeviceInfo::SerialNumber).await?,
+        })
+    }
+
+    /// Read firmware, hardware and SHDLC protocol versions
+    pub async fn version(&mut self) -> Result<Versions, Error<E, E>> {
+        self.execute(Command::READ_VERSION, &[]).await
+    }
+
+    /// Enter sleep mode (firmware 2.0 or newer)
+    ///
+    /// Only allowed in idle mode
+    pub async fn sleep(&mut self) -> Result<(), Error<E, E>> {
+        self.execute(Command::SLEEP, &[]).await?;
+        self.asleep = true;
+        Ok(())
+    }
+
+    /// Wake-up from sleep mode (firmware 2.0 or newer)
+    ///
+    /// A single 0xFF byte is sent first to generate the low pulse that activates the
+    /// interface. The Wake-up command must follow within 100ms, so it is sent right after.
+    pub async fn wake_up(&mut self) -> Result<(), Error<E, E>> {
+        self.serial
+            .write_all(&[0xFF])
+            .await
+            .map_err(Error::SerialW)?;
+        self.send_raw_command(CommandType::WakeUp as u8, &[])
+            .await?;
+        self.asleep = false;
+        Ok(())
+    }
+
+    /// Reset device
+    ///
+    /// After calling this function, caller must sleep before issuing more commands
+    pub async fn reset(&mut self) -> Result<(), Error<E, E>> {
+        self.execute(Command::RESET, &[]).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::future::Future;
+    use core::pin::pin;
+    use core::task::{Context, Poll, Waker};
+    use embedded_io_async::{ErrorKind, ErrorType};
+    #[cfg(not(feature = "no-float"))]
+    use futures_util::StreamExt;
+
+    /// Transport answering every command with an empty MISO Frame, counting the responses
+    #[derive(Default)]
+    struct Acknowledge {
+        frame: [u8; 7],
+        pos: usize,
+        responses: usize,
+    }
+
+    impl ErrorType for Acknowledge {
+        type Error = ErrorKind;
+    }
+
+    impl Read for Acknowledge {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, ErrorKind> {
+            let byte = *self.frame.get(self.pos).ok_or(ErrorKind::Other)?;
+            self.pos += 1;
+            buf[0] = byte;
+            Ok(1)
+        }
+    }
+
+    impl Write for Acknowledge {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, ErrorKind> {
+            let cmd = buf[2];
+            self.frame = [0x7E, 0x00, cmd, 0x00, 0x00, 0xFF - cmd, 0x7E];
+            self.pos = 0;
+            self.responses += 1;
+            Ok(buf.len())
+        }
+    }
+
+    /// Run `future` to completion, the test transport and `NoDelay` never wait
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        let mut cx = Context::from_waker(Waker::noop());
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+        }
+    }
+
+    #[test]
+    fn start_measurement_is_acknowledged() {
+        let mut sensor = Sps30Async::new(Acknowledge::default());
+        assert!(block_on(sensor.start_measurement(MeasurementFormat::Float)).is_ok());
+        assert_eq!(sensor.release().responses, 1);
+    }
+
+    #[cfg(not(feature = "no-float"))]
+    #[test]
+    fn measurement_stream_times_out() {
+        let mut sensor = Sps30Async::new(Acknowledge::default());
+        {
+            let mut stream = pin!(sensor.measurement_stream(NoDelay, 1000));
+            let result = block_on(stream.next());
+            assert!(matches!(result, Some(Err(Error::Timeout))));
+        }
+        // A read every 100ms for 10s
+        assert_eq!(sensor.release().responses, 101);
+    }
+}
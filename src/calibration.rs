@@ -0,0 +1,69 @@
+//! Correction of measured values
+
+use crate::Measurement;
+
+/// Per channel linear correction, plus an optional humidity compensation
+///
+/// Channels are in the order of [`Measurement::to_array`]. Each value is corrected as
+/// `value * gain + offset`, then, if both are set, `humidity_compensation` is called with
+/// the corrected values and `relative_humidity`.
+#[derive(Debug, Clone, Copy)]
+pub struct Calibration {
+    /// Gain of each channel, 1.0 by default
+    pub gain: [f32; 10],
+    /// Offset of each channel, in the unit of the channel, 0.0 by default
+    pub offset: [f32; 10],
+    /// Compensation of the humidity effect, called with the relative humidity in %
+    pub humidity_compensation: Option<fn(&mut Measurement, f32)>,
+    /// Current relative humidity in %, e.g. from another sensor, updated by the caller
+    pub relative_humidity: Option<f32>,
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Calibration {
+            gain: [1.0; 10],
+            offset: [0.0; 10],
+            humidity_compensation: None,
+            relative_humidity: None,
+        }
+    }
+}
+
+impl Calibration {
+    /// Corrected values of `measurement`
+    pub fn apply(&self, measurement: &Measurement) -> Measurement {
+        let mut values = measurement.to_array();
+        for ((value, gain), offset) in values
+            .iter_mut()
+            .zip(self.gain.iter())
+            .zip(self.offset.iter())
+        {
+            *value = *value * gain + offset;
+        }
+        let mut corrected = Measurement::from(values);
+        if let (Some(compensate), Some(rh)) = (self.humidity_compensation, self.relative_humidity) {
+            compensate(&mut corrected, rh);
+        }
+        corrected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gain_offset_then_humidity() {
+        let mut calibration = Calibration::default();
+        calibration.gain[1] = 2.0;
+        calibration.offset[1] = -1.0;
+        calibration.humidity_compensation = Some(|m, rh| m.mass_pm2_5 /= 1.0 + rh / 100.0);
+        let measurement = Measurement::from([3.0; 10]);
+
+        assert_eq!(calibration.apply(&measurement).mass_pm2_5, 5.0);
+        calibration.relative_humidity = Some(25.0);
+        assert_eq!(calibration.apply(&measurement).mass_pm2_5, 4.0);
+        assert_eq!(calibration.apply(&measurement).mass_pm1_0, 3.0);
+    }
+}
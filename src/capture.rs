@@ -0,0 +1,297 @@
+//! Frame capture and replay
+//!
+//! Enabled with the `capture` feature.
+
+use crate::protocol::{Direction, Frame, FrameParser, ENCODED_FRAME_SIZE};
+use crate::Transport;
+
+/// SHDLC start and stop flag
+const FLAG: u8 = 0x7E;
+
+/// Raw bytes of one frame, tagged with direction and sequence number
+///
+/// Bytes are kept SHDLC encoded, as sent or received. Bytes received outside of frames,
+/// e.g. noise, are captured in records of their own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Record {
+    seq: u32,
+    direction: Direction,
+    bytes: heapless::Vec<u8, ENCODED_FRAME_SIZE>,
+}
+
+impl Record {
+    /// Unused slot, to initialize capture buffers with `[Record::EMPTY; N]`
+    pub const EMPTY: Record = Record {
+        seq: 0,
+        direction: Direction::Tx,
+        bytes: heapless::Vec::new(),
+    };
+
+    /// Sequence number, counting all records of both directions
+    pub fn seq(&self) -> u32 {
+        self.seq
+    }
+
+    /// Direction
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    /// Raw bytes
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Whether nothing was recorded
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// Decode the captured bytes, `None` if they hold no complete frame
+    pub fn decode(&self) -> Option<Frame> {
+        let mut parser = FrameParser::new();
+        self.bytes.iter().find_map(|&byte| parser.feed_byte(byte))
+    }
+}
+
+impl Default for Record {
+    fn default() -> Self {
+        Record::EMPTY
+    }
+}
+
+/// Transport wrapper copying every frame sent and received into a ring buffer
+///
+/// The buffer, e.g. `[Record::EMPTY; 16]`, is provided by the caller and overwritten oldest
+/// first once full. Read it back through [`Sps30::serial_mut`](crate::Sps30::serial_mut)
+/// or once the driver is released, then feed it to a [`Replayer`] to reproduce a session.
+#[derive(Debug)]
+pub struct Recorder<'a, T> {
+    inner: T,
+    ring: &'a mut [Record],
+    head: usize,
+    len: usize,
+    seq: u32,
+    rx: Record,
+    in_frame: bool,
+}
+
+impl<'a, T> Recorder<'a, T> {
+    /// Wrap `inner`, capturing into `ring`
+    pub fn new(inner: T, ring: &'a mut [Record]) -> Self {
+        Recorder {
+            inner,
+            ring,
+            head: 0,
+            len: 0,
+            seq: 0,
+            rx: Record::EMPTY,
+            in_frame: false,
+        }
+    }
+
+    /// Records captured so far, oldest first
+    ///
+    /// Bytes of a MISO Frame still being received are not included.
+    pub fn records(&self) -> impl Iterator<Item = &Record> {
+        let start = if self.len < self.ring.len() {
+            0
+        } else {
+            self.head
+        };
+        let (wrapped, first) = self.ring.split_at(start);
+        first.iter().chain(wrapped).take(self.len)
+    }
+
+    /// Drop all captured records
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+        self.rx = Record::EMPTY;
+        self.in_frame = false;
+    }
+
+    /// Return the wrapped transport
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn store(&mut self, direction: Direction, bytes: &[u8]) {
+        for chunk in bytes.chunks(ENCODED_FRAME_SIZE) {
+            let mut record = Record {
+                seq: self.seq,
+                direction,
+                bytes: heapless::Vec::new(),
+            };
+            // Chunks fit by construction
+            let _ = record.bytes.extend_from_slice(chunk);
+            self.seq = self.seq.wrapping_add(1);
+            if let Some(slot) = self.ring.get_mut(self.head) {
+                *slot = record;
+                self.head = (self.head + 1) % self.ring.len();
+                self.len = (self.len + 1).min(self.ring.len());
+            }
+        }
+    }
+
+    fn commit_rx(&mut self) {
+        let rx = core::mem::replace(&mut self.rx, Record::EMPTY);
+        if !rx.is_empty() {
+            self.store(Direction::Rx, &rx.bytes);
+        }
+    }
+
+    fn capture_rx(&mut self, byte: u8) {
+        if byte == FLAG {
+            if !self.in_frame {
+                // Stray bytes before the start flag
+                self.commit_rx();
+                self.in_frame = true;
+            } else if self.rx.bytes.len() > 1 {
+                let _ = self.rx.bytes.push(byte);
+                self.commit_rx();
+                self.in_frame = false;
+                return;
+            } else {
+                // Back-to-back flags, keep a single start flag
+                return;
+            }
+        }
+        if self.rx.bytes.push(byte).is_err() {
+            self.commit_rx();
+            let _ = self.rx.bytes.push(byte);
+        }
+    }
+}
+
+impl<'a, T: Transport> Transport for Recorder<'a, T> {
+    type WriteError = T::WriteError;
+    type ReadError = T::ReadError;
+
+    fn write_all(&mut self, data: &[u8]) -> Result<(), Self::WriteError> {
+        self.store(Direction::Tx, data);
+        self.inner.write_all(data)
+    }
+
+    fn read_byte(&mut self) -> nb::Result<u8, Self::ReadError> {
+        let byte = self.inner.read_byte()?;
+        self.capture_rx(byte);
+        Ok(byte)
+    }
+
+    fn is_disconnected(&self) -> bool {
+        self.inner.is_disconnected()
+    }
+}
+
+/// Transport feeding captured MISO bytes back to the driver
+///
+/// Received records are replayed in sequence order, whatever their position in the
+/// buffer, so the buffer of a [`Recorder`] can be used as is. Sent bytes are dropped.
+/// Reads return `WouldBlock` once all records are replayed.
+#[derive(Debug, Clone)]
+pub struct Replayer<'a> {
+    records: &'a [Record],
+    current: Option<&'a Record>,
+    pos: usize,
+}
+
+impl<'a> Replayer<'a> {
+    /// Replay the received records of `records`
+    pub fn new(records: &'a [Record]) -> Self {
+        let current = Self::next_from(records, 0);
+        Replayer {
+            records,
+            current,
+            pos: 0,
+        }
+    }
+
+    /// Whether all bytes were replayed
+    pub fn is_done(&self) -> bool {
+        self.current.is_none()
+    }
+
+    /// First received record numbered `seq` or more
+    fn next_from(records: &'a [Record], seq: u32) -> Option<&'a Record> {
+        records
+            .iter()
+            .filter(|record| record.direction == Direction::Rx && !record.is_empty())
+            .filter(|record| record.seq >= seq)
+            .min_by_key(|record| record.seq)
+    }
+}
+
+impl<'a> Transport for Replayer<'a> {
+    type WriteError = ();
+    type ReadError = ();
+
+    fn write_all(&mut self, _data: &[u8]) -> Result<(), ()> {
+        Ok(())
+    }
+
+    fn read_byte(&mut self) -> nb::Result<u8, ()> {
+        let record = self.current.ok_or(nb::Error::WouldBlock)?;
+        let byte = record.bytes[self.pos];
+        self.pos += 1;
+        if self.pos == record.bytes.len() {
+            self.current = record
+                .seq
+                .checked_add(1)
+                .and_then(|seq| Self::next_from(self.records, seq));
+            self.pos = 0;
+        }
+        Ok(byte)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol;
+    use crate::replay::Replay;
+    use crate::{CommandType, MeasurementFormat, Sps30};
+
+    #[test]
+    fn capture_then_replay() {
+        // Noise, then the Start Measurement response with a duplicated start flag
+        let rx = &[0x12, 0x7E, 0x7E, 0x00, 0x00, 0x00, 0x00, 0xFF, 0x7E];
+        let mut records = [Record::EMPTY; 4];
+        let sensor = Sps30::new(Recorder::new(Replay { rx }, &mut records));
+        let sensor = sensor
+            .start_measurement(MeasurementFormat::Float)
+            .ok()
+            .unwrap();
+        let recorder = sensor.release();
+        let captured: heapless::Vec<Record, 4> = recorder.records().cloned().collect();
+        drop(recorder);
+
+        assert_eq!(captured.len(), 3);
+        assert_eq!(captured[0].direction(), Direction::Tx);
+        let mosi = protocol::encode_request::<(), ()>(CommandType::StartMeasurement, &[0x01, 0x03])
+            .unwrap();
+        assert_eq!(captured[0].bytes(), &mosi[..]);
+        assert_eq!(captured[1].bytes(), &[0x12]);
+        assert_eq!(captured[2].direction(), Direction::Rx);
+        assert_eq!(captured[2].seq(), 2);
+        assert_eq!(captured[2].decode().unwrap().as_bytes()[1], 0x00);
+
+        let sensor = Sps30::new(Replayer::new(&records));
+        assert!(sensor.start_measurement(MeasurementFormat::Float).is_ok());
+    }
+
+    #[test]
+    fn ring_keeps_latest_records() {
+        let mut records = [Record::EMPTY; 2];
+        let mut recorder = Recorder::new(Replay { rx: &[] }, &mut records);
+        for byte in 1..=3u8 {
+            recorder.write_all(&[byte]).unwrap();
+        }
+        let seqs: heapless::Vec<u32, 2> = recorder.records().map(Record::seq).collect();
+        assert_eq!(&seqs[..], &[1, 2]);
+
+        recorder.clear();
+        assert_eq!(recorder.records().count(), 0);
+    }
+}
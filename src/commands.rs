@@ -0,0 +1,142 @@
+//! Command table shared by the UART drivers
+//!
+//! Each command is a [`Command`] entry: its CMD byte and the parser of its response data,
+//! which checks the expected length. The drivers run every entry through one generic
+//! executor, the request data being built by the calling method.
+
+#[cfg(feature = "cleaning")]
+use crate::AutoCleanInterval;
+#[cfg(feature = "device-info")]
+use crate::InfoString;
+#[cfg(not(feature = "no-float"))]
+use crate::Measurement;
+use crate::{protocol, CommandType, DeviceStatus, Error, MeasurementU16, Versions};
+
+/// Interpret the data of a MISO Frame
+type Parser<T, E, F> = fn(&[u8]) -> Result<T, Error<E, F>>;
+
+/// Command sent by the drivers, answered with a `T`
+pub(crate) struct Command<T, E, F> {
+    /// Command to send
+    pub(crate) cmd: CommandType,
+    /// Interpret the data of the response, checking its length
+    pub(crate) parse: Parser<T, E, F>,
+}
+
+impl<T, E, F> Clone for Command<T, E, F> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T, E, F> Copy for Command<T, E, F> {}
+
+impl<E, F> Command<(), E, F> {
+    pub(crate) const START_MEASUREMENT: Self = Command::acknowledged(CommandType::StartMeasurement);
+    pub(crate) const STOP_MEASUREMENT: Self = Command::acknowledged(CommandType::StopMeasurement);
+    #[cfg(feature = "fan-clean")]
+    pub(crate) const START_FAN_CLEANING: Self =
+        Command::acknowledged(CommandType::StartFanCleaning);
+    #[cfg(feature = "cleaning")]
+    pub(crate) const WRITE_CLEANING_INTERVAL: Self = Command {
+        cmd: CommandType::ReadWriteAutoCleaningInterval,
+        parse: empty,
+    };
+    pub(crate) const SLEEP: Self = Command::acknowledged(CommandType::Sleep);
+    pub(crate) const WAKE_UP: Self = Command::acknowledged(CommandType::WakeUp);
+    pub(crate) const RESET: Self = Command::acknowledged(CommandType::Reset);
+
+    /// Command whose response data, if any, is ignored
+    const fn acknowledged(cmd: CommandType) -> Self {
+        Command { cmd, parse: ignore }
+    }
+}
+
+#[cfg(not(feature = "no-float"))]
+impl<E, F> Command<Measurement, E, F> {
+    pub(crate) const READ_MEASUREMENT: Self = Command {
+        cmd: CommandType::ReadMeasuredData,
+        parse: protocol::parse_measurement,
+    };
+}
+
+impl<E, F> Command<[u8; 40], E, F> {
+    pub(crate) const READ_MEASUREMENT_RAW: Self = Command {
+        cmd: CommandType::ReadMeasuredData,
+        parse: protocol::parse_measurement_raw,
+    };
+}
+
+impl<E, F> Command<MeasurementU16, E, F> {
+    pub(crate) const READ_MEASUREMENT_U16: Self = Command {
+        cmd: CommandType::ReadMeasuredData,
+        parse: protocol::parse_measurement_u16,
+    };
+}
+
+#[cfg(feature = "cleaning")]
+impl<E, F> Command<AutoCleanInterval, E, F> {
+    pub(crate) const READ_CLEANING_INTERVAL: Self = Command {
+        cmd: CommandType::ReadWriteAutoCleaningInterval,
+        parse: cleaning_interval,
+    };
+}
+
+impl<E, F> Command<DeviceStatus, E, F> {
+    pub(crate) const READ_DEVICE_STATUS: Self = Command {
+        cmd: CommandType::ReadDeviceStatusRegister,
+        parse: protocol::parse_device_status,
+    };
+}
+
+#[cfg(feature = "device-info")]
+impl<E, F> Command<InfoString, E, F> {
+    pub(crate) const DEVICE_INFORMATION: Self = Command {
+        cmd: CommandType::DeviceInformation,
+        parse: protocol::parse_device_info_string,
+    };
+}
+
+impl<E, F> Command<Versions, E, F> {
+    pub(crate) const READ_VERSION: Self = Command {
+        cmd: CommandType::ReadVersion,
+        parse: protocol::parse_versions,
+    };
+}
+
+fn ignore<E, F>(_data: &[u8]) -> Result<(), Error<E, F>> {
+    Ok(())
+}
+
+#[cfg(feature = "cleaning")]
+fn empty<E, F>(data: &[u8]) -> Result<(), Error<E, F>> {
+    if !data.is_empty() {
+        return Err(Error::InvalidRespose);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "cleaning")]
+fn cleaning_interval<E, F>(data: &[u8]) -> Result<AutoCleanInterval, Error<E, F>> {
+    protocol::parse_cleaning_interval(data).map(AutoCleanInterval::from_secs)
+}
+
+#[cfg(all(test, feature = "cleaning"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_parse_their_responses() {
+        let write = Command::<(), (), ()>::WRITE_CLEANING_INTERVAL;
+        assert_eq!(write.cmd, CommandType::ReadWriteAutoCleaningInterval);
+        assert!((write.parse)(&[]).is_ok());
+        assert!(matches!((write.parse)(&[0x00]), Err(Error::InvalidRespose)));
+
+        let read = Command::<_, (), ()>::READ_CLEANING_INTERVAL;
+        assert_eq!(
+            (read.parse)(&[0x00, 0x09, 0x3A, 0x80]).ok(),
+            Some(AutoCleanInterval::from_secs(604_800))
+        );
+        assert!((Command::<(), (), ()>::RESET.parse)(&[0x42]).is_ok());
+    }
+}
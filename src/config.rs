@@ -0,0 +1,311 @@
+//! Driver configuration
+
+#[cfg(feature = "debug-frames")]
+use crate::protocol::Direction;
+use crate::protocol::Validation;
+use crate::{
+    Calibration, FrameObserver, Idle, LinkStats, MeasurementFormat, NoDelay, SpecialChars, Sps30,
+    Transport,
+};
+use core::marker::PhantomData;
+use embedded_hal::delay::DelayNs;
+
+/// Behavior of the [`Sps30`] driver, see [`Sps30::builder`]
+#[derive(Debug, Clone, Copy)]
+pub struct Config {
+    /// SHDLC address byte (ADR) of MOSI Frames, expected back in MISO Frames, 0x00 by default
+    ///
+    /// The SPS30 always uses 0x00, other values are meant for multi-drop buses or other
+    /// devices speaking the same protocol.
+    pub address: u8,
+    /// Number of times a command is sent again after a link error (checksum or SHDLC
+    /// failure, invalid frame or timeout), 0 by default
+    pub retries: u8,
+    /// Delay before the first retry, in ms, doubled before every following one, 0 by default
+    ///
+    /// Only used when a timer is set.
+    pub retry_delay_ms: u32,
+    /// Also retry reading measured values when no new ones are available yet, false by default
+    pub retry_on_empty: bool,
+    /// Added to the maximum response time of every command, in ms, 10 by default
+    ///
+    /// Only used when a timer is set, it accounts for the time needed to transfer the frames.
+    pub timeout_margin_ms: u32,
+    /// Max characters read while waiting for a MISO Frame, 100 by default
+    ///
+    /// Values larger than [`ENCODED_FRAME_SIZE`](crate::protocol::ENCODED_FRAME_SIZE) have
+    /// no effect, the receive buffer is sized for the largest frame the device sends.
+    pub max_frame_size: usize,
+    /// SHDLC special characters
+    pub special_chars: SpecialChars,
+    /// How strictly MISO Frames are checked
+    pub validation: Validation,
+    /// Call [`Sps30::flush_rx`] before sending every MOSI Frame, false by default
+    pub flush_before_command: bool,
+    /// What to do while no response byte is available, [`WaitStrategy::Delay`] by default
+    pub wait: WaitStrategy,
+    /// Correction applied to the values returned by `read_measurement`, none by default
+    ///
+    /// Raw and integer format values are returned uncorrected.
+    pub calibration: Option<Calibration>,
+    /// Fail reading measured values with [`Error::Implausible`](crate::Error::Implausible)
+    /// when they are out of the datasheet ranges, false by default
+    ///
+    /// See [`Measurement::plausibility`](crate::Measurement::plausibility), values are
+    /// checked before calibration.
+    pub reject_implausible: bool,
+    /// Number of measured values read after measurement started that belong to the fan
+    /// warm-up, 0 by default
+    ///
+    /// The first ~30s of readings are not representative. New values are produced every
+    /// second, so when reading them every second this is the warm-up time in s, see
+    /// [`Sps30::is_warmed_up`].
+    pub warm_up_reads: u32,
+    /// Fail reading measured values of the warm-up window with
+    /// [`Error::EmptyResult`](crate::Error::EmptyResult), as if none were available yet,
+    /// false by default
+    pub discard_warm_up: bool,
+    /// Recover after that many link errors in a row while reading measured values,
+    /// 0 (never) by default
+    ///
+    /// The RX buffer is flushed, the device reset and measurement started again, as done by
+    /// [`Sps30::recover`], waiting with the timer for the device to start up. The read still
+    /// fails with the last link error. See [`LinkStats::consecutive_errors`].
+    ///
+    /// Only done once a timer is set with [`Sps30::with_timeout`]: without one, measurement
+    /// would be started again before the device is ready.
+    pub recover_after: u32,
+    /// Called after every automatic recovery, with the link counters at that time
+    pub on_recover: Option<fn(&LinkStats)>,
+    /// Told about every frame sent and received, none by default
+    pub observer: Option<&'static dyn FrameObserver>,
+    /// Called with every raw frame sent or received, none by default
+    ///
+    /// With the `defmt` feature, frames are also logged at debug level.
+    #[cfg(feature = "debug-frames")]
+    pub frame_formatter: Option<fn(Direction, &[u8])>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            address: 0x00,
+            retries: 0,
+            retry_delay_ms: 0,
+            retry_on_empty: false,
+            timeout_margin_ms: crate::DEFAULT_TIMEOUT_MARGIN_MS,
+            max_frame_size: crate::MAX_BUFFER,
+            special_chars: SpecialChars::default(),
+            validation: Validation::Strict,
+            flush_before_command: false,
+            wait: WaitStrategy::Delay,
+            calibration: None,
+            reject_implausible: false,
+            warm_up_reads: 0,
+            discard_warm_up: false,
+            recover_after: 0,
+            on_recover: None,
+            observer: None,
+            #[cfg(feature = "debug-frames")]
+            frame_formatter: None,
+        }
+    }
+}
+
+/// How the [`Sps30`] driver waits when the serial port has no response byte yet
+#[derive(Debug, Clone, Copy, Default)]
+pub enum WaitStrategy {
+    /// Poll every 100μs with the timer until the response deadline, see
+    /// [`Sps30::with_timeout`]; without timer, fail at once with `nb::Error::WouldBlock`
+    #[default]
+    Delay,
+    /// Read again right away, at most `polls` times before failing with
+    /// [`Error::Timeout`](crate::Error::Timeout)
+    Spin {
+        /// Reads attempted after the first one
+        polls: u32,
+    },
+    /// Call `yield_now` before reading again, at most `polls` times before failing with
+    /// [`Error::Timeout`](crate::Error::Timeout)
+    ///
+    /// Lets an RTOS scheduler run other tasks, or the core sleep until the next interrupt,
+    /// e.g. with `cortex_m::asm::wfi`, while waiting for bytes.
+    Yield {
+        /// Called before every read after the first one
+        yield_now: fn(),
+        /// Reads attempted after the first one
+        polls: u32,
+    },
+}
+
+/// Builder for the [`Sps30`] driver, created with [`Sps30::builder`]
+#[derive(Debug)]
+pub struct Sps30Builder<SERIAL, DELAY = NoDelay> {
+    /// The concrete Serial device implementation.
+    serial: SERIAL,
+    /// Timer used while waiting for response bytes
+    timer: Option<DELAY>,
+    /// Configuration of the driver being built
+    config: Config,
+}
+
+impl<SERIAL, E, F> Sps30Builder<SERIAL, NoDelay>
+where
+    SERIAL: Transport<WriteError = E, ReadError = F>,
+{
+    /// Start building a driver talking through `serial`, with the default [`Config`]
+    pub fn new(serial: SERIAL) -> Self {
+        Sps30Builder {
+            serial,
+            timer: None,
+            config: Config::default(),
+        }
+    }
+}
+
+impl<SERIAL, DELAY, E, F> Sps30Builder<SERIAL, DELAY>
+where
+    SERIAL: Transport<WriteError = E, ReadError = F>,
+    DELAY: DelayNs,
+{
+    /// Replace the whole configuration
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Set [`Config::address`]
+    pub fn address(mut self, address: u8) -> Self {
+        self.config.address = address;
+        self
+    }
+
+    /// Set [`Config::retries`]
+    pub fn retries(mut self, retries: u8) -> Self {
+        self.config.retries = retries;
+        self
+    }
+
+    /// Set [`Config::retry_delay_ms`]
+    pub fn retry_delay_ms(mut self, delay_ms: u32) -> Self {
+        self.config.retry_delay_ms = delay_ms;
+        self
+    }
+
+    /// Set [`Config::retry_on_empty`]
+    pub fn retry_on_empty(mut self, retry: bool) -> Self {
+        self.config.retry_on_empty = retry;
+        self
+    }
+
+    /// Set [`Config::timeout_margin_ms`]
+    pub fn timeout_margin_ms(mut self, margin_ms: u32) -> Self {
+        self.config.timeout_margin_ms = margin_ms;
+        self
+    }
+
+    /// Set [`Config::max_frame_size`]
+    pub fn max_frame_size(mut self, size: usize) -> Self {
+        self.config.max_frame_size = size;
+        self
+    }
+
+    /// Set [`Config::special_chars`]
+    pub fn special_chars(mut self, special_chars: SpecialChars) -> Self {
+        self.config.special_chars = special_chars;
+        self
+    }
+
+    /// Set [`Config::validation`]
+    pub fn validation(mut self, validation: Validation) -> Self {
+        self.config.validation = validation;
+        self
+    }
+
+    /// Set [`Config::flush_before_command`]
+    pub fn flush_before_command(mut self, flush: bool) -> Self {
+        self.config.flush_before_command = flush;
+        self
+    }
+
+    /// Set [`Config::calibration`]
+    pub fn calibration(mut self, calibration: Calibration) -> Self {
+        self.config.calibration = Some(calibration);
+        self
+    }
+
+    /// Set [`Config::reject_implausible`]
+    pub fn reject_implausible(mut self, reject: bool) -> Self {
+        self.config.reject_implausible = reject;
+        self
+    }
+
+    /// Set [`Config::warm_up_reads`]
+    pub fn warm_up_reads(mut self, reads: u32) -> Self {
+        self.config.warm_up_reads = reads;
+        self
+    }
+
+    /// Set [`Config::discard_warm_up`]
+    pub fn discard_warm_up(mut self, discard: bool) -> Self {
+        self.config.discard_warm_up = discard;
+        self
+    }
+
+    /// Set [`Config::recover_after`]
+    pub fn recover_after(mut self, errors: u32) -> Self {
+        self.config.recover_after = errors;
+        self
+    }
+
+    /// Set [`Config::on_recover`]
+    pub fn on_recover(mut self, callback: fn(&LinkStats)) -> Self {
+        self.config.on_recover = Some(callback);
+        self
+    }
+
+    /// Set [`Config::wait`]
+    pub fn wait(mut self, wait: WaitStrategy) -> Self {
+        self.config.wait = wait;
+        self
+    }
+
+    /// Set [`Config::observer`]
+    pub fn observer(mut self, observer: &'static dyn FrameObserver) -> Self {
+        self.config.observer = Some(observer);
+        self
+    }
+
+    /// Set [`Config::frame_formatter`]
+    #[cfg(feature = "debug-frames")]
+    pub fn frame_formatter(mut self, formatter: fn(Direction, &[u8])) -> Self {
+        self.config.frame_formatter = Some(formatter);
+        self
+    }
+
+    /// Use `timer` to wait for response bytes, see [`Sps30::with_timeout`]
+    pub fn timeout<D: DelayNs>(self, timer: D) -> Sps30Builder<SERIAL, D> {
+        Sps30Builder {
+            serial: self.serial,
+            timer: Some(timer),
+            config: self.config,
+        }
+    }
+
+    /// Build the driver
+    ///
+    /// Device is expected to be in idle mode, as it is after power-up or reset
+    pub fn build(self) -> Sps30<SERIAL, Idle, DELAY> {
+        Sps30 {
+            serial: self.serial,
+            mode: PhantomData,
+            timer: self.timer,
+            config: self.config,
+            stats: LinkStats::default(),
+            firmware: None,
+            format: MeasurementFormat::default(),
+            values_read: 0,
+            cleaning: false,
+        }
+    }
+}
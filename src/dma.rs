@@ -0,0 +1,154 @@
+//! Support for serial ports moving bytes with DMA only
+//!
+//! Enabled with the `embedded-dma` feature.
+
+use crate::protocol::ENCODED_FRAME_SIZE;
+use crate::Transport;
+use embedded_dma::{ReadBuffer, WriteBuffer};
+
+/// Buffer handed to DMA transfers, large enough for any SHDLC encoded frame
+///
+/// Satisfies the `embedded-dma` [`ReadBuffer`] and [`WriteBuffer`] requirements: take it
+/// from a `static`, e.g. with `cortex_m::singleton!`.
+pub type DmaBuffer = &'static mut [u8; ENCODED_FRAME_SIZE];
+
+const _: fn() = || {
+    fn dma_buffer<B: ReadBuffer<Word = u8> + WriteBuffer<Word = u8>>() {}
+    dma_buffer::<DmaBuffer>();
+};
+
+/// Serial port only exposing DMA transfers, implemented on top of a HAL to use it with
+/// [`Dma`]
+pub trait DmaSerial {
+    /// Error of a transfer
+    type Error;
+
+    /// Send the first `len` bytes of `buffer`, blocking until done, and give `buffer` back
+    fn transmit(&mut self, buffer: DmaBuffer, len: usize) -> (DmaBuffer, Result<(), Self::Error>);
+
+    /// Receive into `buffer`, blocking until a frame is received, e.g. until the line goes
+    /// idle, or nothing came in time
+    ///
+    /// Gives `buffer` back along with the number of bytes received, 0 if none.
+    fn receive(&mut self, buffer: DmaBuffer) -> (DmaBuffer, Result<usize, Self::Error>);
+}
+
+/// Wrapper making a [`DmaSerial`] port usable by the [`Sps30`](crate::Sps30) driver
+///
+/// MOSI Frames are copied into the TX buffer and sent in a single transfer. Received bytes
+/// are handed to the driver one by one, a new transfer being started once all are read;
+/// a transfer receiving nothing is reported as `nb::Error::WouldBlock`.
+#[derive(Debug)]
+pub struct Dma<S> {
+    serial: S,
+    tx: Option<DmaBuffer>,
+    rx: Option<DmaBuffer>,
+    received: usize,
+    pos: usize,
+}
+
+impl<S: DmaSerial> Dma<S> {
+    /// Wrap `serial`, transferring through the `tx` and `rx` buffers
+    pub fn new(serial: S, tx: DmaBuffer, rx: DmaBuffer) -> Self {
+        Dma {
+            serial,
+            tx: Some(tx),
+            rx: Some(rx),
+            received: 0,
+            pos: 0,
+        }
+    }
+
+    /// Return the wrapped port and the TX and RX buffers
+    ///
+    /// Buffers are `None` only if a transfer panicked.
+    pub fn release(self) -> (S, Option<DmaBuffer>, Option<DmaBuffer>) {
+        (self.serial, self.tx, self.rx)
+    }
+}
+
+/// Error of a [`Dma`] transport
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DmaError<E> {
+    /// Transfer failed
+    Transfer(E),
+    /// MOSI Frame larger than the TX buffer
+    TooLong,
+    /// Buffer lost in a previous transfer
+    NoBuffer,
+}
+
+impl<S: DmaSerial> Transport for Dma<S> {
+    type WriteError = DmaError<S::Error>;
+    type ReadError = DmaError<S::Error>;
+
+    fn write_all(&mut self, data: &[u8]) -> Result<(), Self::WriteError> {
+        if data.len() > ENCODED_FRAME_SIZE {
+            return Err(DmaError::TooLong);
+        }
+        let buffer = self.tx.take().ok_or(DmaError::NoBuffer)?;
+        buffer[..data.len()].copy_from_slice(data);
+        let (buffer, result) = self.serial.transmit(buffer, data.len());
+        self.tx = Some(buffer);
+        result.map_err(DmaError::Transfer)
+    }
+
+    fn read_byte(&mut self) -> nb::Result<u8, Self::ReadError> {
+        if self.pos == self.received {
+            let buffer = self.rx.take().ok_or(DmaError::NoBuffer)?;
+            let (buffer, result) = self.serial.receive(buffer);
+            self.rx = Some(buffer);
+            self.received = result.map_err(DmaError::Transfer)?.min(ENCODED_FRAME_SIZE);
+            self.pos = 0;
+            if self.received == 0 {
+                return Err(nb::Error::WouldBlock);
+            }
+        }
+        let rx = self.rx.as_ref().ok_or(DmaError::NoBuffer)?;
+        let byte = rx[self.pos];
+        self.pos += 1;
+        Ok(byte)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::{MeasurementFormat, Sps30};
+    use std::boxed::Box;
+
+    /// Port answering every transfer with the Start Measurement response
+    struct Loopback {
+        sent: usize,
+    }
+
+    impl DmaSerial for Loopback {
+        type Error = ();
+
+        fn transmit(&mut self, buffer: DmaBuffer, len: usize) -> (DmaBuffer, Result<(), ()>) {
+            self.sent += len;
+            (buffer, Ok(()))
+        }
+
+        fn receive(&mut self, buffer: DmaBuffer) -> (DmaBuffer, Result<usize, ()>) {
+            let response = [0x7E, 0x00, 0x00, 0x00, 0x00, 0xFF, 0x7E];
+            buffer[..response.len()].copy_from_slice(&response);
+            (buffer, Ok(response.len()))
+        }
+    }
+
+    #[test]
+    fn transfers_whole_frames() {
+        let tx = Box::leak(Box::new([0; ENCODED_FRAME_SIZE]));
+        let rx = Box::leak(Box::new([0; ENCODED_FRAME_SIZE]));
+        let sensor = Sps30::new(Dma::new(Loopback { sent: 0 }, tx, rx));
+        let sensor = sensor
+            .start_measurement(MeasurementFormat::Float)
+            .ok()
+            .unwrap();
+        let (serial, tx, _) = sensor.release().release();
+        assert_eq!(serial.sent, 8);
+        assert_eq!(&tx.unwrap()[..8], &crate::protocol::START_MEASUREMENT_FLOAT);
+    }
+}
@@ -0,0 +1,187 @@
+//! Duty-cycled measurement, for battery-powered devices
+
+#[cfg(not(feature = "no-float"))]
+use crate::iter::MAX_EMPTY_WAIT_MS;
+#[cfg(not(feature = "no-float"))]
+use crate::{Error, Measurement, MeasurementFormat, Transition, Transport};
+use crate::{Idle, Measuring, Sleeping, Sps30};
+#[cfg(not(feature = "no-float"))]
+use embedded_hal::delay::DelayNs;
+
+/// Time for measured values to settle after starting measurement, in ms
+///
+/// The datasheet gives 8s to 30s, depending on the concentration.
+#[cfg(not(feature = "no-float"))]
+pub(crate) const WARM_UP_MS: u32 = 30_000;
+
+/// Driver in any mode, when the mode is only known at runtime
+#[derive(Debug)]
+pub enum AnySps30<SERIAL, DELAY> {
+    /// Driver in idle mode
+    Idle(Sps30<SERIAL, Idle, DELAY>),
+    /// Driver in measurement mode
+    Measuring(Sps30<SERIAL, Measuring, DELAY>),
+    /// Driver in sleep mode
+    Sleeping(Sps30<SERIAL, Sleeping, DELAY>),
+}
+
+/// Timing of a measure cycle of [`DutyCycled`]
+#[cfg(not(feature = "no-float"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DutyCycle {
+    /// Time waited after starting measurement before reading, in ms, 30s by default
+    ///
+    /// The datasheet gives 8s to 30s, depending on the concentration, for values to settle.
+    pub warm_up_ms: u32,
+    /// Number of measurements averaged, 1 by default
+    pub reads: u8,
+    /// Time between two reads, in ms, 1s by default
+    pub read_interval_ms: u32,
+    /// Put the device in sleep mode between cycles, true by default
+    ///
+    /// Devices with a firmware older than 2.0 stay in idle mode.
+    pub sleep: bool,
+}
+
+#[cfg(not(feature = "no-float"))]
+impl Default for DutyCycle {
+    fn default() -> Self {
+        DutyCycle {
+            warm_up_ms: WARM_UP_MS,
+            reads: 1,
+            read_interval_ms: 1000,
+            sleep: true,
+        }
+    }
+}
+
+/// Driver measuring only when sampled, the fan is stopped in between
+///
+/// Every [`DutyCycled::sample`] wakes the device up, starts measurement, waits for the
+/// warm-up time, averages the configured number of reads, stops measurement and puts the
+/// device back to sleep.
+#[cfg(not(feature = "no-float"))]
+pub struct DutyCycled<SERIAL, DELAY, D> {
+    /// Driver, `None` only while changing mode
+    sps30: Option<AnySps30<SERIAL, DELAY>>,
+    /// Delay used for warm-up and between reads
+    delay: D,
+    /// Timing of the cycles
+    cycle: DutyCycle,
+}
+
+#[cfg(not(feature = "no-float"))]
+impl<SERIAL, DELAY, D, E, F> DutyCycled<SERIAL, DELAY, D>
+where
+    SERIAL: Transport<WriteError = E, ReadError = F>,
+    DELAY: DelayNs,
+    D: DelayNs,
+{
+    /// Duty-cycle `sps30`, in any mode, waiting with `delay`
+    pub fn new(sps30: impl Into<AnySps30<SERIAL, DELAY>>, delay: D, cycle: DutyCycle) -> Self {
+        DutyCycled {
+            sps30: Some(sps30.into()),
+            delay,
+            cycle,
+        }
+    }
+
+    /// Run a measure cycle, returning the mean of the values read
+    ///
+    /// On failure, measurement is stopped if it was started and the driver stays in the
+    /// mode it reached; the next call resumes from there.
+    pub fn sample(&mut self) -> Result<Measurement, Error<E, F>> {
+        let sps30 = self.sps30.take().expect("driver is always put back");
+        let (result, sps30) = match self.start(sps30) {
+            Ok(mut measuring) => {
+                let result = self.read(&mut measuring);
+                (result, self.stop(measuring))
+            }
+            Err((e, sps30)) => (Err(e), sps30),
+        };
+        self.sps30 = Some(sps30);
+        result
+    }
+
+    /// Driver in its current mode
+    pub fn release(mut self) -> AnySps30<SERIAL, DELAY> {
+        self.sps30.take().expect("driver is always put back")
+    }
+
+    /// Wake up if needed, then start measuring and wait for the warm-up time
+    fn start(
+        &mut self,
+        sps30: AnySps30<SERIAL, DELAY>,
+    ) -> Transition<Sps30<SERIAL, Measuring, DELAY>, AnySps30<SERIAL, DELAY>, E, F> {
+        let idle = match sps30 {
+            AnySps30::Measuring(measuring) => return Ok(measuring),
+            AnySps30::Idle(idle) => idle,
+            AnySps30::Sleeping(sleeping) => sleeping
+                .wake_up()
+                .map_err(|(e, sleeping)| (e, sleeping.into()))?,
+        };
+        let measuring = idle
+            .start_measurement(MeasurementFormat::Float)
+            .map_err(|(e, idle)| (e, idle.into()))?;
+        self.delay.delay_ms(self.cycle.warm_up_ms);
+        Ok(measuring)
+    }
+
+    /// Mean of the configured number of reads, empty results are read again for up to 10s
+    fn read(
+        &mut self,
+        measuring: &mut Sps30<SERIAL, Measuring, DELAY>,
+    ) -> Result<Measurement, Error<E, F>> {
+        let reads = self.cycle.reads.max(1);
+        let mut sum = [0.0; 10];
+        for n in 0..reads {
+            if n > 0 {
+                self.delay.delay_ms(self.cycle.read_interval_ms);
+            }
+            let measurement =
+                measuring.wait_for_first_measurement(&mut self.delay, MAX_EMPTY_WAIT_MS)?;
+            for (sum, value) in sum.iter_mut().zip(measurement.to_array().iter()) {
+                *sum += value;
+            }
+        }
+        for sum in sum.iter_mut() {
+            *sum /= f32::from(reads);
+        }
+        Ok(Measurement::from(sum))
+    }
+
+    /// Stop measuring, then sleep if configured, as far as the device allows
+    fn stop(&mut self, measuring: Sps30<SERIAL, Measuring, DELAY>) -> AnySps30<SERIAL, DELAY> {
+        let idle = match measuring.stop_measurement() {
+            Ok(idle) => idle,
+            Err((_, measuring)) => return measuring.into(),
+        };
+        if !self.cycle.sleep {
+            return idle.into();
+        }
+        match idle.sleep() {
+            Ok(sleeping) => sleeping.into(),
+            Err((_, idle)) => idle.into(),
+        }
+    }
+}
+
+impl<SERIAL, DELAY> From<Sps30<SERIAL, Idle, DELAY>> for AnySps30<SERIAL, DELAY> {
+    fn from(sps30: Sps30<SERIAL, Idle, DELAY>) -> Self {
+        AnySps30::Idle(sps30)
+    }
+}
+
+impl<SERIAL, DELAY> From<Sps30<SERIAL, Measuring, DELAY>> for AnySps30<SERIAL, DELAY> {
+    fn from(sps30: Sps30<SERIAL, Measuring, DELAY>) -> Self {
+        AnySps30::Measuring(sps30)
+    }
+}
+
+impl<SERIAL, DELAY> From<Sps30<SERIAL, Sleeping, DELAY>> for AnySps30<SERIAL, DELAY> {
+    fn from(sps30: Sps30<SERIAL, Sleeping, DELAY>) -> Self {
+        AnySps30::Sleeping(sps30)
+    }
+}
@@ -0,0 +1,89 @@
+//! Measurement tasks publishing to `embassy-sync` primitives
+//!
+//! Enabled with the `embassy` feature.
+
+use crate::iter::{EMPTY_POLL_MS, MIN_INTERVAL_MS};
+use crate::{DeviceError, Error, Measurement, MeasurementFormat, Sps30Async};
+use core::future::Future;
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::channel::Channel;
+use embassy_sync::watch::Watch;
+use embedded_hal_async::delay::DelayNs;
+use embedded_io_async::{Read, Write};
+
+impl<SERIAL, DELAY, E> Sps30Async<SERIAL, DELAY>
+where
+    SERIAL: Read<Error = E> + Write<Error = E>,
+    DELAY: DelayNs,
+{
+    /// Measure forever, sending the values read every `interval_ms` to `watch`
+    ///
+    /// Meant as the body of an embassy task owning the driver, with `watch` a `static`
+    /// the rest of the firmware reads the latest values from:
+    /// `sensor.publish_to_watch(delay, 1000, &MEASUREMENTS).await`. See
+    /// [`publish_to_channel`](Self::publish_to_channel) for the details.
+    pub async fn publish_to_watch<D, M, const N: usize>(
+        &mut self,
+        delay: D,
+        interval_ms: u32,
+        watch: &Watch<M, Measurement, N>,
+    ) -> !
+    where
+        D: DelayNs,
+        M: RawMutex,
+    {
+        let sender = watch.sender();
+        self.publish(delay, interval_ms, |measurement| {
+            sender.send(measurement);
+            async {}
+        })
+        .await
+    }
+
+    /// Measure forever, sending the values read every `interval_ms` to `channel`
+    ///
+    /// Measurement is started in float format, and started again after every failed read in
+    /// case the device was reset. Empty results are skipped and errors are dropped, so only
+    /// measured values are published; a full channel pauses the sampling until a value is
+    /// received. The interval is at least 1s.
+    pub async fn publish_to_channel<D, M, const N: usize>(
+        &mut self,
+        delay: D,
+        interval_ms: u32,
+        channel: &Channel<M, Measurement, N>,
+    ) -> !
+    where
+        D: DelayNs,
+        M: RawMutex,
+    {
+        self.publish(delay, interval_ms, |measurement| channel.send(measurement))
+            .await
+    }
+
+    async fn publish<D, P, Fut>(&mut self, mut delay: D, interval_ms: u32, mut publish: P) -> !
+    where
+        D: DelayNs,
+        P: FnMut(Measurement) -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        let interval_ms = interval_ms.max(MIN_INTERVAL_MS);
+        let mut measuring = false;
+        loop {
+            if !measuring {
+                measuring = match self.start_measurement(MeasurementFormat::Float).await {
+                    Ok(()) | Err(Error::StatusError(DeviceError::NotAllowedInState)) => true,
+                    Err(_) => false,
+                };
+            }
+            match self.read_measurement().await {
+                Ok(measurement) => publish(measurement).await,
+                Err(Error::EmptyResult) => {
+                    delay.delay_ms(EMPTY_POLL_MS).await;
+                    continue;
+                }
+                Err(_) => measuring = false,
+            }
+            delay.delay_ms(interval_ms).await;
+        }
+    }
+}
@@ -0,0 +1,210 @@
+//! Smoothing of measured values
+//!
+//! Filters are applied channel by channel: each field of the returned [`Measurement`] is
+//! the filtered value of that field.
+
+use crate::Measurement;
+use heapless::HistoryBuffer;
+
+/// Filter smoothing measured values
+pub trait Filter {
+    /// Feed a new measurement, returning the filtered values
+    fn update(&mut self, measurement: &Measurement) -> Measurement;
+
+    /// Forget the measurements fed so far
+    fn reset(&mut self);
+}
+
+/// Filter returning measured values unchanged
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Unfiltered;
+
+impl Filter for Unfiltered {
+    fn update(&mut self, measurement: &Measurement) -> Measurement {
+        *measurement
+    }
+
+    fn reset(&mut self) {}
+}
+
+/// Simple moving average over the last `N` measurements
+///
+/// Until `N` measurements were fed, the average is over the ones available.
+#[derive(Debug, Default)]
+pub struct MovingAverage<const N: usize> {
+    /// Last `N` values
+    window: HistoryBuffer<[f32; 10], N>,
+}
+
+impl<const N: usize> MovingAverage<N> {
+    /// Moving average, nothing fed yet
+    pub fn new() -> Self {
+        MovingAverage {
+            window: HistoryBuffer::new(),
+        }
+    }
+}
+
+impl<const N: usize> Filter for MovingAverage<N> {
+    fn update(&mut self, measurement: &Measurement) -> Measurement {
+        self.window.write(measurement.to_array());
+        let count = self.window.len() as f32;
+        let mut mean = [0.0; 10];
+        for values in self.window.iter() {
+            for (mean, value) in mean.iter_mut().zip(values.iter()) {
+                *mean += value;
+            }
+        }
+        for value in mean.iter_mut() {
+            *value /= count;
+        }
+        Measurement::from(mean)
+    }
+
+    fn reset(&mut self) {
+        self.window.clear();
+    }
+}
+
+/// Exponential moving average
+///
+/// Each new value is weighted by `alpha`, the previous average by `1 - alpha`: the closer
+/// `alpha` is to 0 the smoother, and the slower to follow changes, the output is.
+#[derive(Debug, Clone, Copy)]
+pub struct ExponentialMovingAverage {
+    /// Weight of a new value, between 0 and 1
+    alpha: f32,
+    /// Current average, `None` until the first measurement is fed
+    average: Option<[f32; 10]>,
+}
+
+impl ExponentialMovingAverage {
+    /// Exponential moving average weighting new values by `alpha`, clamped to 0..=1
+    ///
+    /// The first measurement fed is returned as is.
+    pub fn new(alpha: f32) -> Self {
+        ExponentialMovingAverage {
+            alpha: alpha.clamp(0.0, 1.0),
+            average: None,
+        }
+    }
+
+    /// Weight of a new value
+    pub fn alpha(&self) -> f32 {
+        self.alpha
+    }
+}
+
+impl Filter for ExponentialMovingAverage {
+    fn update(&mut self, measurement: &Measurement) -> Measurement {
+        let values = measurement.to_array();
+        let average = match self.average.as_mut() {
+            Some(average) => {
+                for (average, value) in average.iter_mut().zip(values.iter()) {
+                    *average += self.alpha * (value - *average);
+                }
+                *average
+            }
+            None => *self.average.insert(values),
+        };
+        Measurement::from(average)
+    }
+
+    fn reset(&mut self) {
+        self.average = None;
+    }
+}
+
+/// One dimensional Kalman filter, assuming values stay constant between measurements
+///
+/// Less lag than a moving average of similar smoothness: the gain adapts to how much the
+/// estimate is trusted. `process_noise` is the variance of the real change between two
+/// measurements, `measurement_noise` the variance of the sensor noise; the larger their
+/// ratio, the faster the output follows changes.
+#[derive(Debug, Clone, Copy)]
+pub struct Kalman {
+    /// Variance of the change between two measurements
+    process_noise: f32,
+    /// Variance of the sensor noise
+    measurement_noise: f32,
+    /// Estimate and its error variance per channel, `None` until the first measurement
+    state: Option<([f32; 10], [f32; 10])>,
+}
+
+impl Kalman {
+    /// Kalman filter with the given process and measurement noise variances
+    ///
+    /// The first measurement fed is returned as is.
+    pub fn new(process_noise: f32, measurement_noise: f32) -> Self {
+        Kalman {
+            process_noise,
+            measurement_noise,
+            state: None,
+        }
+    }
+}
+
+impl Filter for Kalman {
+    fn update(&mut self, measurement: &Measurement) -> Measurement {
+        let values = measurement.to_array();
+        let (estimate, variance) = match self.state.as_mut() {
+            Some(state) => state,
+            None => {
+                return Measurement::from(
+                    self.state.insert((values, [self.measurement_noise; 10])).0,
+                )
+            }
+        };
+        for ((x, p), z) in estimate
+            .iter_mut()
+            .zip(variance.iter_mut())
+            .zip(values.iter())
+        {
+            let predicted = *p + self.process_noise;
+            let gain = predicted / (predicted + self.measurement_noise);
+            *x += gain * (z - *x);
+            *p = (1.0 - gain) * predicted;
+        }
+        Measurement::from(*estimate)
+    }
+
+    fn reset(&mut self) {
+        self.state = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn measurement(value: f32) -> Measurement {
+        Measurement::from([value; 10])
+    }
+
+    #[test]
+    fn moving_average_over_window() {
+        let mut sma = MovingAverage::<2>::new();
+        assert_eq!(sma.update(&measurement(2.0)), measurement(2.0));
+        assert_eq!(sma.update(&measurement(4.0)), measurement(3.0));
+        assert_eq!(sma.update(&measurement(8.0)), measurement(6.0));
+        sma.reset();
+        assert_eq!(sma.update(&measurement(1.0)), measurement(1.0));
+    }
+
+    #[test]
+    fn exponential_moving_average() {
+        let mut ema = ExponentialMovingAverage::new(0.5);
+        assert_eq!(ema.update(&measurement(2.0)), measurement(2.0));
+        assert_eq!(ema.update(&measurement(4.0)), measurement(3.0));
+        assert_eq!(ema.update(&measurement(3.0)), measurement(3.0));
+    }
+
+    #[test]
+    fn kalman_converges() {
+        let mut kalman = Kalman::new(0.0, 1.0);
+        assert_eq!(kalman.update(&measurement(0.0)), measurement(0.0));
+        // Without process noise, the estimate is the mean of the measurements
+        assert_eq!(kalman.update(&measurement(4.0)), measurement(2.0));
+        assert_eq!(kalman.update(&measurement(8.0)), measurement(4.0));
+    }
+}
@@ -0,0 +1,110 @@
+//! Measured values as fixed-point numbers
+//!
+//! Enabled with the `fixed` feature, for targets without FPU: values are converted from
+//! the bits of the IEEE754 floats sent by the device, or from the integer format, without
+//! any float arithmetic.
+
+use crate::MeasurementU16;
+use fixed::types::{I16F16, U16F16};
+
+/// Number of fractional bits of the fixed-point types
+const FRAC_BITS: i32 = 16;
+
+/// Convert a big-endian IEEE754 float to an unsigned fixed-point number
+///
+/// Truncated towards zero. Negative values and NaN give 0, values too large saturate.
+pub fn u16f16_from_be_bytes(bytes: [u8; 4]) -> U16F16 {
+    let bits = u32::from_be_bytes(bytes);
+    if bits >> 31 != 0 {
+        return U16F16::ZERO;
+    }
+    match scaled(bits) {
+        Some(value) => U16F16::from_bits(value),
+        None if is_nan(bits) => U16F16::ZERO,
+        None => U16F16::MAX,
+    }
+}
+
+/// Convert a big-endian IEEE754 float to a signed fixed-point number
+///
+/// Truncated towards zero. NaN gives 0, values too large saturate.
+pub fn i16f16_from_be_bytes(bytes: [u8; 4]) -> I16F16 {
+    let bits = u32::from_be_bytes(bytes);
+    let negative = bits >> 31 != 0;
+    let magnitude = match scaled(bits & 0x7FFF_FFFF) {
+        Some(value) if value <= i32::MAX as u32 => value as i32,
+        None if is_nan(bits) => 0,
+        _ => i32::MAX,
+    };
+    I16F16::from_bits(if negative { -magnitude } else { magnitude })
+}
+
+/// Measured values from the payload of Read Measured Values in float format
+///
+/// Same order as [`Measurement::to_array`](crate::Measurement::to_array).
+pub fn from_bytes(bytes: &[u8; 40]) -> [U16F16; 10] {
+    let mut values = [U16F16::ZERO; 10];
+    for (value, chunk) in values.iter_mut().zip(bytes.chunks_exact(4)) {
+        *value = u16f16_from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+    }
+    values
+}
+
+/// Measured values read in integer format, with the units of the float format
+///
+/// The typical particle size is converted from nm to μm, the other values are exact.
+pub fn from_u16(measurement: &MeasurementU16) -> [U16F16; 10] {
+    let mut fixed = [U16F16::ZERO; 10];
+    for (fixed, value) in fixed.iter_mut().zip(measurement.to_array().iter()) {
+        *fixed = U16F16::from_num(*value);
+    }
+    fixed[9] /= U16F16::from_num(1000);
+    fixed
+}
+
+/// Positive float `bits` times 2^16, `None` if it does not fit in 32 bits or is not finite
+fn scaled(bits: u32) -> Option<u32> {
+    let exponent = ((bits >> 23) & 0xFF) as i32;
+    if exponent == 0xFF {
+        return None;
+    }
+    if exponent == 0 {
+        // Zero, or subnormal far below the resolution
+        return Some(0);
+    }
+    let mantissa = (bits & 0x7F_FFFF) | 0x80_0000;
+    // value * 2^16 = mantissa * 2^(exponent - 127 - 23 + 16)
+    let shift = exponent - 127 - 23 + FRAC_BITS;
+    if shift >= 0 {
+        mantissa
+            .checked_shl(shift as u32)
+            .filter(|v| v >> shift == mantissa)
+    } else {
+        Some(mantissa.checked_shr((-shift) as u32).unwrap_or(0))
+    }
+}
+
+/// Float `bits` is NaN
+fn is_nan(bits: u32) -> bool {
+    (bits >> 23) & 0xFF == 0xFF && bits & 0x7F_FFFF != 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn float_bits_to_fixed() {
+        let fixed = |v: f32| u16f16_from_be_bytes(v.to_be_bytes());
+        assert_eq!(fixed(0.0), U16F16::ZERO);
+        assert_eq!(fixed(12.5), U16F16::from_num(12.5));
+        assert_eq!(fixed(1000.25), U16F16::from_num(1000.25));
+        assert_eq!(fixed(-3.0), U16F16::ZERO);
+        assert_eq!(fixed(f32::NAN), U16F16::ZERO);
+        assert_eq!(fixed(70000.0), U16F16::MAX);
+        assert_eq!(
+            i16f16_from_be_bytes((-3.5f32).to_be_bytes()),
+            I16F16::from_num(-3.5)
+        );
+    }
+}
@@ -0,0 +1,311 @@
+//! SHDLC byte-stuffing, done in place on caller buffers
+//!
+//! With the `sensirion-hdlc` feature, [`SpecialChars`] and [`HDLCError`] are the types of
+//! the `sensirion-hdlc` crate, for code written against it.
+
+#[cfg(feature = "sensirion-hdlc")]
+pub use sensirion_hdlc::{HDLCError, SpecialChars};
+
+/// XON and XOFF, byte-stuffed as `fesc` followed by these bytes XORed with 0x20
+pub(crate) const XON: u8 = 0x11;
+pub(crate) const XOFF: u8 = 0x13;
+
+/// Special characters of the SHDLC framing
+#[cfg(not(feature = "sensirion-hdlc"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpecialChars {
+    /// Frame start and stop flag
+    pub fend: u8,
+    /// Escape character
+    pub fesc: u8,
+    /// Escaped `fend`
+    pub tfend: u8,
+    /// Escaped `fesc`
+    pub tfesc: u8,
+}
+
+#[cfg(not(feature = "sensirion-hdlc"))]
+impl Default for SpecialChars {
+    /// Characters used by the SPS30
+    fn default() -> Self {
+        SpecialChars {
+            fend: 0x7E,
+            fesc: 0x7D,
+            tfend: 0x5E,
+            tfesc: 0x5D,
+        }
+    }
+}
+
+#[cfg(not(feature = "sensirion-hdlc"))]
+impl SpecialChars {
+    /// Custom special characters
+    pub fn new(fend: u8, fesc: u8, tfend: u8, tfesc: u8) -> Self {
+        SpecialChars {
+            fend,
+            fesc,
+            tfend,
+            tfesc,
+        }
+    }
+}
+
+/// SHDLC encoding or decoding error
+#[cfg(not(feature = "sensirion-hdlc"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HDLCError {
+    /// Start or stop flag found inside the frame
+    FendCharInData,
+    /// Escape character not followed by an escaped character
+    MissingTradeChar,
+    /// Frame does not begin with a start flag
+    MissingFirstFend,
+    /// Frame does not end with a stop flag
+    MissingFinalFend,
+    /// Special characters are not all different
+    DuplicateSpecialChar,
+    /// Frame too large for the buffer
+    TooMuchData,
+}
+
+/// Whether `a` and `b` are the same special characters
+pub(crate) fn same_chars(a: SpecialChars, b: SpecialChars) -> bool {
+    (a.fend, a.fesc, a.tfend, a.tfesc) == (b.fend, b.fesc, b.tfend, b.tfesc)
+}
+
+/// SHDLC encode the frame made of `header` followed by `data` and their checksum into
+/// `buf`, returns the encoded length
+pub(crate) fn encode_frame(
+    header: &[u8],
+    data: &[u8],
+    s_chars: SpecialChars,
+    buf: &mut [u8],
+) -> Result<usize, HDLCError> {
+    let mut frame = FrameBuilder::new(buf, s_chars)?;
+    frame.extend(header)?;
+    frame.extend(data)?;
+    frame.finish()
+}
+
+/// Same as [`encode_frame`] with a wrong checksum, stuffed like any other byte
+#[cfg(feature = "simulator")]
+pub(crate) fn encode_corrupted_frame(
+    header: &[u8],
+    data: &[u8],
+    s_chars: SpecialChars,
+    buf: &mut [u8],
+) -> Result<usize, HDLCError> {
+    let mut frame = FrameBuilder::new(buf, s_chars)?;
+    frame.extend(header)?;
+    frame.extend(data)?;
+    frame.sum ^= 0x01;
+    frame.finish()
+}
+
+/// Frame being SHDLC encoded into a caller buffer, checksum computed along the way
+///
+/// Every byte pushed is stuffed and added to the running sum right away, so the frame is
+/// built in a single pass; [`FrameBuilder::finish`] appends the checksum and stop flag.
+struct FrameBuilder<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+    sum: u8,
+    s_chars: SpecialChars,
+}
+
+impl<'a> FrameBuilder<'a> {
+    /// Start a frame in `buf` with a start flag
+    fn new(buf: &'a mut [u8], s_chars: SpecialChars) -> Result<Self, HDLCError> {
+        let chars = [s_chars.fend, s_chars.fesc, s_chars.tfend, s_chars.tfesc];
+        if chars
+            .iter()
+            .enumerate()
+            .any(|(i, c)| chars[i + 1..].contains(c))
+        {
+            return Err(HDLCError::DuplicateSpecialChar);
+        }
+        let mut builder = FrameBuilder {
+            buf,
+            len: 0,
+            sum: 0,
+            s_chars,
+        };
+        builder.put(s_chars.fend)?;
+        Ok(builder)
+    }
+
+    /// Append `bytes` to the frame content
+    fn extend(&mut self, bytes: &[u8]) -> Result<(), HDLCError> {
+        bytes.iter().try_for_each(|&byte| self.push(byte))
+    }
+
+    /// Append `byte` to the frame content
+    fn push(&mut self, byte: u8) -> Result<(), HDLCError> {
+        self.sum = self.sum.wrapping_add(byte);
+        self.stuff(byte)
+    }
+
+    /// Append the checksum and stop flag, returns the encoded length
+    fn finish(mut self) -> Result<usize, HDLCError> {
+        self.stuff(255 - self.sum)?;
+        self.put(self.s_chars.fend)?;
+        Ok(self.len)
+    }
+
+    fn stuff(&mut self, byte: u8) -> Result<(), HDLCError> {
+        let s_chars = self.s_chars;
+        if byte == s_chars.fend {
+            self.put(s_chars.fesc)?;
+            self.put(s_chars.tfend)
+        } else if byte == s_chars.fesc {
+            self.put(s_chars.fesc)?;
+            self.put(s_chars.tfesc)
+        } else if byte == XON || byte == XOFF {
+            self.put(s_chars.fesc)?;
+            self.put(byte ^ 0x20)
+        } else {
+            self.put(byte)
+        }
+    }
+
+    fn put(&mut self, byte: u8) -> Result<(), HDLCError> {
+        let slot = self.buf.get_mut(self.len).ok_or(HDLCError::TooMuchData)?;
+        *slot = byte;
+        self.len += 1;
+        Ok(())
+    }
+}
+
+/// MOSI Frame of command byte `cmd` carrying `data` to the device at address 0x00, built at
+/// compile time for the default special characters
+///
+/// Fails to compile if `N` is not the frame length, i.e. if a byte would need stuffing.
+pub(crate) const fn fixed_frame<const N: usize>(cmd: u8, data: &[u8]) -> [u8; N] {
+    assert!(N == data.len() + 6, "frame length mismatch");
+    let mut frame = [0x7E; N];
+    frame[2] = cmd;
+    frame[3] = data.len() as u8;
+    let mut sum = cmd.wrapping_add(data.len() as u8);
+    let mut i = 0;
+    while i < data.len() {
+        frame[4 + i] = data[i];
+        sum = sum.wrapping_add(data[i]);
+        i += 1;
+    }
+    frame[1] = 0x00;
+    frame[N - 2] = 255 - sum;
+    let mut i = 1;
+    while i < N - 1 {
+        let byte = frame[i];
+        assert!(
+            byte != 0x7E && byte != 0x7D && byte != XON && byte != XOFF,
+            "byte needs stuffing"
+        );
+        i += 1;
+    }
+    frame
+}
+
+/// Remove the flags and byte-stuffing of `frame` in place, returns the decoded length
+///
+/// Decoded bytes are written from the beginning of `frame`, never past the byte being read.
+pub(crate) fn unstuff_in_place(
+    frame: &mut [u8],
+    s_chars: SpecialChars,
+) -> Result<usize, HDLCError> {
+    let end = inner_end(frame, s_chars)?;
+    let mut read = 1;
+    let mut len = 0;
+    while read < end {
+        let byte = frame[read];
+        read += 1;
+        frame[len] = if byte == s_chars.fesc {
+            let next = frame[..end].get(read).copied();
+            read += 1;
+            unescape(next, s_chars)?
+        } else if byte == s_chars.fend {
+            return Err(HDLCError::FendCharInData);
+        } else {
+            byte
+        };
+        len += 1;
+    }
+    Ok(len)
+}
+
+/// Remove the flags and byte-stuffing of `frame` into `buf`, returns the decoded length
+pub(crate) fn unstuff(
+    frame: &[u8],
+    s_chars: SpecialChars,
+    buf: &mut [u8],
+) -> Result<usize, HDLCError> {
+    let end = inner_end(frame, s_chars)?;
+    let mut bytes = frame[1..end].iter();
+    let mut len = 0;
+    while let Some(&byte) = bytes.next() {
+        let value = if byte == s_chars.fesc {
+            unescape(bytes.next().copied(), s_chars)?
+        } else if byte == s_chars.fend {
+            return Err(HDLCError::FendCharInData);
+        } else {
+            byte
+        };
+        *buf.get_mut(len).ok_or(HDLCError::TooMuchData)? = value;
+        len += 1;
+    }
+    Ok(len)
+}
+
+/// Index of the stop flag of `frame`, checking both flags are there
+fn inner_end(frame: &[u8], s_chars: SpecialChars) -> Result<usize, HDLCError> {
+    match frame {
+        [first, .., last] if *first == s_chars.fend && *last == s_chars.fend => Ok(frame.len() - 1),
+        [first, ..] if *first == s_chars.fend => Err(HDLCError::MissingFinalFend),
+        _ => Err(HDLCError::MissingFirstFend),
+    }
+}
+
+/// Byte escaped by `next`, the byte following an escape character
+fn unescape(next: Option<u8>, s_chars: SpecialChars) -> Result<u8, HDLCError> {
+    match next {
+        Some(next) if next == s_chars.tfend => Ok(s_chars.fend),
+        Some(next) if next == s_chars.tfesc => Ok(s_chars.fesc),
+        Some(next) if next == XON ^ 0x20 || next == XOFF ^ 0x20 => Ok(next ^ 0x20),
+        _ => Err(HDLCError::MissingTradeChar),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn in_place_round_trip() {
+        let s_chars = SpecialChars::default();
+        let frame = [0x00, 0x7E, 0x7D, 0x11, 0x13, 0x42];
+        let mut buf = [0; 16];
+        let mut builder = FrameBuilder::new(&mut buf, s_chars).unwrap();
+        builder.extend(&frame).unwrap();
+        let len = builder.finish().unwrap();
+        assert_eq!(
+            &buf[..len],
+            &[0x7E, 0x00, 0x7D, 0x5E, 0x7D, 0x5D, 0x7D, 0x31, 0x7D, 0x33, 0x42, 0x9E, 0x7E]
+        );
+        let len = unstuff_in_place(&mut buf[..len], s_chars).unwrap();
+        assert_eq!(&buf[..len - 1], &frame);
+
+        let mut truncated = [0x7E, 0x00, 0x7D, 0x7E];
+        assert_eq!(
+            unstuff_in_place(&mut truncated, s_chars),
+            Err(HDLCError::MissingTradeChar)
+        );
+        let duplicate = SpecialChars::new(0x7E, 0x7E, 0x5E, 0x5D);
+        assert!(matches!(
+            FrameBuilder::new(&mut buf, duplicate),
+            Err(HDLCError::DuplicateSpecialChar)
+        ));
+        let mut short = [0; 4];
+        let mut builder = FrameBuilder::new(&mut short, s_chars).unwrap();
+        assert_eq!(builder.extend(&frame), Err(HDLCError::TooMuchData));
+    }
+}
@@ -0,0 +1,431 @@
+//! I2C interface of the SPS30
+//!
+//! Every command is a 16 bit pointer, data words are 2 bytes long and each word is
+//! followed by a CRC-8 byte (section 5.2 of the data sheet).
+
+#[cfg(feature = "device-info")]
+use crate::protocol::parse_device_info_string;
+use crate::protocol::parse_measurement_u16;
+#[cfg(feature = "cleaning")]
+use crate::AutoCleanInterval;
+#[cfg(not(feature = "no-float"))]
+use crate::{protocol::parse_measurement, Measurement};
+#[cfg(feature = "device-info")]
+use crate::{DeviceIdentity, DeviceInfo, InfoString};
+use crate::{
+    DeviceStatus, Error, Health, MeasurementFormat, MeasurementU16, NoDelay, Version,
+    WAKE_UP_TIME_MS,
+};
+use arrayvec::ArrayVec;
+use embedded_hal::delay::DelayNs;
+
+/// Fixed I2C address of the device
+const I2C_ADDRESS: u8 = 0x69;
+
+/// Available I2C commands (pointer addresses)
+#[repr(u16)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Command {
+    StartMeasurement = 0x0010,
+    StopMeasurement = 0x0104,
+    ReadDataReadyFlag = 0x0202,
+    ReadMeasuredValues = 0x0300,
+    #[cfg(feature = "cleaning")]
+    ReadWriteAutoCleaningInterval = 0x8004,
+    #[cfg(feature = "fan-clean")]
+    StartFanCleaning = 0x5607,
+    #[cfg(feature = "device-info")]
+    ReadProductType = 0xD002,
+    #[cfg(feature = "device-info")]
+    ReadSerialNumber = 0xD033,
+    #[cfg(feature = "device-info")]
+    ReadArticleCode = 0xD025,
+    Reset = 0xD304,
+    ReadVersion = 0xD100,
+    ReadDeviceStatusRegister = 0xD206,
+    ClearDeviceStatusRegister = 0xD210,
+    Sleep = 0x1001,
+    WakeUp = 0x1103,
+}
+
+/// CRC-8 implemented as per section 5.2 from spec
+///
+/// Polynomial 0x31, initialization 0xFF, no reflection, no final XOR
+fn compute_crc(data: &[u8]) -> u8 {
+    let mut crc: u8 = 0xFF;
+    for &byte in data.iter() {
+        crc ^= byte;
+        for _ in 0..8 {
+            if crc & 0x80 != 0 {
+                crc = (crc << 1) ^ 0x31;
+            } else {
+                crc <<= 1;
+            }
+        }
+    }
+    crc
+}
+
+/// Sps30 driver using the I2C interface
+///
+/// `DELAY` waits for the device to wake up, see [`Sps30I2c::with_auto_wake`].
+#[derive(Debug, Default)]
+pub struct Sps30I2c<I2C, DELAY = NoDelay> {
+    /// The concrete I2C device implementation.
+    i2c: I2C,
+    /// Device put in sleep mode by [`Sps30I2c::sleep`]
+    asleep: bool,
+    /// Delay used to wake the device up before commands sent while asleep, if enabled
+    wake_delay: Option<DELAY>,
+}
+
+impl<I2C, E> Sps30I2c<I2C>
+where
+    I2C: embedded_hal::i2c::I2c<Error = E>,
+{
+    /// Create new instance of the Sps30 device
+    pub fn new(i2c: I2C) -> Self {
+        Sps30I2c {
+            i2c,
+            asleep: false,
+            wake_delay: None,
+        }
+    }
+}
+
+impl<I2C, DELAY, E> Sps30I2c<I2C, DELAY>
+where
+    I2C: embedded_hal::i2c::I2c<Error = E>,
+    DELAY: DelayNs,
+{
+    /// Wake the device up before sending a command while it sleeps, disabled by default
+    ///
+    /// The driver tracks the mode set by [`Sps30I2c::sleep`] and [`Sps30I2c::wake_up`], so
+    /// commands sent while asleep don't fail but wake the device up first, then wait 5ms
+    /// with `delay` for it to accept commands.
+    pub fn with_auto_wake<D: DelayNs>(self, delay: D) -> Sps30I2c<I2C, D> {
+        Sps30I2c {
+            i2c: self.i2c,
+            asleep: self.asleep,
+            wake_delay: Some(delay),
+        }
+    }
+
+    /// Destroy the driver and return the I2C bus
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+
+    /// Write a command pointer followed by data words
+    ///
+    /// A CRC byte is appended after every 2 data bytes
+    fn write_command(&mut self, cmd: Command, data: &[u8]) -> Result<(), Error<E, E>> {
+        if self.asleep && self.wake_delay.is_some() && cmd != Command::WakeUp {
+            self.wake_up()?;
+            if let Some(delay) = self.wake_delay.as_mut() {
+                delay.delay_ms(WAKE_UP_TIME_MS);
+            }
+        }
+        let mut output = ArrayVec::<[u8; 8]>::new();
+        for item in &(cmd as u16).to_be_bytes() {
+            output.push(*item);
+        }
+        for word in data.chunks(2) {
+            for item in word {
+                output.push(*item);
+            }
+            output.push(compute_crc(word));
+        }
+        self.i2c.write(I2C_ADDRESS, &output).map_err(Error::I2cW)
+    }
+
+    /// Read words after setting the command pointer
+    ///
+    /// CRC of every word is verified and removed, `data` receives 2 bytes for every
+    /// 3 bytes read from the bus.
+    fn read_words(&mut self, cmd: Command, data: &mut [u8]) -> Result<(), Error<E, E>> {
+        self.write_command(cmd, &[])?;
+
+        let mut input = [0; 60];
+        let len = data.len() / 2 * 3;
+        self.i2c
+            .read(I2C_ADDRESS, &mut input[..len])
+            .map_err(Error::I2cR)?;
+
+        for (word, chunk) in data.chunks_mut(2).zip(input[..len].chunks(3)) {
+            if compute_crc(&chunk[..2]) != chunk[2] {
+                return Err(Error::ChecksumFailed);
+            }
+            word.copy_from_slice(&chunk[..2]);
+        }
+        Ok(())
+    }
+
+    /// Start measuring
+    ///
+    /// Measured values are produced in `format`, read them with `read_measurement` for
+    /// [`MeasurementFormat::Float`] or `read_measurement_u16` for
+    /// [`MeasurementFormat::UnsignedInteger16`].
+    pub fn start_measurement(&mut self, format: MeasurementFormat) -> Result<(), Error<E, E>> {
+        self.write_command(Command::StartMeasurement, &[format as u8, 0x00])
+    }
+
+    /// Stop measuring
+    pub fn stop_measurement(&mut self) -> Result<(), Error<E, E>> {
+        self.write_command(Command::StopMeasurement, &[])
+    }
+
+    /// Check if new measured values are available
+    pub fn read_data_ready(&mut self) -> Result<bool, Error<E, E>> {
+        let mut data = [0; 2];
+        self.read_words(Command::ReadDataReadyFlag, &mut data)?;
+        Ok(data[1] == 1)
+    }
+
+    /// Read measuring
+    ///
+    /// Returns `Error::EmptyResult` if no new measured values are available
+    #[cfg(not(feature = "no-float"))]
+    pub fn read_measurement(&mut self) -> Result<Measurement, Error<E, E>> {
+        if !self.read_data_ready()? {
+            return Err(Error::EmptyResult);
+        }
+
+        let mut data = [0; 40];
+        self.read_words(Command::ReadMeasuredValues, &mut data)?;
+        parse_measurement(&data)
+    }
+
+    /// Read measuring into `measurement`, left untouched on failure
+    ///
+    /// Same as `read_measurement`, without returning the measured values by copy
+    #[cfg(not(feature = "no-float"))]
+    pub fn read_measurement_into(
+        &mut self,
+        measurement: &mut Measurement,
+    ) -> Result<(), Error<E, E>> {
+        let bytes = self.read_measurement_raw()?;
+        measurement.set_from_bytes(&bytes);
+        Ok(())
+    }
+
+    /// Read measuring, returning the 40 bytes payload as sent by the device
+    ///
+    /// CRCs are checked but the values, big-endian IEEE754 floats, are not converted.
+    /// Returns `Error::EmptyResult` if no new measured values are available
+    pub fn read_measurement_raw(&mut self) -> Result<[u8; 40], Error<E, E>> {
+        if !self.read_data_ready()? {
+            return Err(Error::EmptyResult);
+        }
+
+        let mut data = [0; 40];
+        self.read_words(Command::ReadMeasuredValues, &mut data)?;
+        Ok(data)
+    }
+
+    /// Read measuring, for measurement started with [`MeasurementFormat::UnsignedInteger16`]
+    ///
+    /// Mass concentrations in μg/m³, number concentrations in #/cm³ and typical particle
+    /// size in nm, see [`MeasurementU16`].
+    /// Returns `Error::EmptyResult` if no new measured values are available
+    pub fn read_measurement_u16(&mut self) -> Result<MeasurementU16, Error<E, E>> {
+        if !self.read_data_ready()? {
+            return Err(Error::EmptyResult);
+        }
+
+        let mut data = [0; 20];
+        self.read_words(Command::ReadMeasuredValues, &mut data)?;
+        parse_measurement_u16(&data)
+    }
+
+    #[cfg(feature = "cleaning")]
+    /// Read cleaning interval
+    pub fn read_cleaning_interval(&mut self) -> Result<AutoCleanInterval, Error<E, E>> {
+        let mut data = [0; 4];
+        self.read_words(Command::ReadWriteAutoCleaningInterval, &mut data)?;
+        Ok(AutoCleanInterval::from_secs(u32::from_be_bytes(data)))
+    }
+
+    #[cfg(feature = "cleaning")]
+    /// Write cleaning interval
+    pub fn write_cleaning_interval(
+        &mut self,
+        interval: AutoCleanInterval,
+    ) -> Result<(), Error<E, E>> {
+        self.write_command(
+            Command::ReadWriteAutoCleaningInterval,
+            &interval.as_secs().to_be_bytes(),
+        )
+    }
+
+    #[cfg(feature = "cleaning")]
+    /// Disable the fan auto cleaning, writing a cleaning interval of 0
+    pub fn disable_auto_cleaning(&mut self) -> Result<(), Error<E, E>> {
+        self.write_cleaning_interval(AutoCleanInterval::DISABLED)
+    }
+
+    /// Read Device Status Register (firmware 2.2 or newer)
+    pub fn read_device_status(&mut self) -> Result<DeviceStatus, Error<E, E>> {
+        let mut data = [0; 4];
+        self.read_words(Command::ReadDeviceStatusRegister, &mut data)?;
+        Ok(DeviceStatus(u32::from_be_bytes(data)))
+    }
+
+    /// Clear Device Status Register (firmware 2.2 or newer)
+    pub fn clear_device_status(&mut self) -> Result<(), Error<E, E>> {
+        self.write_command(Command::ClearDeviceStatusRegister, &[])
+    }
+
+    /// Summarize the health of the device from its status register (firmware 2.2 or newer)
+    pub fn health_check(&mut self) -> Result<Health, Error<E, E>> {
+        self.read_device_status().map(Health::from)
+    }
+
+    #[cfg(feature = "fan-clean")]
+    /// Start fan cleaning
+    pub fn start_fan_cleaning(&mut self) -> Result<(), Error<E, E>> {
+        self.write_command(Command::StartFanCleaning, &[])
+    }
+
+    #[cfg(feature = "device-info")]
+    /// Get info
+    ///
+    /// Return the info as an ASCII string, without the trailing NULs
+    pub fn device_info(&mut self, info: DeviceInfo) -> Result<InfoString, Error<E, E>> {
+        let mut ret: [u8; 32] = [0; 32];
+        match info {
+            DeviceInfo::ProductName => self.read_words(Command::ReadProductType, &mut ret[..8])?,
+            DeviceInfo::ArticleCode => self.read_words(Command::ReadArticleCode, &mut ret)?,
+            DeviceInfo::SerialNumber => self.read_words(Command::ReadSerialNumber, &mut ret)?,
+        }
+        parse_device_info_string(&ret)
+    }
+
+    #[cfg(feature = "device-info")]
+    /// Read Product Name, Article Code and Serial Number
+    pub fn device_identity(&mut self) -> Result<DeviceIdentity, Error<E, E>> {
+        Ok(DeviceIdentity {
+            product_name: self.device_info(DeviceInfo::ProductName)?,
+            article_code: self.device_info(DeviceInfo::ArticleCode)?,
+            serial_number: self.device_info(DeviceInfo::SerialNumber)?,
+        })
+    }
+
+    /// Read firmware version
+    ///
+    /// Hardware revision and protocol version are only available over UART
+    pub fn firmware_version(&mut self) -> Result<Version, Error<E, E>> {
+        let mut data = [0; 2];
+        self.read_words(Command::ReadVersion, &mut data)?;
+        Ok(Version::new(data[0], data[1]))
+    }
+
+    /// Enter sleep mode (firmware 2.0 or newer)
+    ///
+    /// Only allowed in idle mode
+    pub fn sleep(&mut self) -> Result<(), Error<E, E>> {
+        self.write_command(Command::Sleep, &[])?;
+        self.asleep = true;
+        Ok(())
+    }
+
+    /// Wake-up from sleep mode (firmware 2.0 or newer)
+    ///
+    /// The first Wake-up command only activates the interface and is not acknowledged,
+    /// its error is ignored. The second one, sent right after within the 100ms window,
+    /// wakes up the device.
+    pub fn wake_up(&mut self) -> Result<(), Error<E, E>> {
+        let _ = self.write_command(Command::WakeUp, &[]);
+        self.write_command(Command::WakeUp, &[])?;
+        self.asleep = false;
+        Ok(())
+    }
+
+    /// Reset device
+    ///
+    /// After calling this function, caller must sleep before issuing more commands
+    pub fn reset(&mut self) -> Result<(), Error<E, E>> {
+        self.write_command(Command::Reset, &[])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compute_crc, Sps30I2c};
+    use core::cell::RefCell;
+    use embedded_hal::delay::DelayNs;
+    use embedded_hal::i2c::{ErrorKind, ErrorType, I2c, Operation};
+
+    /// Command pointer written or delay waited, in ns
+    #[derive(Debug, PartialEq)]
+    enum Event {
+        Write(u16),
+        Delay(u32),
+    }
+
+    type Log = RefCell<heapless::Vec<Event, 8>>;
+
+    /// Bus recording the command pointers written
+    struct Recorder<'a> {
+        log: &'a Log,
+    }
+
+    impl ErrorType for Recorder<'_> {
+        type Error = ErrorKind;
+    }
+
+    impl I2c for Recorder<'_> {
+        fn transaction(
+            &mut self,
+            _address: u8,
+            operations: &mut [Operation<'_>],
+        ) -> Result<(), ErrorKind> {
+            for operation in operations.iter_mut() {
+                if let Operation::Write(bytes) = operation {
+                    let pointer = u16::from_be_bytes([bytes[0], bytes[1]]);
+                    self.log
+                        .borrow_mut()
+                        .push(Event::Write(pointer))
+                        .map_err(|_| ErrorKind::Overrun)?;
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// Delay recording the time waited in the same log as the bus
+    struct Waiter<'a> {
+        log: &'a Log,
+    }
+
+    impl DelayNs for Waiter<'_> {
+        fn delay_ns(&mut self, ns: u32) {
+            self.log.borrow_mut().push(Event::Delay(ns)).unwrap();
+        }
+    }
+
+    #[test]
+    fn crc_matches_datasheet_example() {
+        assert_eq!(compute_crc(&[0xBE, 0xEF]), 0x92);
+    }
+
+    #[test]
+    fn auto_wake_before_command() {
+        let log = Log::default();
+        let mut sensor = Sps30I2c::new(Recorder { log: &log }).with_auto_wake(Waiter { log: &log });
+        sensor.sleep().unwrap();
+        sensor.stop_measurement().unwrap();
+        sensor.stop_measurement().unwrap();
+        assert_eq!(
+            *log.borrow(),
+            [
+                Event::Write(0x1001),
+                Event::Write(0x1103),
+                Event::Write(0x1103),
+                Event::Delay(5_000_000),
+                Event::Write(0x0104),
+                Event::Write(0x0104),
+            ]
+        );
+    }
+}
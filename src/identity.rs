@@ -0,0 +1,20 @@
+//! Device identification
+
+/// Device Information string, ASCII without the trailing NULs
+pub type InfoString = heapless::String<32>;
+
+/// Product Name reported by the SPS30
+pub const SPS30_PRODUCT_NAME: &str = "00080000";
+
+/// All Device Information, as returned by `device_identity`
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DeviceIdentity {
+    /// Product Name
+    pub product_name: InfoString,
+    /// Article Code
+    pub article_code: InfoString,
+    /// Serial Number
+    pub serial_number: InfoString,
+}
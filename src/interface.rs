@@ -0,0 +1,331 @@
+//! Driver-independent command set, for writing application logic against a trait
+
+#[cfg(feature = "cleaning")]
+use crate::AutoCleanInterval;
+#[cfg(not(feature = "no-float"))]
+use crate::Measurement;
+use crate::{
+    AnySps30, DeviceError, DeviceStatus, Error, Health, MeasurementFormat, MeasurementU16,
+    Sps30I2c, Transport,
+};
+#[cfg(feature = "device-info")]
+use crate::{DeviceInfo, InfoString};
+use embedded_hal::delay::DelayNs;
+
+/// Commands of the SPS30, whatever the interface and driver
+///
+/// Implemented by [`Sps30I2c`] and [`DynSps30`]. Write application logic generic over this
+/// trait to test it with a mock, e.g. generated by `mockall`, instead of a serial or I2C
+/// mock.
+pub trait Sps30Interface {
+    /// Error returned by all commands
+    type Error;
+
+    /// Start measuring, producing values in `format`
+    fn start_measurement(&mut self, format: MeasurementFormat) -> Result<(), Self::Error>;
+
+    /// Stop measuring
+    fn stop_measurement(&mut self) -> Result<(), Self::Error>;
+
+    /// Read measured values in float format, [`Error::EmptyResult`] if none is new
+    #[cfg(not(feature = "no-float"))]
+    fn read_measurement(&mut self) -> Result<Measurement, Self::Error>;
+
+    /// Read measured values in integer format, [`Error::EmptyResult`] if none is new
+    fn read_measurement_u16(&mut self) -> Result<MeasurementU16, Self::Error>;
+
+    #[cfg(feature = "fan-clean")]
+    /// Start a fan cleaning, only while measuring
+    fn start_fan_cleaning(&mut self) -> Result<(), Self::Error>;
+
+    #[cfg(feature = "cleaning")]
+    /// Read the auto cleaning interval
+    fn read_cleaning_interval(&mut self) -> Result<AutoCleanInterval, Self::Error>;
+
+    #[cfg(feature = "cleaning")]
+    /// Write the auto cleaning interval
+    fn write_cleaning_interval(&mut self, interval: AutoCleanInterval) -> Result<(), Self::Error>;
+
+    #[cfg(feature = "device-info")]
+    /// Read one of the Device Information strings
+    fn device_info(&mut self, info: DeviceInfo) -> Result<InfoString, Self::Error>;
+
+    /// Read the Device Status Register, without clearing it (firmware 2.2 or newer)
+    fn read_device_status(&mut self) -> Result<DeviceStatus, Self::Error>;
+
+    /// Summarize the Device Status Register (firmware 2.2 or newer)
+    fn health_check(&mut self) -> Result<Health, Self::Error> {
+        self.read_device_status().map(Health::from)
+    }
+
+    /// Enter sleep mode (firmware 2.0 or newer), only from idle mode
+    fn sleep(&mut self) -> Result<(), Self::Error>;
+
+    /// Wake-up from sleep mode (firmware 2.0 or newer)
+    fn wake_up(&mut self) -> Result<(), Self::Error>;
+
+    /// Reset the device, back to idle mode
+    fn reset(&mut self) -> Result<(), Self::Error>;
+}
+
+impl<I2C, DELAY, E> Sps30Interface for Sps30I2c<I2C, DELAY>
+where
+    I2C: embedded_hal::i2c::I2c<Error = E>,
+    DELAY: DelayNs,
+{
+    type Error = Error<E, E>;
+
+    fn start_measurement(&mut self, format: MeasurementFormat) -> Result<(), Self::Error> {
+        Sps30I2c::start_measurement(self, format)
+    }
+
+    fn stop_measurement(&mut self) -> Result<(), Self::Error> {
+        Sps30I2c::stop_measurement(self)
+    }
+
+    #[cfg(not(feature = "no-float"))]
+    fn read_measurement(&mut self) -> Result<Measurement, Self::Error> {
+        Sps30I2c::read_measurement(self)
+    }
+
+    fn read_measurement_u16(&mut self) -> Result<MeasurementU16, Self::Error> {
+        Sps30I2c::read_measurement_u16(self)
+    }
+
+    #[cfg(feature = "fan-clean")]
+    fn start_fan_cleaning(&mut self) -> Result<(), Self::Error> {
+        Sps30I2c::start_fan_cleaning(self)
+    }
+
+    #[cfg(feature = "cleaning")]
+    fn read_cleaning_interval(&mut self) -> Result<AutoCleanInterval, Self::Error> {
+        Sps30I2c::read_cleaning_interval(self)
+    }
+
+    #[cfg(feature = "cleaning")]
+    fn write_cleaning_interval(&mut self, interval: AutoCleanInterval) -> Result<(), Self::Error> {
+        Sps30I2c::write_cleaning_interval(self, interval)
+    }
+
+    #[cfg(feature = "device-info")]
+    fn device_info(&mut self, info: DeviceInfo) -> Result<InfoString, Self::Error> {
+        Sps30I2c::device_info(self, info)
+    }
+
+    fn read_device_status(&mut self) -> Result<DeviceStatus, Self::Error> {
+        Sps30I2c::read_device_status(self)
+    }
+
+    fn sleep(&mut self) -> Result<(), Self::Error> {
+        Sps30I2c::sleep(self)
+    }
+
+    fn wake_up(&mut self) -> Result<(), Self::Error> {
+        Sps30I2c::wake_up(self)
+    }
+
+    fn reset(&mut self) -> Result<(), Self::Error> {
+        Sps30I2c::reset(self)
+    }
+}
+
+/// UART driver tracking its mode at runtime, instead of in its type
+///
+/// Commands not allowed in the current mode fail with
+/// [`DeviceError::NotAllowedInState`], as the device would answer, without being sent.
+/// Failed mode changes leave the driver in the mode it was in.
+#[derive(Debug)]
+pub struct DynSps30<SERIAL, DELAY> {
+    /// Driver, `None` only while changing mode
+    sps30: Option<AnySps30<SERIAL, DELAY>>,
+}
+
+/// Driver in the wrong mode for the command
+fn not_allowed<T, E, F>() -> Result<T, Error<E, F>> {
+    Err(Error::StatusError(DeviceError::NotAllowedInState))
+}
+
+impl<SERIAL, DELAY> DynSps30<SERIAL, DELAY> {
+    /// Track the mode of `sps30` at runtime
+    pub fn new(sps30: impl Into<AnySps30<SERIAL, DELAY>>) -> Self {
+        DynSps30 {
+            sps30: Some(sps30.into()),
+        }
+    }
+
+    /// Destroy the wrapper and return the driver, in the mode it reached
+    pub fn release(mut self) -> AnySps30<SERIAL, DELAY> {
+        self.sps30.take().expect("driver is always put back")
+    }
+
+    /// Borrow the driver, to check its current mode
+    pub fn get(&self) -> &AnySps30<SERIAL, DELAY> {
+        self.sps30.as_ref().expect("driver is always put back")
+    }
+
+    fn get_mut(&mut self) -> &mut AnySps30<SERIAL, DELAY> {
+        self.sps30.as_mut().expect("driver is always put back")
+    }
+
+    /// Run the mode change `f`, putting back the driver it returns
+    fn change<E>(
+        &mut self,
+        f: impl FnOnce(AnySps30<SERIAL, DELAY>) -> (Result<(), E>, AnySps30<SERIAL, DELAY>),
+    ) -> Result<(), E> {
+        let sps30 = self.sps30.take().expect("driver is always put back");
+        let (result, sps30) = f(sps30);
+        self.sps30 = Some(sps30);
+        result
+    }
+}
+
+/// Outcome of a mode change, with the driver in the mode reached
+fn settle<OK, ERR, SERIAL, DELAY, E>(
+    result: Result<OK, (E, ERR)>,
+) -> (Result<(), E>, AnySps30<SERIAL, DELAY>)
+where
+    OK: Into<AnySps30<SERIAL, DELAY>>,
+    ERR: Into<AnySps30<SERIAL, DELAY>>,
+{
+    match result {
+        Ok(sps30) => (Ok(()), sps30.into()),
+        Err((e, sps30)) => (Err(e), sps30.into()),
+    }
+}
+
+impl<SERIAL, DELAY, E, F> Sps30Interface for DynSps30<SERIAL, DELAY>
+where
+    SERIAL: Transport<WriteError = E, ReadError = F>,
+    DELAY: DelayNs,
+{
+    type Error = Error<E, F>;
+
+    fn start_measurement(&mut self, format: MeasurementFormat) -> Result<(), Self::Error> {
+        self.change(|sps30| match sps30 {
+            AnySps30::Idle(idle) => settle(idle.start_measurement(format)),
+            other => (not_allowed(), other),
+        })
+    }
+
+    fn stop_measurement(&mut self) -> Result<(), Self::Error> {
+        self.change(|sps30| match sps30 {
+            AnySps30::Measuring(measuring) => settle(measuring.stop_measurement()),
+            other => (not_allowed(), other),
+        })
+    }
+
+    #[cfg(not(feature = "no-float"))]
+    fn read_measurement(&mut self) -> Result<Measurement, Self::Error> {
+        match self.get_mut() {
+            AnySps30::Measuring(measuring) => measuring.read_measurement(),
+            _ => not_allowed(),
+        }
+    }
+
+    fn read_measurement_u16(&mut self) -> Result<MeasurementU16, Self::Error> {
+        match self.get_mut() {
+            AnySps30::Measuring(measuring) => measuring.read_measurement_u16(),
+            _ => not_allowed(),
+        }
+    }
+
+    #[cfg(feature = "fan-clean")]
+    fn start_fan_cleaning(&mut self) -> Result<(), Self::Error> {
+        match self.get_mut() {
+            AnySps30::Measuring(measuring) => measuring.start_fan_cleaning(),
+            _ => not_allowed(),
+        }
+    }
+
+    #[cfg(feature = "cleaning")]
+    fn read_cleaning_interval(&mut self) -> Result<AutoCleanInterval, Self::Error> {
+        match self.get_mut() {
+            AnySps30::Idle(idle) => idle.read_cleaning_interval(),
+            AnySps30::Measuring(measuring) => measuring.read_cleaning_interval(),
+            AnySps30::Sleeping(_) => not_allowed(),
+        }
+    }
+
+    #[cfg(feature = "cleaning")]
+    fn write_cleaning_interval(&mut self, interval: AutoCleanInterval) -> Result<(), Self::Error> {
+        match self.get_mut() {
+            AnySps30::Idle(idle) => idle.write_cleaning_interval(interval),
+            AnySps30::Measuring(measuring) => measuring.write_cleaning_interval(interval),
+            AnySps30::Sleeping(_) => not_allowed(),
+        }
+    }
+
+    #[cfg(feature = "device-info")]
+    fn device_info(&mut self, info: DeviceInfo) -> Result<InfoString, Self::Error> {
+        match self.get_mut() {
+            AnySps30::Idle(idle) => idle.device_info(info),
+            AnySps30::Measuring(measuring) => measuring.device_info(info),
+            AnySps30::Sleeping(_) => not_allowed(),
+        }
+    }
+
+    fn read_device_status(&mut self) -> Result<DeviceStatus, Self::Error> {
+        match self.get_mut() {
+            AnySps30::Idle(idle) => idle.read_device_status(false),
+            AnySps30::Measuring(measuring) => measuring.read_device_status(false),
+            AnySps30::Sleeping(_) => not_allowed(),
+        }
+    }
+
+    fn sleep(&mut self) -> Result<(), Self::Error> {
+        self.change(|sps30| match sps30 {
+            AnySps30::Idle(idle) => settle(idle.sleep()),
+            other => (not_allowed(), other),
+        })
+    }
+
+    fn wake_up(&mut self) -> Result<(), Self::Error> {
+        self.change(|sps30| match sps30 {
+            AnySps30::Sleeping(sleeping) => settle(sleeping.wake_up()),
+            other => (not_allowed(), other),
+        })
+    }
+
+    fn reset(&mut self) -> Result<(), Self::Error> {
+        self.change(|sps30| match sps30 {
+            AnySps30::Idle(idle) => settle(idle.reset()),
+            AnySps30::Measuring(measuring) => settle(measuring.reset()),
+            other => (not_allowed(), other),
+        })
+    }
+}
+
+#[cfg(all(test, not(feature = "no-float")))]
+mod tests {
+    use super::*;
+    use crate::replay::Replay;
+    use crate::Sps30;
+
+    /// Application logic written against the trait
+    fn measure_once<S: Sps30Interface>(sensor: &mut S) -> Result<Measurement, S::Error> {
+        sensor.start_measurement(MeasurementFormat::Float)?;
+        let measurement = sensor.read_measurement();
+        sensor.stop_measurement()?;
+        measurement
+    }
+
+    #[test]
+    fn mode_is_tracked_at_runtime() {
+        // Start Measurement, Read Measured Values with no new values, Stop Measurement
+        let rx = &[
+            0x7E, 0x00, 0x00, 0x00, 0x00, 0xFF, 0x7E, 0x7E, 0x00, 0x03, 0x00, 0x00, 0xFC, 0x7E,
+            0x7E, 0x00, 0x01, 0x00, 0x00, 0xFE, 0x7E,
+        ];
+        let mut sensor = DynSps30::new(Sps30::new(Replay { rx }));
+        assert!(matches!(
+            sensor.read_measurement(),
+            Err(Error::StatusError(DeviceError::NotAllowedInState))
+        ));
+        assert!(matches!(measure_once(&mut sensor), Err(Error::EmptyResult)));
+        assert!(matches!(sensor.get(), AnySps30::Idle(_)));
+
+        // Nothing left to answer, the driver stays idle
+        assert!(sensor.sleep().is_err());
+        assert!(matches!(sensor.release(), AnySps30::Idle(_)));
+    }
+}
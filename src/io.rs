@@ -0,0 +1,45 @@
+//! Support for byte streams implementing the blocking `embedded-io` traits
+//!
+//! Enabled with the `embedded-io` feature.
+
+use crate::Transport;
+use embedded_io::{Read, Write};
+
+/// Wrapper making an `embedded-io` byte stream usable by the [`Sps30`](crate::Sps30) driver
+///
+/// Wrap anything implementing `embedded_io::{Read, Write}`, e.g. a HAL UART or a std port
+/// through `embedded-io-adapters`, before passing it to [`Sps30::new`](crate::Sps30::new).
+///
+/// `read` blocks until at least one byte is available. A read returning no bytes (end of
+/// stream) is reported as `nb::Error::WouldBlock`.
+#[derive(Debug, Default)]
+pub struct Io<T>(pub T);
+
+impl<T> Io<T> {
+    /// Return the wrapped byte stream
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T, E> Transport for Io<T>
+where
+    T: Read<Error = E> + Write<Error = E>,
+{
+    type WriteError = E;
+    type ReadError = E;
+
+    fn write_all(&mut self, data: &[u8]) -> Result<(), E> {
+        self.0.write_all(data)?;
+        self.0.flush()
+    }
+
+    fn read_byte(&mut self) -> nb::Result<u8, E> {
+        let mut byte = [0; 1];
+        match self.0.read(&mut byte) {
+            Ok(0) => Err(nb::Error::WouldBlock),
+            Ok(_) => Ok(byte[0]),
+            Err(e) => Err(nb::Error::Other(e)),
+        }
+    }
+}
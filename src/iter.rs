@@ -0,0 +1,100 @@
+//! Periodic reading of measured values
+
+use crate::{Error, Measurement, Measuring, Sps30, Transport};
+use embedded_hal::delay::DelayNs;
+
+/// Minimum time between two reads, the device produces new values every second, in ms
+pub(crate) const MIN_INTERVAL_MS: u32 = 1000;
+
+/// Time between two reads while no new values are available, in ms
+pub(crate) const EMPTY_POLL_MS: u32 = 100;
+
+/// Longest wait for new values once measuring, in ms
+///
+/// The device produces new values every second: none after this long means it stopped
+/// measuring, e.g. after a reset, or its fan is blocked.
+pub(crate) const MAX_EMPTY_WAIT_MS: u32 = 10_000;
+
+/// Call `read` every [`EMPTY_POLL_MS`] while it returns an empty result, failing with
+/// [`Error::Timeout`] after `max_wait_ms`
+pub(crate) fn wait_for_values<T, E, F, D: DelayNs>(
+    delay: &mut D,
+    max_wait_ms: u32,
+    mut read: impl FnMut() -> Result<T, Error<E, F>>,
+) -> Result<T, Error<E, F>> {
+    let mut waited_ms = 0;
+    loop {
+        match read() {
+            Err(Error::EmptyResult) if waited_ms < max_wait_ms => {
+                delay.delay_ms(EMPTY_POLL_MS);
+                waited_ms += EMPTY_POLL_MS;
+            }
+            Err(Error::EmptyResult) => return Err(Error::Timeout),
+            result => return result,
+        }
+    }
+}
+
+/// Iterator over measured values, returned by [`Sps30::measurements`]
+///
+/// Never ends, use e.g. [`Iterator::take`] to stop after a number of measurements.
+pub struct Measurements<'a, SERIAL, DELAY, D>
+where
+    SERIAL: Transport,
+    DELAY: DelayNs,
+    D: DelayNs,
+{
+    /// Driver values are read from
+    sps30: &'a mut Sps30<SERIAL, Measuring, DELAY>,
+    /// Delay used between reads
+    delay: D,
+    /// Time between two measurements, in ms
+    interval_ms: u32,
+    /// No measurement returned yet
+    first: bool,
+}
+
+impl<'a, SERIAL, DELAY, D> Measurements<'a, SERIAL, DELAY, D>
+where
+    SERIAL: Transport,
+    DELAY: DelayNs,
+    D: DelayNs,
+{
+    /// Read every `interval_ms`, at least [`MIN_INTERVAL_MS`]
+    pub(crate) fn new(
+        sps30: &'a mut Sps30<SERIAL, Measuring, DELAY>,
+        delay: D,
+        interval_ms: u32,
+    ) -> Self {
+        Measurements {
+            sps30,
+            delay,
+            interval_ms: interval_ms.max(MIN_INTERVAL_MS),
+            first: true,
+        }
+    }
+}
+
+impl<SERIAL, DELAY, D, E, F> Iterator for Measurements<'_, SERIAL, DELAY, D>
+where
+    SERIAL: Transport<WriteError = E, ReadError = F>,
+    DELAY: DelayNs,
+    D: DelayNs,
+{
+    type Item = Result<Measurement, Error<E, F>>;
+
+    /// Wait for the interval, then read until new values are available
+    ///
+    /// The first measurement is read right away. Yields [`Error::Timeout`] if no new values
+    /// are available after 10s.
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.first {
+            self.delay.delay_ms(self.interval_ms);
+        }
+        self.first = false;
+        Some(
+            self.sps30
+                .wait_for_first_measurement(&mut self.delay, MAX_EMPTY_WAIT_MS),
+        )
+    }
+}
@@ -0,0 +1,98 @@
+//! Support for peripherals implementing the embedded-hal 0.2 traits
+//!
+//! Enabled with the `embedded-hal-02` feature.
+
+use crate::{Split, Transport};
+use embedded_hal::i2c::{ErrorKind, ErrorType, I2c, Operation};
+use embedded_hal_02::blocking::i2c as i2c02;
+use embedded_hal_02::blocking::serial as blocking02;
+use embedded_hal_02::serial as serial02;
+
+/// Wrapper making an embedded-hal 0.2 peripheral usable by the drivers
+///
+/// Wrap an UART implementing the 0.2 serial traits before passing it to
+/// [`Sps30::new`](crate::Sps30::new), or an I2C bus implementing the 0.2 blocking
+/// I2C traits before passing it to [`Sps30I2c::new`](crate::Sps30I2c::new).
+#[derive(Debug, Default)]
+pub struct Legacy<T>(pub T);
+
+impl<T> Legacy<T> {
+    /// Return the wrapped peripheral
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<SERIAL, E, F> Transport for Legacy<SERIAL>
+where
+    SERIAL: blocking02::Write<u8, Error = E> + serial02::Read<u8, Error = F>,
+{
+    type WriteError = E;
+    type ReadError = F;
+
+    fn write_all(&mut self, data: &[u8]) -> Result<(), E> {
+        self.0.bwrite_all(data)
+    }
+
+    fn read_byte(&mut self) -> nb::Result<u8, F> {
+        self.0.read()
+    }
+}
+
+/// Split embedded-hal 0.2 serial halves, wrap them as `Legacy(Split::new(tx, rx))`
+impl<TX, RX, E, F> Transport for Legacy<Split<TX, RX>>
+where
+    TX: blocking02::Write<u8, Error = E>,
+    RX: serial02::Read<u8, Error = F>,
+{
+    type WriteError = E;
+    type ReadError = F;
+
+    fn write_all(&mut self, data: &[u8]) -> Result<(), E> {
+        self.0.tx.bwrite_all(data)
+    }
+
+    fn read_byte(&mut self) -> nb::Result<u8, F> {
+        self.0.rx.read()
+    }
+}
+
+/// Error of an embedded-hal 0.2 I2C bus wrapped in [`Legacy`]
+#[derive(Debug)]
+pub struct LegacyError<E>(pub E);
+
+impl<E: core::fmt::Debug> embedded_hal::i2c::Error for LegacyError<E> {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Other
+    }
+}
+
+impl<I2C, E> ErrorType for Legacy<I2C>
+where
+    I2C: i2c02::Write<Error = E> + i2c02::Read<Error = E>,
+    E: core::fmt::Debug,
+{
+    type Error = LegacyError<E>;
+}
+
+/// Operations are executed one after the other, each one as a separate transfer
+impl<I2C, E> I2c for Legacy<I2C>
+where
+    I2C: i2c02::Write<Error = E> + i2c02::Read<Error = E>,
+    E: core::fmt::Debug,
+{
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        for operation in operations.iter_mut() {
+            match operation {
+                Operation::Read(buffer) => self.0.read(address, buffer),
+                Operation::Write(bytes) => self.0.write(address, bytes),
+            }
+            .map_err(LegacyError)?;
+        }
+        Ok(())
+    }
+}
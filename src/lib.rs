@@ -1,7 +1,69 @@
 //! A platform agnostic driver to interface the Sensirion SPS30 (UART Particulate Matter Sensor)
 //!
 //! This driver was built using [`embedded-hal`] traits.
-//!  
+//!
+//! Both interfaces of the sensor are supported: [`Sps30`] talks SHDLC over UART,
+//! [`Sps30I2c`] talks to the sensor over I2C. The UART protocol itself is available,
+//! independent of any transport, in the [`protocol`] module. Measured values can be
+//! smoothed with the filters of the [`filter`] module, and turned into an Air Quality
+//! Index with the [`aqi`] module.
+//!
+//! # Features
+//!
+//! - `embedded-hal-nb` (default): use serial ports implementing the embedded-hal 1.0
+//!   `embedded-hal-nb` serial traits directly with [`Sps30`].
+//! - `cleaning` (default): read and write the auto cleaning interval.
+//! - `device-info` (default): read the Device Information strings, and check the link
+//!   with `probe` and `self_test`.
+//! - `fan-clean` (default): start fan cleaning.
+//! - `embedded-hal-02`: use embedded-hal 0.2 peripherals by wrapping them in `Legacy`.
+//! - `embedded-io`: use blocking `embedded-io` byte streams by wrapping them in `Io`.
+//! - `async`: `Sps30Async`, an async UART driver built on `embedded-io-async`, and its
+//!   `Stream` of measured values paced with an `embedded-hal-async` delay.
+//! - `embedded-dma`: use serial ports only exposing DMA transfers by implementing
+//!   `DmaSerial` and wrapping them in `Dma`, transferring through `embedded-dma` buffers.
+//! - `embassy`: `Sps30Async` measurement tasks publishing measured values to an
+//!   `embassy-sync` `Channel` or `Watch`.
+//! - `tokio-serial`: `TokioSerial`, a `tokio-serial` port with read timeouts for
+//!   `Sps30Async`, for async Linux daemons.
+//! - `critical-section`: `RxQueue`, a receive queue filled from a UART RX interrupt and
+//!   read by [`Sps30`] from the main context.
+//! - `defmt`: `defmt::Format` for [`Error`], [`Measurement`] and the other public types.
+//! - `std`: `std::error::Error` for [`Error`], and `Reconnecting`, a transport reopening
+//!   its port after a disconnection.
+//! - `serialport`: use desktop and single-board computer serial ports by wrapping them in
+//!   `SerialPort`, `SerialPort::open` sets them up for the SPS30.
+//! - `linux`: `Sps30::open_linux`, creating a driver from a serial device path.
+//! - `rppal`: `Sps30::open_rppal`, creating a driver on a Raspberry Pi UART.
+//! - `tracing`: a `tracing` span around every command, recording the command, its payload
+//!   length, the retries and the outcome.
+//! - `debug-frames`: raw MOSI and MISO Frames logged with `defmt`, if enabled, and handed
+//!   to [`Config`]'s `frame_formatter`. Compiled out when disabled.
+//! - `log`: trace-level hex dumps of MOSI and MISO Frames and debug-level command outcomes
+//!   through the `log` facade.
+//! - `serde`: `Serialize` and `Deserialize` for [`Measurement`], [`Versions`] and the
+//!   other plain data types.
+//! - `fixed`: measured values as `fixed` fixed-point numbers, see `fixed_point`.
+//! - `no-float`: float-free build, for targets without FPU. The float measurement reads
+//!   and what is built on them (`measurements`, `Sampler`, `DutyCycled`, the `embassy`
+//!   tasks) are compiled out; read [`MeasurementU16`] or raw bytes instead, and use the
+//!   `_u16` helpers of [`aqi`] and [`alerts::AlertsU16`].
+//! - `uom`: measured values and cleaning interval as `uom` quantities, see `units`.
+//! - `simulator`: `SimulatedSps30`, a serial port behaving like a SPS30, for host-side
+//!   tests without hardware.
+//! - `fugit`: [`AutoCleanInterval`] conversions to and from `fugit` durations.
+//! - `capture`: `Recorder`, capturing every frame sent and received into a ring buffer,
+//!   and `Replayer`, feeding captured frames back to the driver.
+//! - `test-vectors`: MOSI and MISO Frames of every command in `test_vectors`, for
+//!   validating custom transports.
+//! - `sensirion-hdlc`: [`SpecialChars`] and [`HDLCError`] are the `sensirion-hdlc` crate
+//!   types, for code sharing them with it. Frames are encoded and decoded by the driver
+//!   either way.
+//!
+//! Disabling `cleaning`, `device-info` or `fan-clean` compiles their commands out, for
+//! firmwares short on flash; starting, stopping and reading measurement are always there.
+//!
+//! [`embedded-hal`]: https://crates.io/crates/embedded-hal
 //!
 //! # References
 //!
@@ -11,16 +73,161 @@
 
 #![deny(unsafe_code)]
 #![deny(missing_docs)]
+// Mode transitions give the driver back by value along with the error, see `Transition`
+#![allow(clippy::result_large_err)]
 #![no_std]
 
+#[cfg(feature = "std")]
+extern crate std;
+
+pub use crate::hdlc::{HDLCError, SpecialChars};
 use arrayvec::ArrayVec;
 use core::convert::From;
-use ieee754::*;
+use core::marker::PhantomData;
+use embedded_hal::delay::DelayNs;
 use nb::Error as nbError;
-use sensirion_hdlc::{decode, encode, HDLCError, SpecialChars};
+
+pub mod alerts;
+pub mod aqi;
+#[cfg(feature = "async")]
+mod asynch;
+mod calibration;
+#[cfg(feature = "capture")]
+mod capture;
+mod commands;
+mod config;
+#[cfg(feature = "embedded-dma")]
+mod dma;
+mod duty;
+#[cfg(all(feature = "embassy", not(feature = "no-float")))]
+mod embassy;
+pub mod filter;
+#[cfg(feature = "fixed")]
+pub mod fixed_point;
+mod hdlc;
+mod i2c;
+mod identity;
+mod interface;
+#[cfg(feature = "embedded-io")]
+mod io;
+#[cfg(not(feature = "no-float"))]
+mod iter;
+#[cfg(feature = "embedded-hal-02")]
+mod legacy;
+mod maintenance;
+mod measurement;
+mod observer;
+pub mod protocol;
+#[cfg(feature = "rppal")]
+mod raspberry;
+#[cfg(feature = "std")]
+mod reconnect;
+#[cfg(test)]
+mod replay;
+mod resumable;
+#[cfg(feature = "critical-section")]
+mod rx_queue;
+#[cfg(not(feature = "no-float"))]
+mod sampler;
+mod self_test;
+mod session;
+#[cfg(feature = "simulator")]
+mod simulator;
+mod sniffer;
+mod stats;
+mod status;
+#[cfg(feature = "serialport")]
+mod std_serial;
+#[cfg(feature = "test-vectors")]
+pub mod test_vectors;
+#[cfg(feature = "tokio-serial")]
+mod tokio_port;
+mod transport;
+#[cfg(feature = "uom")]
+pub mod units;
+mod version;
+pub mod who;
+
+#[cfg(feature = "async")]
+pub use crate::asynch::Sps30Async;
+pub use crate::calibration::Calibration;
+#[cfg(feature = "capture")]
+pub use crate::capture::{Record, Recorder, Replayer};
+use crate::commands::Command;
+pub use crate::config::{Config, Sps30Builder, WaitStrategy};
+#[cfg(feature = "embedded-dma")]
+pub use crate::dma::{Dma, DmaBuffer, DmaError, DmaSerial};
+pub use crate::duty::AnySps30;
+#[cfg(not(feature = "no-float"))]
+pub use crate::duty::{DutyCycle, DutyCycled};
+pub use crate::i2c::Sps30I2c;
+pub use crate::identity::{DeviceIdentity, InfoString, SPS30_PRODUCT_NAME};
+pub use crate::interface::{DynSps30, Sps30Interface};
+#[cfg(feature = "embedded-io")]
+pub use crate::io::Io;
+#[cfg(not(feature = "no-float"))]
+pub use crate::iter::Measurements;
+#[cfg(feature = "embedded-hal-02")]
+pub use crate::legacy::{Legacy, LegacyError};
+pub use crate::maintenance::{AutoCleanInterval, CleaningSchedule};
+pub use crate::measurement::{
+    Measurement, MeasurementFormat, MeasurementKind, MeasurementU16, Plausibility,
+};
+pub use crate::observer::FrameObserver;
+use crate::protocol::FrameSync;
+pub use crate::protocol::Validation;
+pub use crate::protocol::{Direction, ResponseFrame};
+#[cfg(feature = "std")]
+pub use crate::reconnect::Reconnecting;
+pub use crate::resumable::PendingCommand;
+#[cfg(feature = "critical-section")]
+pub use crate::rx_queue::RxQueue;
+#[cfg(not(feature = "no-float"))]
+pub use crate::sampler::Sampler;
+pub use crate::self_test::SelfTest;
+pub use crate::session::MeasurementSession;
+#[cfg(feature = "simulator")]
+pub use crate::simulator::{Fault, SimulatedSps30};
+pub use crate::sniffer::{SniffedFrame, Sniffer};
+pub use crate::stats::LinkStats;
+pub use crate::status::{DeviceStatus, Health};
+#[cfg(feature = "serialport")]
+pub use crate::std_serial::SerialPort;
+#[cfg(feature = "tokio-serial")]
+pub use crate::tokio_port::TokioSerial;
+pub use crate::transport::{Split, Transport};
+pub use crate::version::{Version, Versions};
+
+/// Baud rate of the UART interface, 8 data bits, no parity, 1 stop bit
+pub const BAUD_RATE: u32 = 115_200;
 
 /// Max characters to read for a frame detection
-const MAX_BUFFER: usize = 600;
+const MAX_BUFFER: usize = protocol::ENCODED_FRAME_SIZE;
+
+/// Maximum response time assumed for raw commands, in ms
+const RAW_COMMAND_RESPONSE_TIME_MS: u32 = 20;
+
+/// Time between two reads while waiting for response bytes, in μs
+const POLL_INTERVAL_US: u32 = 100;
+
+/// Time the device needs after a reset before accepting commands again, in ms
+const RESET_STARTUP_TIME_MS: u32 = 100;
+
+/// Time the device needs after a Wake-up before accepting commands again, in ms
+const WAKE_UP_TIME_MS: u32 = 5;
+
+#[cfg(feature = "fan-clean")]
+/// Duration of a fan cleaning, in ms
+const FAN_CLEANING_MS: u32 = 10_000;
+
+/// Oldest firmware supporting sleep, wake-up and the integer measurement format
+const FIRMWARE_2_0: Version = Version::new(2, 0);
+
+/// First firmware with the Read Device Status Register command
+const FIRMWARE_2_2: Version = Version::new(2, 2);
+
+/// Default margin added to the maximum response time of commands, in ms
+const DEFAULT_TIMEOUT_MARGIN_MS: u32 = 10;
 
 /// Errors for this crate
 #[derive(Debug)]
@@ -31,18 +238,244 @@ pub enum Error<E, F> {
     SerialW(E),
     /// SHDLC decode error
     SHDLC(HDLCError),
+    /// SHDLC encode error
+    Encode(HDLCError),
     /// No valid frame read.
     ///
-    /// Input function read more than 600 characters without seeing two 0x7e
+    /// More than [`Config::max_frame_size`] characters (100 by default) were read without
+    /// seeing two 0x7e
     InvalidFrame,
     /// Result is empty
     EmptyResult,
-    /// Checksum failed, after shdlc decode
+    /// Checksum failed, after shdlc decode (UART) or CRC mismatch (I2C)
     ChecksumFailed,
     /// Response is for another CommandType
     InvalidRespose,
     /// Device returned an Error (State field of MISO Frame is not 0)
-    StatusError,
+    StatusError(DeviceError),
+    /// I2C bus write error
+    I2cW(E),
+    /// I2C bus read error
+    I2cR(F),
+    /// No response within the deadline of the command
+    Timeout,
+    /// Frame does not fit in the buffer, e.g. too much data for a MOSI Frame
+    BufferTooSmall,
+    /// No SPS30 answered the probe, check wiring and power
+    NotDetected,
+    /// A device answered the probe, but its Product Name is not the one expected
+    UnexpectedProduct(InfoString),
+    /// Measured values out of the datasheet ranges, see [`Config::reject_implausible`]
+    Implausible(Plausibility),
+    /// Command not supported by the firmware of the device
+    UnsupportedFirmware {
+        /// Oldest firmware version supporting the command
+        required: Version,
+        /// Firmware version of the device
+        actual: Version,
+    },
+    /// Serial port lost, e.g. USB-serial adapter unplugged, see [`Transport::is_disconnected`]
+    Disconnected,
+}
+
+impl<E: core::fmt::Debug, F: core::fmt::Debug> core::fmt::Display for Error<E, F> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Error::SerialR(nb::Error::WouldBlock) => write!(f, "serial read would block"),
+            Error::SerialR(nb::Error::Other(e)) => write!(f, "serial read error: {:?}", e),
+            Error::SerialW(e) => write!(f, "serial write error: {:?}", e),
+            Error::SHDLC(e) => write!(f, "SHDLC decode error: {:?}", e),
+            Error::Encode(e) => write!(f, "SHDLC encode error: {:?}", e),
+            Error::InvalidFrame => write!(f, "no valid frame read"),
+            Error::EmptyResult => write!(f, "result is empty"),
+            Error::ChecksumFailed => write!(f, "checksum failed"),
+            Error::InvalidRespose => write!(f, "invalid response"),
+            Error::StatusError(e) => write!(f, "device error: {}", e),
+            Error::I2cW(e) => write!(f, "I2C write error: {:?}", e),
+            Error::I2cR(e) => write!(f, "I2C read error: {:?}", e),
+            Error::Timeout => write!(f, "no response before timeout"),
+            Error::BufferTooSmall => write!(f, "buffer too small for the frame"),
+            Error::NotDetected => write!(f, "no SPS30 detected"),
+            Error::UnexpectedProduct(name) => write!(f, "unexpected product name {:?}", name),
+            Error::Implausible(p) => write!(f, "implausible measured values {:#06x}", p.0),
+            Error::UnsupportedFirmware { required, actual } => write!(
+                f,
+                "firmware {}.{} does not support the command, {}.{} required",
+                actual.major, actual.minor, required.major, required.minor
+            ),
+            Error::Disconnected => write!(f, "serial port disconnected"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E: core::fmt::Debug, F: core::fmt::Debug> std::error::Error for Error<E, F> {}
+
+#[cfg(feature = "defmt")]
+impl<E: defmt::Format, F: defmt::Format> defmt::Format for Error<E, F> {
+    fn format(&self, fmt: defmt::Formatter) {
+        match self {
+            Error::SerialR(nb::Error::WouldBlock) => defmt::write!(fmt, "SerialR(WouldBlock)"),
+            Error::SerialR(nb::Error::Other(e)) => defmt::write!(fmt, "SerialR({})", e),
+            Error::SerialW(e) => defmt::write!(fmt, "SerialW({})", e),
+            Error::SHDLC(e) => defmt::write!(fmt, "SHDLC({})", defmt::Debug2Format(e)),
+            Error::Encode(e) => defmt::write!(fmt, "Encode({})", defmt::Debug2Format(e)),
+            Error::InvalidFrame => defmt::write!(fmt, "InvalidFrame"),
+            Error::EmptyResult => defmt::write!(fmt, "EmptyResult"),
+            Error::ChecksumFailed => defmt::write!(fmt, "ChecksumFailed"),
+            Error::InvalidRespose => defmt::write!(fmt, "InvalidRespose"),
+            Error::StatusError(e) => defmt::write!(fmt, "StatusError({})", e),
+            Error::I2cW(e) => defmt::write!(fmt, "I2cW({})", e),
+            Error::I2cR(e) => defmt::write!(fmt, "I2cR({})", e),
+            Error::Timeout => defmt::write!(fmt, "Timeout"),
+            Error::BufferTooSmall => defmt::write!(fmt, "BufferTooSmall"),
+            Error::NotDetected => defmt::write!(fmt, "NotDetected"),
+            Error::UnexpectedProduct(name) => defmt::write!(fmt, "UnexpectedProduct({})", name),
+            Error::Implausible(p) => defmt::write!(fmt, "Implausible({})", p),
+            Error::UnsupportedFirmware { required, actual } => defmt::write!(
+                fmt,
+                "UnsupportedFirmware {{ required: {}, actual: {} }}",
+                required,
+                actual
+            ),
+            Error::Disconnected => defmt::write!(fmt, "Disconnected"),
+        }
+    }
+}
+
+/// Error reported by the device in the State field of a MISO Frame
+///
+/// Codes as per section 4.2 from spec
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DeviceError {
+    /// Wrong data length for this command (too much or little data)
+    WrongDataLength,
+    /// Unknown command
+    UnknownCommand,
+    /// No access right for command
+    NoAccess,
+    /// Illegal command parameter or parameter out of allowed range
+    IllegalParameter,
+    /// Internal function argument out of range
+    InternalError,
+    /// Command not allowed in current state
+    NotAllowedInState,
+    /// Code not documented in the datasheet
+    Unknown(u8),
+}
+
+impl From<u8> for DeviceError {
+    fn from(code: u8) -> Self {
+        match code {
+            0x01 => DeviceError::WrongDataLength,
+            0x02 => DeviceError::UnknownCommand,
+            0x03 => DeviceError::NoAccess,
+            0x04 => DeviceError::IllegalParameter,
+            0x28 => DeviceError::InternalError,
+            0x43 => DeviceError::NotAllowedInState,
+            code => DeviceError::Unknown(code),
+        }
+    }
+}
+
+impl core::fmt::Display for DeviceError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            DeviceError::WrongDataLength => write!(f, "wrong data length"),
+            DeviceError::UnknownCommand => write!(f, "unknown command"),
+            DeviceError::NoAccess => write!(f, "no access right for command"),
+            DeviceError::IllegalParameter => write!(f, "illegal command parameter"),
+            DeviceError::InternalError => write!(f, "internal function argument out of range"),
+            DeviceError::NotAllowedInState => write!(f, "command not allowed in current state"),
+            DeviceError::Unknown(code) => write!(f, "unknown error code {:#04x}", code),
+        }
+    }
+}
+
+/// Kind of [`Error`], independent of the bus error types
+///
+/// Lets application code match on errors without naming the HAL's error types.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// Reading from the bus failed
+    Read,
+    /// Writing to the bus failed
+    Write,
+    /// No data available yet, try again later
+    WouldBlock,
+    /// No valid frame read, SHDLC decoding failed or frame too long
+    Framing,
+    /// MOSI Frame could not be SHDLC encoded
+    Encode,
+    /// Checksum or CRC mismatch
+    Checksum,
+    /// Response does not match the command sent
+    InvalidResponse,
+    /// No new data available
+    EmptyResult,
+    /// Device reported an error
+    Device,
+    /// No response within the deadline of the command
+    Timeout,
+    /// Caller-provided buffer is too small
+    BufferTooSmall,
+    /// No sensor, or not the expected one, answered the probe
+    NotDetected,
+    /// Measured values out of the datasheet ranges
+    Implausible,
+    /// Command needs a newer firmware
+    UnsupportedFirmware,
+    /// Serial port lost
+    Disconnected,
+}
+
+impl<E, F> Error<E, F> {
+    /// Kind of the error
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::SerialR(nb::Error::WouldBlock) => ErrorKind::WouldBlock,
+            Error::SerialR(nb::Error::Other(_)) | Error::I2cR(_) => ErrorKind::Read,
+            Error::SerialW(_) | Error::I2cW(_) => ErrorKind::Write,
+            Error::SHDLC(_) | Error::InvalidFrame => ErrorKind::Framing,
+            Error::Encode(_) => ErrorKind::Encode,
+            Error::EmptyResult => ErrorKind::EmptyResult,
+            Error::ChecksumFailed => ErrorKind::Checksum,
+            Error::InvalidRespose => ErrorKind::InvalidResponse,
+            Error::StatusError(_) => ErrorKind::Device,
+            Error::Timeout => ErrorKind::Timeout,
+            Error::BufferTooSmall => ErrorKind::BufferTooSmall,
+            Error::NotDetected | Error::UnexpectedProduct(_) => ErrorKind::NotDetected,
+            Error::Implausible(_) => ErrorKind::Implausible,
+            Error::UnsupportedFirmware { .. } => ErrorKind::UnsupportedFirmware,
+            Error::Disconnected => ErrorKind::Disconnected,
+        }
+    }
+
+    #[cfg(feature = "device-info")]
+    /// Map errors meaning nothing answered, or not a SPS30, to [`Error::NotDetected`]
+    fn not_detected(self) -> Self {
+        match self.kind() {
+            ErrorKind::WouldBlock
+            | ErrorKind::Framing
+            | ErrorKind::Checksum
+            | ErrorKind::InvalidResponse
+            | ErrorKind::Timeout => Error::NotDetected,
+            _ => self,
+        }
+    }
+
+    /// Error caused by a corrupted or lost frame, the command can be sent again
+    fn is_link_error(&self) -> bool {
+        matches!(
+            self.kind(),
+            ErrorKind::Framing | ErrorKind::Checksum | ErrorKind::Timeout
+        )
+    }
 }
 
 impl<E, F> From<nbError<F>> for Error<E, F> {
@@ -53,6 +486,7 @@ impl<E, F> From<nbError<F>> for Error<E, F> {
 
 /// Types of information device holds
 #[repr(u8)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum DeviceInfo {
     /// Product Name
     ProductName = 1,
@@ -64,6 +498,8 @@ pub enum DeviceInfo {
 
 /// Available commands
 #[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum CommandType {
     /// Start measurement
     StartMeasurement = 0,
@@ -79,320 +515,1459 @@ pub enum CommandType {
     DeviceInformation = 0xD0,
     /// Reset
     Reset = 0xD3,
+    /// Sleep (firmware 2.0 or newer)
+    Sleep = 0x10,
+    /// Wake-up (firmware 2.0 or newer)
+    WakeUp = 0x11,
+    /// Read Version
+    ReadVersion = 0xD1,
+    /// Read Device Status Register (firmware 2.2 or newer)
+    ReadDeviceStatusRegister = 0xD2,
 }
 
-/// Checksum implemented as per section 4.1 from spec
-fn compute_cksum(data: &[u8]) -> u8 {
-    let mut cksum: u8 = 0;
-    for &byte in data.iter() {
-        let val: u16 = cksum as u16 + byte as u16;
-        let lsb = val % 256;
-        cksum = lsb as u8;
+impl CommandType {
+    /// Maximum response time of the command, in ms, as per section 4.3 from spec
+    pub fn max_response_time_ms(self) -> u32 {
+        match self {
+            CommandType::Sleep | CommandType::WakeUp => 5,
+            _ => 20,
+        }
     }
 
-    255 - cksum
+    /// Command identified by its CMD byte, `None` if unknown
+    pub fn from_code(code: u8) -> Option<Self> {
+        Some(match code {
+            0x00 => CommandType::StartMeasurement,
+            0x01 => CommandType::StopMeasurement,
+            0x03 => CommandType::ReadMeasuredData,
+            0x80 => CommandType::ReadWriteAutoCleaningInterval,
+            0x56 => CommandType::StartFanCleaning,
+            0xD0 => CommandType::DeviceInformation,
+            0xD3 => CommandType::Reset,
+            0x10 => CommandType::Sleep,
+            0x11 => CommandType::WakeUp,
+            0xD1 => CommandType::ReadVersion,
+            0xD2 => CommandType::ReadDeviceStatusRegister,
+            _ => return None,
+        })
+    }
 }
 
+/// Result of a mode transition
+///
+/// The driver in its new mode, or the error along with the driver still in its previous mode
+pub type Transition<NEW, OLD, E, F> = Result<NEW, (Error<E, F>, OLD)>;
+
+/// Timer placeholder used until one is set, e.g. with [`Sps30::with_timeout`]
+#[derive(Debug, Default)]
+pub struct NoDelay;
+
+impl DelayNs for NoDelay {
+    fn delay_ns(&mut self, _ns: u32) {}
+}
+
+/// Idle mode, the fan is off and no measured values are produced
+#[derive(Debug, Default)]
+pub struct Idle;
+
+/// Measurement mode, the fan is running and measured values are produced every second
+#[derive(Debug, Default)]
+pub struct Measuring;
+
+/// Sleep mode (firmware 2.0 or newer), only the Wake-up command is accepted
+#[derive(Debug, Default)]
+pub struct Sleeping;
+
+/// Modes in which the device accepts commands other than Wake-up
+pub trait Awake {}
+
+impl Awake for Idle {}
+impl Awake for Measuring {}
+
 /// Sps30 driver
+///
+/// `MODE` is the operating mode of the device, [`Idle`], [`Measuring`] or [`Sleeping`].
+/// Commands not allowed in a mode (see section 4.3 from spec) are not available on the driver.
 #[derive(Debug, Default)]
-pub struct Sps30<SERIAL> {
+pub struct Sps30<SERIAL, MODE = Idle, DELAY = NoDelay> {
     /// The concrete Serial device implementation.
     serial: SERIAL,
+    /// Operating mode of the device
+    mode: PhantomData<MODE>,
+    /// Timer used while waiting for response bytes, see [`Sps30::with_timeout`]
+    timer: Option<DELAY>,
+    /// Behavior of the driver
+    config: Config,
+    /// Counters of the UART link
+    stats: LinkStats,
+    /// Firmware version, once read
+    firmware: Option<Version>,
+    /// Format of measured values, of the last measurement started
+    format: MeasurementFormat,
+    /// Measured values read since the last measurement started
+    values_read: u32,
+    /// Fan cleaning started, no measured values read since
+    cleaning: bool,
 }
 
-impl<SERIAL, E, F> Sps30<SERIAL>
+impl<SERIAL, E, F> Sps30<SERIAL, Idle, NoDelay>
 where
-    SERIAL: embedded_hal::blocking::serial::Write<u8, Error = E>
-        + embedded_hal::serial::Read<u8, Error = F>,
+    SERIAL: Transport<WriteError = E, ReadError = F>,
 {
     /// Create new instance of the Sps30 device
+    ///
+    /// Device is expected to be in idle mode, as it is after power-up or reset
     pub fn new(serial: SERIAL) -> Self {
-        Sps30 { serial }
+        Sps30 {
+            serial,
+            mode: PhantomData,
+            timer: None,
+            config: Config::default(),
+            stats: LinkStats::default(),
+            firmware: None,
+            format: MeasurementFormat::default(),
+            values_read: 0,
+            cleaning: false,
+        }
+    }
+
+    /// Build an instance of the Sps30 device with a custom [`Config`] or a timer
+    pub fn builder(serial: SERIAL) -> Sps30Builder<SERIAL> {
+        Sps30Builder::new(serial)
+    }
+}
+
+impl<SERIAL, DELAY, E, F> Sps30<SERIAL, Idle, DELAY>
+where
+    SERIAL: Transport<WriteError = E, ReadError = F>,
+    DELAY: DelayNs,
+{
+    /// Start measuring
+    ///
+    /// Measured values are produced in `format`, read them with [`Sps30::read_measurement`]
+    /// for [`MeasurementFormat::Float`] or [`Sps30::read_measurement_u16`] for
+    /// [`MeasurementFormat::UnsignedInteger16`]. The integer format needs firmware 2.0 or
+    /// newer, the firmware version is read first if not known yet.
+    ///
+    /// On failure the driver is given back together with the error, still in idle mode
+    pub fn start_measurement(
+        mut self,
+        format: MeasurementFormat,
+    ) -> Transition<Sps30<SERIAL, Measuring, DELAY>, Self, E, F> {
+        if format == MeasurementFormat::UnsignedInteger16 {
+            if let Err(e) = self.require_firmware(FIRMWARE_2_0) {
+                return Err((e, self));
+            }
+        }
+        let mut sps30 = self.transition(Command::START_MEASUREMENT, &[0x01, format as u8])?;
+        sps30.format = format;
+        Ok(sps30)
+    }
+
+    /// Start measuring in `preferred` format if the firmware supports it, in float format
+    /// otherwise
+    ///
+    /// The firmware version is read once, then cached by the driver. Integer format needs
+    /// firmware 2.0 or newer, see [`Sps30::active_format`] for the format chosen.
+    pub fn start_measurement_auto(
+        mut self,
+        preferred: MeasurementFormat,
+    ) -> Transition<Sps30<SERIAL, Measuring, DELAY>, Self, E, F> {
+        let firmware = match self.firmware {
+            Some(firmware) => firmware,
+            None => match self.read_versions() {
+                Ok(versions) => versions.firmware,
+                Err(e) => return Err((e, self)),
+            },
+        };
+        let format = match preferred {
+            MeasurementFormat::UnsignedInteger16 if firmware >= FIRMWARE_2_0 => preferred,
+            _ => MeasurementFormat::Float,
+        };
+        self.start_measurement(format)
+    }
+
+    /// Recover from persistent link errors: flush RX, reset the device and wait with
+    /// `delay` for it to start up
+    ///
+    /// See [`Config::recover_after`] to recover automatically while measuring.
+    pub fn recover<D: DelayNs>(&mut self, delay: &mut D) -> Result<(), Error<E, F>> {
+        self.recover_idle(|_| delay.delay_ms(RESET_STARTUP_TIME_MS))
+    }
+
+    /// Power-up sequence: reset the device, wait for it to start up and read its versions
+    ///
+    /// `delay` is used for the startup time after the reset, the versions read confirm the
+    /// device is ready to accept commands.
+    pub fn init<D: DelayNs>(&mut self, delay: &mut D) -> Result<Versions, Error<E, F>> {
+        self.execute(Command::RESET, &[])?;
+        delay.delay_ms(RESET_STARTUP_TIME_MS);
+        self.read_versions()
+    }
+
+    /// Same as [`Sps30::init`], then start measuring in `format`
+    ///
+    /// On failure the driver is given back together with the error, in idle mode
+    pub fn init_measuring<D: DelayNs>(
+        mut self,
+        delay: &mut D,
+        format: MeasurementFormat,
+    ) -> Transition<Sps30<SERIAL, Measuring, DELAY>, Self, E, F> {
+        if let Err(e) = self.init(delay) {
+            return Err((e, self));
+        }
+        self.start_measurement(format)
+    }
+
+    /// Start measuring for the lifetime of the returned [`MeasurementSession`]
+    ///
+    /// Stop Measurement is sent when the session is ended or dropped, e.g. on an early
+    /// return, so the fan is not left running. The driver stays in idle mode. As with
+    /// [`Sps30::start_measurement`], the integer format needs firmware 2.0 or newer.
+    pub fn start_measurement_session(
+        &mut self,
+        format: MeasurementFormat,
+    ) -> Result<MeasurementSession<'_, SERIAL, DELAY>, Error<E, F>> {
+        if format == MeasurementFormat::UnsignedInteger16 {
+            self.require_firmware(FIRMWARE_2_0)?;
+        }
+        self.execute(Command::START_MEASUREMENT, &[0x01, format as u8])?;
+        self.format = format;
+        self.values_read = 0;
+        self.cleaning = false;
+        Ok(MeasurementSession::new(self))
+    }
+
+    /// Measure once: start measuring, wait for values to settle, read and stop measuring
+    ///
+    /// Waits 30s with `delay`, the longest settling time of the datasheet, then polls
+    /// every 100ms until values are available, failing with [`Error::Timeout`] if none are
+    /// after 10s. Measurement is stopped even if reading fails, the driver stays in idle
+    /// mode.
+    #[cfg(not(feature = "no-float"))]
+    pub fn measure_blocking<D: DelayNs>(
+        &mut self,
+        delay: &mut D,
+    ) -> Result<Measurement, Error<E, F>> {
+        let mut session = self.start_measurement_session(MeasurementFormat::Float)?;
+        delay.delay_ms(duty::WARM_UP_MS);
+        let measurement = session.wait_for_first_measurement(delay, iter::MAX_EMPTY_WAIT_MS)?;
+        session.end()?;
+        Ok(measurement)
+    }
+
+    /// Enter sleep mode (firmware 2.0 or newer)
+    ///
+    /// The firmware version is read first if not known yet, older firmwares fail with
+    /// [`Error::UnsupportedFirmware`].
+    ///
+    /// On failure the driver is given back together with the error, still in idle mode
+    pub fn sleep(mut self) -> Transition<Sps30<SERIAL, Sleeping, DELAY>, Self, E, F> {
+        if let Err(e) = self.require_firmware(FIRMWARE_2_0) {
+            return Err((e, self));
+        }
+        self.transition(Command::SLEEP, &[])
+    }
+}
+
+impl<SERIAL, DELAY, E, F> Sps30<SERIAL, Sleeping, DELAY>
+where
+    SERIAL: Transport<WriteError = E, ReadError = F>,
+    DELAY: DelayNs,
+{
+    /// Wake-up from sleep mode, device returns to idle mode
+    ///
+    /// A single 0xFF byte is sent first to generate the low pulse that activates the
+    /// interface. The Wake-up command must follow within 100ms, so it is sent right after.
+    ///
+    /// On failure the driver is given back together with the error, still in sleep mode
+    pub fn wake_up(mut self) -> Transition<Sps30<SERIAL, Idle, DELAY>, Self, E, F> {
+        if let Err(e) = self.check_firmware(FIRMWARE_2_0) {
+            return Err((e, self));
+        }
+        if let Err(e) = self.send_uart_data(&[0xFF]) {
+            return Err((e, self));
+        }
+        self.transition(Command::WAKE_UP, &[])
+    }
+}
+
+impl<SERIAL, DELAY, E, F> Sps30<SERIAL, Measuring, DELAY>
+where
+    SERIAL: Transport<WriteError = E, ReadError = F>,
+    DELAY: DelayNs,
+{
+    /// Format of measured values, as selected when measurement was started
+    pub fn active_format(&self) -> MeasurementFormat {
+        self.format
+    }
+
+    /// Recover from persistent link errors: flush RX, reset the device, wait with `delay`
+    /// for it to start up and start measurement again in the same format
+    ///
+    /// Values are produced again after the fan warm-up. See [`Config::recover_after`] to
+    /// recover automatically.
+    pub fn recover<D: DelayNs>(&mut self, delay: &mut D) -> Result<(), Error<E, F>> {
+        self.recover_measurement(|_| delay.delay_ms(RESET_STARTUP_TIME_MS))
+    }
+
+    /// Whether the values last read were measured after the fan warm-up
+    ///
+    /// The first [`Config::warm_up_reads`] values read after measurement started are
+    /// considered warm-up, false until more were read. Always true when the window is 0.
+    pub fn is_warmed_up(&self) -> bool {
+        self.warmed_up()
+    }
+
+    /// Stop measuring
+    ///
+    /// On failure the driver is given back together with the error, still in measurement mode
+    pub fn stop_measurement(self) -> Transition<Sps30<SERIAL, Idle, DELAY>, Self, E, F> {
+        self.transition(Command::STOP_MEASUREMENT, &[])
+    }
+
+    /// Read measuring
+    ///
+    /// Use [`Measurement::to_array`] to get the values in the order the device sends them
+    #[cfg(not(feature = "no-float"))]
+    pub fn read_measurement(&mut self) -> Result<Measurement, Error<E, F>> {
+        let measurement = self.read_measured_data(Command::READ_MEASUREMENT)?;
+        self.calibrate(&measurement)
+    }
+
+    /// Read measuring, waiting with `delay` until new values are available
+    ///
+    /// Right after starting measurement, reads return empty results until the first values
+    /// are produced. Reads every 100ms, failing with [`Error::Timeout`] if no values are
+    /// available after `max_wait_ms`.
+    #[cfg(not(feature = "no-float"))]
+    pub fn wait_for_first_measurement<D: DelayNs>(
+        &mut self,
+        delay: &mut D,
+        max_wait_ms: u32,
+    ) -> Result<Measurement, Error<E, F>> {
+        iter::wait_for_values(delay, max_wait_ms, || self.read_measurement())
+    }
+
+    /// Same as [`Sps30::health_check`], also checking new measured values are produced
+    ///
+    /// Waits up to `max_wait_ms` for new values, see [`Sps30::wait_for_first_measurement`].
+    /// If none are, the health is [`Health::Failed`]. The values read are dropped.
+    #[cfg(not(feature = "no-float"))]
+    pub fn health_check_measuring<D: DelayNs>(
+        &mut self,
+        delay: &mut D,
+        max_wait_ms: u32,
+    ) -> Result<Health, Error<E, F>> {
+        let status = self.read_device_status(false)?;
+        let health = Health::from(status);
+        if let Health::Failed(_) = health {
+            return Ok(health);
+        }
+        match self.wait_for_first_measurement(delay, max_wait_ms) {
+            Ok(_) => Ok(health),
+            Err(Error::Timeout) => Ok(Health::Failed(status)),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Iterate over measured values, read every `interval_ms` using `delay`
+    ///
+    /// The interval is at least 1s, the rate at which the device produces new values.
+    /// Empty results are skipped by reading again every 100ms until new values are
+    /// available, so each item is either a new measurement or an error, [`Error::Timeout`]
+    /// if none are available after 10s.
+    #[cfg(not(feature = "no-float"))]
+    pub fn measurements<D: DelayNs>(
+        &mut self,
+        delay: D,
+        interval_ms: u32,
+    ) -> Measurements<'_, SERIAL, DELAY, D> {
+        Measurements::new(self, delay, interval_ms)
+    }
+
+    /// Read measuring into `measurement`, left untouched on failure
+    ///
+    /// Same as `read_measurement`, without returning the measured values by copy
+    #[cfg(not(feature = "no-float"))]
+    pub fn read_measurement_into(
+        &mut self,
+        measurement: &mut Measurement,
+    ) -> Result<(), Error<E, F>> {
+        let bytes = self.read_measurement_raw()?;
+        *measurement = self.calibrate(&Measurement::from_bytes(&bytes))?;
+        Ok(())
+    }
+
+    /// Read measuring, returning the 40 bytes payload as sent by the device
+    ///
+    /// The response is checked but the values, big-endian IEEE754 floats, are not converted.
+    /// Use [`Measurement::from_bytes`] to convert them later on.
+    pub fn read_measurement_raw(&mut self) -> Result<[u8; 40], Error<E, F>> {
+        self.read_measured_data(Command::READ_MEASUREMENT_RAW)
+    }
+
+    /// Read measuring as fixed-point numbers, without float arithmetic
+    ///
+    /// Values are in the same order and units as [`Measurement::to_array`], see
+    /// [`fixed_point::from_bytes`]. Calibration is not applied.
+    #[cfg(feature = "fixed")]
+    pub fn read_measurement_fixed(&mut self) -> Result<[fixed::types::U16F16; 10], Error<E, F>> {
+        let bytes = self.read_measurement_raw()?;
+        Ok(fixed_point::from_bytes(&bytes))
+    }
+
+    /// Read measuring, for measurement started with [`MeasurementFormat::UnsignedInteger16`]
+    ///
+    /// Mass concentrations in μg/m³, number concentrations in #/cm³ and typical particle
+    /// size in nm, see [`MeasurementU16`]
+    pub fn read_measurement_u16(&mut self) -> Result<MeasurementU16, Error<E, F>> {
+        self.read_measured_data(Command::READ_MEASUREMENT_U16)
+    }
+
+    #[cfg(feature = "fan-clean")]
+    /// Start fan cleaning
+    ///
+    /// The command returns right away, the cleaning lasts about 10s, see
+    /// [`Sps30::cleaning_in_progress`] and [`Sps30::clean_and_wait`].
+    pub fn start_fan_cleaning(&mut self) -> Result<(), Error<E, F>> {
+        self.execute(Command::START_FAN_CLEANING, &[])?;
+        self.cleaning = true;
+        Ok(())
+    }
+
+    #[cfg(feature = "fan-clean")]
+    /// Start fan cleaning and wait for it to complete, about 10s, using `delay`
+    pub fn clean_and_wait<D: DelayNs>(&mut self, delay: &mut D) -> Result<(), Error<E, F>> {
+        self.start_fan_cleaning()?;
+        delay.delay_ms(FAN_CLEANING_MS);
+        self.cleaning = false;
+        Ok(())
+    }
+
+    /// Whether a fan cleaning started by the driver may still be running
+    ///
+    /// Measured values are not updated during the cleaning, so it is assumed to be over once
+    /// new values are read, or once [`Sps30::clean_and_wait`] returned. Cleanings started by
+    /// the auto-cleaning interval are not tracked.
+    pub fn cleaning_in_progress(&self) -> bool {
+        self.cleaning
+    }
+}
+
+impl<SERIAL, MODE, DELAY, E, F> Sps30<SERIAL, MODE, DELAY>
+where
+    SERIAL: Transport<WriteError = E, ReadError = F>,
+    DELAY: DelayNs,
+{
+    /// Firmware version, if already read by [`Sps30::version`], [`Sps30::init`] or
+    /// [`Sps30::start_measurement_auto`]
+    pub fn firmware_version(&self) -> Option<Version> {
+        self.firmware
+    }
+
+    /// Use `timer` to wait for response bytes
+    ///
+    /// Without a timer, reading fails with `nb::Error::WouldBlock` as soon as no byte is
+    /// available. With a timer, the driver waits instead, polling every 100μs, and fails
+    /// with [`Error::Timeout`] once it waited longer than the maximum response time of the
+    /// command (see [`CommandType::max_response_time_ms`]) plus a margin.
+    /// See [`Config::wait`] for other ways to wait.
+    pub fn with_timeout<D: DelayNs>(self, timer: D) -> Sps30<SERIAL, MODE, D> {
+        Sps30 {
+            serial: self.serial,
+            mode: PhantomData,
+            timer: Some(timer),
+            config: self.config,
+            stats: self.stats,
+            firmware: self.firmware,
+            format: self.format,
+            values_read: self.values_read,
+            cleaning: self.cleaning,
+        }
+    }
+
+    /// Set the margin added to the maximum response time of every command, in ms
+    ///
+    /// It accounts for the time needed to transfer the frames, 10ms by default
+    pub fn set_timeout_margin(&mut self, margin_ms: u32) {
+        self.config.timeout_margin_ms = margin_ms;
+    }
+
+    /// Current configuration
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Mutable access to the configuration
+    pub fn config_mut(&mut self) -> &mut Config {
+        &mut self.config
+    }
+
+    /// Destroy the driver and return the serial port, e.g. to re-configure it
+    ///
+    /// The device is left in its current mode.
+    pub fn release(self) -> SERIAL {
+        self.serial
+    }
+
+    /// Mutable access to the serial port
+    ///
+    /// Bytes written or read directly can leave the device or the driver out of sync.
+    pub fn serial_mut(&mut self) -> &mut SERIAL {
+        &mut self.serial
+    }
+
+    /// Counters of the UART link since creation or the last [`Sps30::reset_stats`]
+    pub fn link_stats(&self) -> LinkStats {
+        self.stats
+    }
+
+    /// Set all link counters back to 0
+    pub fn reset_stats(&mut self) {
+        self.stats = LinkStats::default();
+    }
+
+    /// Send command byte `cmd` carrying `data`, e.g. a new or undocumented command
+    ///
+    /// Framing, checksum and MISO Frame checks are performed, interpreting the data of the
+    /// response is left to the caller. Retries apply as for any other command, the device is
+    /// expected to respond within 20ms. The driver mode is not changed, commands switching
+    /// the device to another mode will leave the driver out of sync.
+    pub fn send_raw_command(&mut self, cmd: u8, data: &[u8]) -> Result<ResponseFrame, Error<E, F>> {
+        self.retry(|sps30| sps30.transceive_raw_once(cmd, data, RAW_COMMAND_RESPONSE_TIME_MS))
+    }
+
+    /// Drop stale bytes waiting in the receive path, e.g. after an aborted read or a reset
+    ///
+    /// Reads until the serial port reports `nb::Error::WouldBlock`, returns the number of
+    /// bytes dropped. See [`Config::flush_before_command`] to do it before every command.
+    pub fn flush_rx(&mut self) -> Result<usize, Error<E, F>> {
+        let mut dropped = 0;
+        loop {
+            match self.serial.read_byte() {
+                Ok(_) => dropped += 1,
+                Err(nb::Error::WouldBlock) => return Ok(dropped),
+                Err(e) => return Err(self.port_error(Error::from(e))),
+            }
+        }
     }
 
     /// Send data through serial interface
     fn send_uart_data(&mut self, data: &[u8]) -> Result<(), Error<E, F>> {
-        let s_chars = SpecialChars::default();
-        let output = encode(&data, s_chars).unwrap();
-        //extern crate std;
-        //std::println!("Write {:x?}", output);
-        self.serial.bwrite_all(&output).map_err(Error::SerialW)
+        #[cfg(feature = "log")]
+        log::trace!("MOSI {:02x?}", data);
+        if let Some(observer) = self.config.observer {
+            observer.on_tx(data);
+        }
+        #[cfg(feature = "debug-frames")]
+        self.debug_frame(Direction::Tx, data);
+        self.serial
+            .write_all(data)
+            .map_err(|e| self.port_error(Error::SerialW(e)))
+    }
+
+    /// Report a failed read or write as [`Error::Disconnected`] if the port is gone
+    fn port_error(&self, error: Error<E, F>) -> Error<E, F> {
+        if self.serial.is_disconnected() {
+            Error::Disconnected
+        } else {
+            error
+        }
     }
 
-    /// Read from serial until two 0x7e are seen
+    /// Hand a raw frame to `defmt` and to [`Config::frame_formatter`]
+    #[cfg(feature = "debug-frames")]
+    fn debug_frame(&self, direction: Direction, frame: &[u8]) {
+        #[cfg(feature = "defmt")]
+        match direction {
+            Direction::Tx => defmt::debug!("MOSI {=[u8]:x}", frame),
+            Direction::Rx => defmt::debug!("MISO {=[u8]:x}", frame),
+        }
+        if let Some(formatter) = self.config.frame_formatter {
+            formatter(direction, frame);
+        }
+    }
+
+    /// Read a complete frame from serial, start and stop flags (0x7e by default) included
     ///
-    /// No more than MAX_BUFFER=600 u8 will be read
-    /// After a MISO Frame is received, result is SHDLC decoded
-    /// Checksum for decoded frame is verified
-    fn read_uart_data(&mut self) -> Result<ArrayVec<[u8; 1024]>, Error<E, F>> {
-        let mut output = ArrayVec::<[u8; 1024]>::new();
+    /// Bytes before the start flag are discarded, back to back flags restart the frame.
+    /// No more than `max_frame_size` (100 by default) u8 will be accumulated, nor discarded.
+    /// Waits for bytes as set by [`Config::wait`], with the timer up to `max_response_time_ms`
+    /// plus the configured margin
+    fn read_uart_data(
+        &mut self,
+        max_response_time_ms: u32,
+    ) -> Result<ArrayVec<[u8; protocol::ENCODED_FRAME_SIZE]>, Error<E, F>> {
+        let mut output = ArrayVec::<[u8; protocol::ENCODED_FRAME_SIZE]>::new();
+        let deadline_us = (max_response_time_ms + self.config.timeout_margin_ms) * 1000;
+        let max_frame_size = self.config.max_frame_size;
+        let fend = self.config.special_chars.fend;
+        let mut waited = 0;
+        let mut discarded = 0;
 
-        let mut seen = 0;
-        while seen != 2 {
-            let byte = self.serial.read();
+        loop {
+            let byte = self.serial.read_byte();
             match byte {
-                Ok(value) => {
-                    if value == 0x7e {
-                        seen += 1;
+                Ok(value) => match protocol::sync_byte(&mut output, value, fend, max_frame_size) {
+                    FrameSync::Complete => return Ok(output),
+                    FrameSync::Pending => {}
+                    FrameSync::Discarded => {
+                        discarded += 1;
+                        if discarded > max_frame_size {
+                            return Err(Error::InvalidFrame);
+                        }
                     }
-                    output.push(value);
+                    FrameSync::Overflow => return Err(Error::InvalidFrame),
+                },
+                Err(nb::Error::WouldBlock) => {
+                    waited = self.wait_for_byte(waited, deadline_us)?;
                 }
                 Err(e) => {
-                    return Err(Error::from(e));
+                    return Err(self.port_error(Error::from(e)));
                 }
             }
-            if output.len() > MAX_BUFFER {
-                return Err(Error::InvalidFrame);
-            }
         }
+    }
 
-        match decode(&output, SpecialChars::default()) {
-            Ok(v) => {
-                if v[v.len() - 1] == compute_cksum(&v[..v.len() - 1]) {
-                    return Ok(v);
-                }
-
-                Err(Error::ChecksumFailed)
+    /// Wait before reading again as set by [`Config::wait`], `waited` polls or μs so far
+    ///
+    /// Returns the updated count, fails once the wait is over.
+    fn wait_for_byte(&mut self, waited: u32, deadline_us: u32) -> Result<u32, Error<E, F>> {
+        match (self.config.wait, self.timer.as_mut()) {
+            (WaitStrategy::Delay, None) => Err(Error::SerialR(nb::Error::WouldBlock)),
+            (WaitStrategy::Delay, Some(_)) if waited >= deadline_us => Err(Error::Timeout),
+            (WaitStrategy::Delay, Some(timer)) => {
+                timer.delay_us(POLL_INTERVAL_US);
+                Ok(waited + POLL_INTERVAL_US)
+            }
+            (WaitStrategy::Spin { polls }, _) | (WaitStrategy::Yield { polls, .. }, _)
+                if waited >= polls =>
+            {
+                Err(Error::Timeout)
+            }
+            (WaitStrategy::Spin { .. }, _) => Ok(waited + 1),
+            (WaitStrategy::Yield { yield_now, .. }, _) => {
+                yield_now();
+                Ok(waited + 1)
             }
-            Err(e) => Err(Error::SHDLC(e)),
         }
     }
 
-    /// Perform checks on MISO Frame
-    ///  * lenght >=5
-    ///  * CMD must match sent MOSI Frame CMD
-    ///  * State should be 0 (No Error)
-    ///  * L(ength) must be valid
-    fn check_miso_frame<'a>(
-        &self,
-        data: &'a [u8],
+    /// Send a command and check the MISO Frame received in response
+    ///
+    /// The command is sent again as configured in [`Config`]
+    fn transceive(
+        &mut self,
         cmd_type: CommandType,
-    ) -> Result<&'a [u8], Error<E, F>> {
-        if data.len() < 5 {
-            return Err(Error::InvalidRespose);
+        data: &[u8],
+    ) -> Result<ResponseFrame, Error<E, F>> {
+        #[cfg(feature = "tracing")]
+        let (span, retries) = (
+            tracing::debug_span!(
+                "sps30_command",
+                command = ?cmd_type,
+                payload_len = data.len(),
+                retries = tracing::field::Empty,
+                outcome = tracing::field::Empty,
+            )
+            .entered(),
+            self.stats.retries,
+        );
+        let result = self.retry(|sps30| sps30.transceive_once(cmd_type, data));
+        #[cfg(feature = "log")]
+        match &result {
+            Ok(_) => log::debug!("{:?} succeeded", cmd_type),
+            Err(e) => log::debug!("{:?} failed: {:?}", cmd_type, e.kind()),
         }
-
-        if data[1] != cmd_type as u8 {
-            return Err(Error::InvalidRespose);
+        #[cfg(feature = "tracing")]
+        {
+            span.record("retries", self.stats.retries.wrapping_sub(retries));
+            match &result {
+                Ok(_) => span.record("outcome", "ok"),
+                Err(e) => span.record("outcome", tracing::field::debug(e.kind())),
+            };
         }
-        if data[2] != 0 {
-            return Err(Error::StatusError);
+        result
+    }
+
+    /// Run `attempt` again, up to `retries` times, while it fails with a link error (or an
+    /// empty result, if `retry_on_empty` is set)
+    ///
+    /// With a timer, waits `retry_delay_ms` before the first retry, doubling the delay
+    /// before every following one.
+    fn retry<T>(
+        &mut self,
+        mut attempt: impl FnMut(&mut Self) -> Result<T, Error<E, F>>,
+    ) -> Result<T, Error<E, F>> {
+        let mut attempts = 0;
+        let mut delay_ms = self.config.retry_delay_ms;
+        loop {
+            let result = attempt(self);
+            match &result {
+                Err(e) if attempts < self.config.retries && self.should_retry(e) => {
+                    attempts += 1;
+                    self.stats.retries = self.stats.retries.wrapping_add(1);
+                    if let Some(timer) = self.timer.as_mut() {
+                        timer.delay_ms(delay_ms);
+                    }
+                    delay_ms = delay_ms.saturating_mul(2);
+                }
+                _ => return result,
+            }
         }
+    }
 
-        if data[3] as usize != data.len() - 5 {
-            return Err(Error::InvalidRespose);
+    /// Check `measurement` if [`Config::reject_implausible`] is set, then apply
+    /// [`Config::calibration`], if any
+    #[cfg(not(feature = "no-float"))]
+    fn calibrate(&self, measurement: &Measurement) -> Result<Measurement, Error<E, F>> {
+        if self.config.reject_implausible {
+            let plausibility = measurement.plausibility();
+            if !plausibility.is_plausible() {
+                return Err(Error::Implausible(plausibility));
+            }
         }
+        Ok(match &self.config.calibration {
+            Some(calibration) => calibration.apply(measurement),
+            None => *measurement,
+        })
+    }
 
-        //extern crate std;
-        //std::println!("Read: {:x?}", &data);
-        Ok(data)
+    /// Fail with [`Error::UnsupportedFirmware`] if the firmware is known to be older than
+    /// `required`
+    fn check_firmware(&self, required: Version) -> Result<(), Error<E, F>> {
+        match self.firmware {
+            Some(actual) if actual < required => {
+                Err(Error::UnsupportedFirmware { required, actual })
+            }
+            _ => Ok(()),
+        }
     }
 
-    /// Start measuring
-    pub fn start_measurement(&mut self) -> Result<(), Error<E, F>> {
-        let mut output = ArrayVec::<[u8; 1024]>::new();
-        let cmd = [0x00, 0x00, 0x02, 0x01, 0x03];
-        for item in &cmd {
-            output.push(*item);
-        }
-        output.push(compute_cksum(&output));
-        self.send_uart_data(&output)?;
-
-        match self.read_uart_data() {
-            Ok(response) => self
-                .check_miso_frame(&response, CommandType::StartMeasurement)
-                .map(|_| ()),
-            Err(e) => Err(e),
+    /// Same as [`Sps30::check_firmware`], reading the firmware version first if not known
+    ///
+    /// Only valid while the device is awake.
+    fn require_firmware(&mut self, required: Version) -> Result<(), Error<E, F>> {
+        if self.firmware.is_none() {
+            self.read_versions()?;
         }
+        self.check_firmware(required)
     }
 
-    /// Stop measuring
-    pub fn stop_measurement(&mut self) -> Result<(), Error<E, F>> {
-        let mut output = ArrayVec::<[u8; 1024]>::new();
-        let cmd = [0x00, 0x01, 0x00];
-        for item in &cmd {
-            output.push(*item);
-        }
-        output.push(compute_cksum(&output));
-        self.send_uart_data(&output)?;
-
-        match self.read_uart_data() {
-            Ok(response) => self
-                .check_miso_frame(&response, CommandType::StopMeasurement)
-                .map(|_| ()),
-            Err(e) => Err(e),
+    /// Send Read Version, keeping the firmware version for [`Sps30::firmware_version`]
+    fn read_versions(&mut self) -> Result<Versions, Error<E, F>> {
+        let versions = self.execute(Command::READ_VERSION, &[])?;
+        self.firmware = Some(versions.firmware);
+        Ok(versions)
+    }
+
+    /// Send Read Measured Values, converting the data of the response as `command` does
+    ///
+    /// Retried as a whole, so an invalid payload is read again like a corrupted frame.
+    fn read_measured_data<T>(&mut self, command: Command<T, E, F>) -> Result<T, Error<E, F>> {
+        let result = self.retry(|sps30| sps30.execute_once(command, &[]));
+        if result.is_err() {
+            self.auto_recover();
+        }
+        let values = result?;
+        self.values_read = self.values_read.saturating_add(1);
+        self.cleaning = false;
+        if self.config.discard_warm_up && !self.warmed_up() {
+            return Err(Error::EmptyResult);
         }
+        Ok(values)
     }
 
-    /// Read measuring
-    pub fn read_measurement(&mut self) -> Result<[f32; 10], Error<E, F>> {
-        let mut output = ArrayVec::<[u8; 1024]>::new();
-        let cmd = [0x00, 0x03, 0x00];
-        for item in &cmd {
-            output.push(*item);
-        }
-        output.push(compute_cksum(&cmd));
-        self.send_uart_data(&output)?;
-
-        let data = self.read_uart_data();
-
-        let mut res: [f32; 10] = [0.0; 10];
-        match data {
-            Ok(v) => match v.len() {
-                45 => {
-                    self.check_miso_frame(&v, CommandType::ReadMeasuredData)?;
-                    for i in 0..res.len() {
-                        let mut bits: u32 = 0;
-                        for &byte in v[4 + 4 * i..4 + 4 * (i + 1)].iter() {
-                            bits = (bits << 8) + byte as u32;
-                        }
-                        res[i] = Ieee754::from_bits(bits);
-                    }
-                    Ok(res)
-                }
-                5 => Err(Error::EmptyResult),
-                _ => Err(Error::InvalidFrame),
-            },
-            Err(e) => Err(e),
+    /// Recover while measuring if [`Config::recover_after`] link errors happened in a row
+    ///
+    /// Skipped without timer, the device startup time could not be waited for.
+    fn auto_recover(&mut self) {
+        let threshold = self.config.recover_after;
+        if threshold == 0 || self.stats.consecutive_errors < threshold || self.timer.is_none() {
+            return;
+        }
+        let _ = self.recover_measurement(|sps30| {
+            if let Some(timer) = sps30.timer.as_mut() {
+                timer.delay_ms(RESET_STARTUP_TIME_MS);
+            }
+        });
+        if let Some(callback) = self.config.on_recover {
+            callback(&self.stats);
         }
     }
 
-    /// Read cleaning interval
-    pub fn read_cleaning_interval(&mut self) -> Result<u32, Error<E, F>> {
-        let mut output = ArrayVec::<[u8; 1024]>::new();
-        let cmd = [0x00, 0x80, 0x01, 0x00];
-        for item in &cmd {
-            output.push(*item);
-        }
-        output.push(compute_cksum(&output));
-        self.send_uart_data(&output)?;
-
-        match self.read_uart_data() {
-            Ok(response) => {
-                match self.check_miso_frame(&response, CommandType::ReadWriteAutoCleaningInterval) {
-                    Ok(v) => {
-                        if v[3] != 4 {
-                            return Err(Error::InvalidRespose);
-                        }
+    /// Flush RX, reset the device and start measurement again in the last format
+    ///
+    /// `wait` is called once the reset is sent, to let the device start up.
+    fn recover_measurement(&mut self, wait: impl FnOnce(&mut Self)) -> Result<(), Error<E, F>> {
+        self.recover_idle(wait)?;
+        self.execute(Command::START_MEASUREMENT, &[0x01, self.format as u8])?;
+        self.values_read = 0;
+        Ok(())
+    }
 
-                        let mut ret: u32 = 0;
-                        for &byte in v[4..8].iter() {
-                            ret = ret * 256 + byte as u32;
-                        }
-                        Ok(ret)
-                    }
-                    Err(e) => Err(e),
-                }
+    /// Flush RX and reset the device, back in idle mode
+    ///
+    /// `wait` is called once the reset is sent, to let the device start up.
+    fn recover_idle(&mut self, wait: impl FnOnce(&mut Self)) -> Result<(), Error<E, F>> {
+        self.stats.recoveries = self.stats.recoveries.wrapping_add(1);
+        self.stats.consecutive_errors = 0;
+        self.cleaning = false;
+        self.flush_rx()?;
+        self.execute(Command::RESET, &[])?;
+        wait(self);
+        self.flush_rx().map(|_| ())
+    }
+
+    /// All values of the warm-up window read, see [`Config::warm_up_reads`]
+    fn warmed_up(&self) -> bool {
+        self.values_read > self.config.warm_up_reads
+    }
+
+    /// Whether a command failing with `error` is sent again
+    fn should_retry(&self, error: &Error<E, F>) -> bool {
+        error.is_link_error() || (self.config.retry_on_empty && matches!(error, Error::EmptyResult))
+    }
+
+    /// Send `command` carrying `data` and interpret the data of its response
+    ///
+    /// The command is sent again as configured in [`Config`]
+    fn execute<T>(&mut self, command: Command<T, E, F>, data: &[u8]) -> Result<T, Error<E, F>> {
+        let response = self.transceive(command.cmd, data)?;
+        (command.parse)(response.data())
+    }
+
+    /// Same as [`Sps30::execute`], sending `command` once
+    fn execute_once<T>(
+        &mut self,
+        command: Command<T, E, F>,
+        data: &[u8],
+    ) -> Result<T, Error<E, F>> {
+        let response = self.transceive_once(command.cmd, data)?;
+        (command.parse)(response.data())
+    }
+
+    /// Send a command once and check the MISO Frame received in response
+    fn transceive_once(
+        &mut self,
+        cmd_type: CommandType,
+        data: &[u8],
+    ) -> Result<ResponseFrame, Error<E, F>> {
+        self.transceive_raw_once(cmd_type as u8, data, cmd_type.max_response_time_ms())
+    }
+
+    /// Send command byte `cmd` once and check the MISO Frame received in response
+    fn transceive_raw_once(
+        &mut self,
+        cmd: u8,
+        data: &[u8],
+        max_response_time_ms: u32,
+    ) -> Result<ResponseFrame, Error<E, F>> {
+        self.send_request(cmd, data)?;
+        let result = self
+            .read_uart_data(max_response_time_ms)
+            .and_then(|mut frame| self.check_response(&mut frame, cmd));
+        self.record_outcome(&result);
+        result
+    }
+
+    /// Send command byte `cmd` carrying `data`, without reading the response
+    fn send_request(&mut self, cmd: u8, data: &[u8]) -> Result<(), Error<E, F>> {
+        if self.config.flush_before_command {
+            self.flush_rx()?;
+        }
+        let s_chars = self.config.special_chars;
+        let address = self.config.address;
+        match protocol::pre_encoded(address, cmd, data, s_chars) {
+            Some(frame) => self.send_uart_data(frame)?,
+            None => {
+                self.send_uart_data(&protocol::encode_raw_request(address, cmd, data, s_chars)?)?
             }
-            Err(e) => Err(e),
         }
+        self.stats.frames_sent = self.stats.frames_sent.wrapping_add(1);
+        Ok(())
     }
 
-    /// Write cleaning interval
-    pub fn write_cleaning_interval(&mut self, val: u32) -> Result<(), Error<E, F>> {
-        let mut output = ArrayVec::<[u8; 1024]>::new();
-        let cmd = [0x00, 0x80, 0x05, 0x00];
-        for item in &cmd {
-            output.push(*item);
-        }
-        for item in &val.to_be_bytes() {
-            output.push(*item);
-        }
-        output.push(compute_cksum(&output));
-        self.send_uart_data(&output)?;
-
-        match self.read_uart_data() {
-            Ok(response) => {
-                match self.check_miso_frame(&response, CommandType::ReadWriteAutoCleaningInterval) {
-                    Ok(v) => {
-                        if v[3] != 0 {
-                            return Err(Error::InvalidRespose);
-                        }
-                        Ok(())
-                    }
-                    Err(e) => Err(e),
-                }
-            }
-            Err(e) => Err(e),
+    /// Check the MISO Frame `frame` received in response to command byte `cmd`
+    fn check_response(&mut self, frame: &mut [u8], cmd: u8) -> Result<ResponseFrame, Error<E, F>> {
+        self.stats.frames_received = self.stats.frames_received.wrapping_add(1);
+        if let Some(observer) = self.config.observer {
+            observer.on_rx(frame);
         }
+        #[cfg(feature = "debug-frames")]
+        self.debug_frame(Direction::Rx, frame);
+        let s_chars = self.config.special_chars;
+        protocol::parse_raw_response_in_place(
+            frame,
+            self.config.address,
+            cmd,
+            s_chars,
+            self.config.validation,
+        )
     }
 
-    /// Start fan cleaning
-    pub fn start_fan_cleaning(&mut self) -> Result<(), Error<E, F>> {
-        let mut output = ArrayVec::<[u8; 1024]>::new();
-        let cmd = [0x00, 0x56, 0x00];
-        for item in &cmd {
-            output.push(*item);
-        }
-        output.push(compute_cksum(&output));
-        self.send_uart_data(&output)?;
-
-        match self.read_uart_data() {
-            Ok(response) => self
-                .check_miso_frame(&response, CommandType::StartFanCleaning)
-                .map(|_| ()),
-            Err(e) => Err(e),
+    /// Update the link statistics with the outcome of a command
+    fn record_outcome(&mut self, result: &Result<ResponseFrame, Error<E, F>>) {
+        match result {
+            Ok(_) => self.stats.consecutive_errors = 0,
+            Err(e) => self.stats.record_error(e),
         }
     }
 
+    /// Send a command and switch to mode `M` if the device accepted it
+    fn transition<M>(
+        mut self,
+        command: Command<(), E, F>,
+        data: &[u8],
+    ) -> Transition<Sps30<SERIAL, M, DELAY>, Self, E, F> {
+        match self.execute(command, data) {
+            Ok(_) => Ok(Sps30 {
+                serial: self.serial,
+                mode: PhantomData,
+                timer: self.timer,
+                config: self.config,
+                stats: self.stats,
+                firmware: self.firmware,
+                format: self.format,
+                values_read: 0,
+                cleaning: false,
+            }),
+            Err(e) => Err((e, self)),
+        }
+    }
+}
+
+impl<SERIAL, MODE, DELAY, E, F> Sps30<SERIAL, MODE, DELAY>
+where
+    SERIAL: Transport<WriteError = E, ReadError = F>,
+    MODE: Awake,
+    DELAY: DelayNs,
+{
+    #[cfg(feature = "cleaning")]
+    /// Read cleaning interval
+    pub fn read_cleaning_interval(&mut self) -> Result<AutoCleanInterval, Error<E, F>> {
+        self.execute(Command::READ_CLEANING_INTERVAL, &[0x00])
+    }
+
+    #[cfg(feature = "cleaning")]
+    /// Write cleaning interval
+    ///
+    /// The new interval applies right away, but reading it back returns the previous one
+    /// until the device is reset or powered down.
+    pub fn write_cleaning_interval(
+        &mut self,
+        interval: AutoCleanInterval,
+    ) -> Result<(), Error<E, F>> {
+        let mut data = [0; 5];
+        data[1..].copy_from_slice(&interval.as_secs().to_be_bytes());
+        self.execute(Command::WRITE_CLEANING_INTERVAL, &data)
+    }
+
+    #[cfg(feature = "cleaning")]
+    /// Disable the fan auto cleaning, writing a cleaning interval of 0
+    pub fn disable_auto_cleaning(&mut self) -> Result<(), Error<E, F>> {
+        self.write_cleaning_interval(AutoCleanInterval::DISABLED)
+    }
+
+    /// Read Device Status Register (firmware 2.2 or newer), clearing it if `clear`
+    ///
+    /// The firmware version is read first if not known yet.
+    pub fn read_device_status(&mut self, clear: bool) -> Result<DeviceStatus, Error<E, F>> {
+        self.require_firmware(FIRMWARE_2_2)?;
+        self.execute(Command::READ_DEVICE_STATUS, &[u8::from(clear)])
+    }
+
+    /// Summarize the health of the device from its status register, without clearing it
+    ///
+    /// Needs firmware 2.2 or newer, see [`Sps30::read_device_status`]. In measurement
+    /// mode, [`Sps30::health_check_measuring`] also checks measured values are produced.
+    pub fn health_check(&mut self) -> Result<Health, Error<E, F>> {
+        self.read_device_status(false).map(Health::from)
+    }
+
+    #[cfg(feature = "device-info")]
     /// Get info
     ///
-    /// Return a [u8;32] with info
-    pub fn device_info(&mut self, info: DeviceInfo) -> Result<[u8; 32], Error<E, F>> {
-        let mut output = ArrayVec::<[u8; 1024]>::new();
-        let cmd = [0x00, 0xD0, 0x01];
-        for item in &cmd {
-            output.push(*item);
-        }
-        output.push(info as u8);
-        output.push(compute_cksum(&output));
-        self.send_uart_data(&output)?;
-
-        match self.read_uart_data() {
-            Ok(response) => {
-                match self.check_miso_frame(&response, CommandType::DeviceInformation) {
-                    Ok(val) => {
-                        let mut ret: [u8; 32] = [0; 32];
-                        if val[3] < 33 {
-                            for i in 0..val[3] {
-                                ret[i as usize] = val[3 + i as usize];
-                            }
-                            return Ok(ret);
-                        }
-                        Err(Error::EmptyResult)
-                    }
-                    Err(e) => Err(e),
-                }
+    /// Return the info as an ASCII string, without the trailing NULs
+    pub fn device_info(&mut self, info: DeviceInfo) -> Result<InfoString, Error<E, F>> {
+        self.execute(Command::DEVICE_INFORMATION, &[info as u8])
+    }
+
+    #[cfg(feature = "device-info")]
+    /// Read Product Name, Article Code and Serial Number
+    pub fn device_identity(&mut self) -> Result<DeviceIdentity, Error<E, F>> {
+        Ok(DeviceIdentity {
+            product_name: self.device_info(DeviceInfo::ProductName)?,
+            article_code: self.device_info(DeviceInfo::ArticleCode)?,
+            serial_number: self.device_info(DeviceInfo::SerialNumber)?,
+        })
+    }
+
+    #[cfg(feature = "device-info")]
+    /// Check a SPS30 answers, returning its Serial Number
+    ///
+    /// Meant to be called at startup, to detect wiring problems before the first
+    /// measurement. Without response, or with a garbled one, fails with
+    /// [`Error::NotDetected`]. If `expected_product` is given, the Product Name is read
+    /// too and must match it, e.g. [`SPS30_PRODUCT_NAME`], otherwise fails with
+    /// [`Error::UnexpectedProduct`].
+    pub fn probe(&mut self, expected_product: Option<&str>) -> Result<InfoString, Error<E, F>> {
+        let serial_number = self
+            .device_info(DeviceInfo::SerialNumber)
+            .map_err(Error::not_detected)?;
+        if let Some(expected) = expected_product {
+            let product_name = self
+                .device_info(DeviceInfo::ProductName)
+                .map_err(Error::not_detected)?;
+            if product_name != expected {
+                return Err(Error::UnexpectedProduct(product_name));
             }
-            Err(e) => Err(e),
         }
+        Ok(serial_number)
     }
 
-    /// Reset device
+    /// Read firmware, hardware and SHDLC protocol versions
+    pub fn version(&mut self) -> Result<Versions, Error<E, F>> {
+        self.read_versions()
+    }
+
+    #[cfg(feature = "device-info")]
+    /// Check the link end-to-end, telling wiring problems from a sensor not answering
     ///
-    /// After calling this function, caller must sleep before issuing more commands
-    pub fn reset(&mut self) -> Result<(), Error<E, F>> {
-        let mut output = ArrayVec::<[u8; 1024]>::new();
-        let cmd = [0x00, 0xD3, 0x00];
-        for item in &cmd {
-            output.push(*item);
+    /// Bytes pending before any command was sent are noise on the RX line. Then Device
+    /// Information and Read Version, which answer with frames of different lengths, are
+    /// sent once each, without retries, and the first failure is reported.
+    pub fn self_test(&mut self) -> SelfTest {
+        match self.flush_rx() {
+            Ok(0) => {}
+            Ok(_) => return SelfTest::Wiring,
+            Err(e) => return SelfTest::from_error(&e),
         }
-        output.push(compute_cksum(&output));
-        self.send_uart_data(&output)?;
-
-        match self.read_uart_data() {
-            Ok(response) => self
-                .check_miso_frame(&response, CommandType::Reset)
-                .map(|_| ()),
-            Err(e) => Err(e),
+        let result = self
+            .execute_once(
+                Command::DEVICE_INFORMATION,
+                &[DeviceInfo::SerialNumber as u8],
+            )
+            .and_then(|_| self.execute_once(Command::READ_VERSION, &[]));
+        match result {
+            Ok(versions) => {
+                self.firmware = Some(versions.firmware);
+                SelfTest::Passed
+            }
+            Err(e) => SelfTest::from_error(&e),
         }
     }
+
+    /// Reset device
+    ///
+    /// Device returns to idle mode. After calling this function, caller must sleep before
+    /// issuing more commands
+    pub fn reset(self) -> Transition<Sps30<SERIAL, Idle, DELAY>, Self, E, F> {
+        self.transition(Command::RESET, &[])
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use crate::replay::Replay;
+
+    /// Transport acknowledging every command with an empty MISO Frame
+    #[cfg(not(feature = "no-float"))]
+    #[derive(Default)]
+    struct Acknowledge {
+        frame: [u8; 7],
+        pos: usize,
+    }
+
+    #[cfg(not(feature = "no-float"))]
+    impl Transport for Acknowledge {
+        type WriteError = ();
+        type ReadError = ();
+
+        fn write_all(&mut self, data: &[u8]) -> Result<(), ()> {
+            let cmd = data[2];
+            self.frame = [0x7E, 0x00, cmd, 0x00, 0x00, 0xFF - cmd, 0x7E];
+            self.pos = 0;
+            Ok(())
+        }
+
+        fn read_byte(&mut self) -> nb::Result<u8, ()> {
+            let byte = *self.frame.get(self.pos).ok_or(nb::Error::WouldBlock)?;
+            self.pos += 1;
+            Ok(byte)
+        }
+    }
+
     #[test]
     fn it_works() {
         assert_eq!(2 + 2, 4);
     }
+
+    #[test]
+    fn noise_before_frame_is_discarded() {
+        // Tail of a previous frame, then the Start Measurement response
+        let rx = &[0x12, 0x34, 0x7E, 0x7E, 0x00, 0x00, 0x00, 0x00, 0xFF, 0x7E];
+        let sensor = Sps30::new(Replay { rx });
+        assert!(sensor.start_measurement(MeasurementFormat::Float).is_ok());
+    }
+
+    #[cfg(not(feature = "no-float"))]
+    #[test]
+    fn retries_after_checksum_failure() {
+        let rx = &[
+            // Start Measurement response
+            0x7E, 0x00, 0x00, 0x00, 0x00, 0xFF, 0x7E,
+            // Corrupted, then valid, Read Measured Values response without data
+            0x7E, 0x00, 0x03, 0x00, 0x00, 0x00, 0x7E, 0x7E, 0x00, 0x03, 0x00, 0x00, 0xFC, 0x7E,
+        ];
+        let mut sensor = Sps30::builder(Replay { rx })
+            .retries(1)
+            .build()
+            .start_measurement(MeasurementFormat::Float)
+            .ok()
+            .unwrap();
+        assert!(matches!(sensor.read_measurement(), Err(Error::EmptyResult)));
+
+        let stats = sensor.link_stats();
+        assert_eq!(stats.frames_sent, 3);
+        assert_eq!(stats.checksum_failures, 1);
+        assert_eq!(stats.retries, 1);
+    }
+
+    #[cfg(feature = "device-info")]
+    #[test]
+    fn probe_without_sensor() {
+        let mut sensor = Sps30::new(Replay { rx: &[] });
+        assert!(matches!(sensor.probe(None), Err(Error::NotDetected)));
+    }
+
+    #[test]
+    fn session_stops_on_drop() {
+        let rx = &[
+            // Start Measurement response
+            0x7E, 0x00, 0x00, 0x00, 0x00, 0xFF, 0x7E, // Stop Measurement response
+            0x7E, 0x00, 0x01, 0x00, 0x00, 0xFE, 0x7E,
+        ];
+        let mut sensor = Sps30::new(Replay { rx });
+        {
+            let session = sensor.start_measurement_session(MeasurementFormat::Float);
+            assert!(session.is_ok());
+        }
+        assert!(sensor.serial_mut().rx.is_empty());
+        assert_eq!(sensor.link_stats().frames_sent, 2);
+    }
+
+    #[test]
+    fn integer_format_needs_firmware_2() {
+        let rx = &[
+            // Read Version response, firmware 2.2
+            0x7E, 0x00, 0xD1, 0x00, 0x07, 0x02, 0x02, 0x00, 0x07, 0x00, 0x02, 0x00, 0x1A, 0x7E,
+            // Start Measurement response
+            0x7E, 0x00, 0x00, 0x00, 0x00, 0xFF, 0x7E,
+        ];
+        let sensor = Sps30::new(Replay { rx })
+            .start_measurement_auto(MeasurementFormat::UnsignedInteger16)
+            .ok()
+            .unwrap();
+        assert_eq!(sensor.firmware_version(), Some(Version::new(2, 2)));
+        assert_eq!(sensor.active_format(), MeasurementFormat::UnsignedInteger16);
+    }
+
+    #[test]
+    fn session_integer_format_needs_firmware_2() {
+        let rx = &[
+            // Read Version response, firmware 1.0
+            0x7E, 0x00, 0xD1, 0x00, 0x07, 0x01, 0x00, 0x00, 0x07, 0x00, 0x02, 0x00, 0x1D, 0x7E,
+        ];
+        let mut sensor = Sps30::new(Replay { rx });
+        let result = sensor.start_measurement_session(MeasurementFormat::UnsignedInteger16);
+        assert!(matches!(
+            result.err(),
+            Some(Error::UnsupportedFirmware { .. })
+        ));
+
+        let rx = &[
+            // Read Version response, firmware 2.2
+            0x7E, 0x00, 0xD1, 0x00, 0x07, 0x02, 0x02, 0x00, 0x07, 0x00, 0x02, 0x00, 0x1A, 0x7E,
+            // Start Measurement response
+            0x7E, 0x00, 0x00, 0x00, 0x00, 0xFF, 0x7E, // Stop Measurement response
+            0x7E, 0x00, 0x01, 0x00, 0x00, 0xFE, 0x7E,
+        ];
+        let mut sensor = Sps30::new(Replay { rx });
+        let session = sensor.start_measurement_session(MeasurementFormat::UnsignedInteger16);
+        assert!(session.is_ok());
+        drop(session);
+        assert_eq!(sensor.format, MeasurementFormat::UnsignedInteger16);
+        assert!(sensor.serial_mut().rx.is_empty());
+    }
+
+    #[test]
+    fn sleep_needs_firmware_2() {
+        let rx = &[
+            // Read Version response, firmware 1.0
+            0x7E, 0x00, 0xD1, 0x00, 0x07, 0x01, 0x00, 0x00, 0x07, 0x00, 0x02, 0x00, 0x1D, 0x7E,
+        ];
+        let (error, _) = Sps30::new(Replay { rx }).sleep().err().unwrap();
+        assert!(matches!(
+            error,
+            Error::UnsupportedFirmware {
+                required: FIRMWARE_2_0,
+                ..
+            }
+        ));
+    }
+
+    #[cfg(not(feature = "no-float"))]
+    #[test]
+    fn first_measurement_times_out() {
+        let rx = &[
+            // Start Measurement response
+            0x7E, 0x00, 0x00, 0x00, 0x00, 0xFF, 0x7E,
+            // Read Measured Values responses without data
+            0x7E, 0x00, 0x03, 0x00, 0x00, 0xFC, 0x7E, 0x7E, 0x00, 0x03, 0x00, 0x00, 0xFC, 0x7E,
+            0x7E, 0x00, 0x03, 0x00, 0x00, 0xFC, 0x7E,
+        ];
+        let mut sensor = Sps30::new(Replay { rx })
+            .start_measurement(MeasurementFormat::Float)
+            .ok()
+            .unwrap();
+        let result = sensor.wait_for_first_measurement(&mut NoDelay, 200);
+        assert!(matches!(result, Err(Error::Timeout)));
+        assert_eq!(sensor.link_stats().frames_received, 4);
+    }
+
+    #[cfg(not(feature = "no-float"))]
+    #[test]
+    fn measurements_time_out() {
+        let mut sensor = Sps30::new(Acknowledge::default())
+            .start_measurement(MeasurementFormat::Float)
+            .ok()
+            .unwrap();
+        let result = sensor.measurements(NoDelay, 1000).next();
+        assert!(matches!(result, Some(Err(Error::Timeout))));
+        // Start Measurement, then a read every 100ms for 10s
+        assert_eq!(sensor.link_stats().frames_received, 102);
+    }
+
+    #[cfg(not(feature = "no-float"))]
+    #[test]
+    fn measure_blocking_times_out() {
+        let mut sensor = Sps30::new(Acknowledge::default());
+        let result = sensor.measure_blocking(&mut NoDelay);
+        assert!(matches!(result, Err(Error::Timeout)));
+        // Start Measurement, reads for 10s, Stop Measurement sent by the session
+        assert_eq!(sensor.link_stats().frames_received, 103);
+        assert_eq!(sensor.serial_mut().frame[2], 0x01);
+    }
+
+    #[cfg(not(feature = "no-float"))]
+    #[test]
+    fn duty_cycle_times_out() {
+        let cycle = DutyCycle {
+            warm_up_ms: 0,
+            sleep: false,
+            ..DutyCycle::default()
+        };
+        let mut duty_cycled = DutyCycled::new(Sps30::new(Acknowledge::default()), NoDelay, cycle);
+        assert!(matches!(duty_cycled.sample(), Err(Error::Timeout)));
+        let mut sensor = match duty_cycled.release() {
+            AnySps30::Idle(sensor) => sensor,
+            _ => panic!("measurement not stopped"),
+        };
+        // Start Measurement, reads for 10s, Stop Measurement
+        assert_eq!(sensor.link_stats().frames_received, 103);
+        assert_eq!(sensor.serial_mut().frame[2], 0x01);
+    }
+
+    #[cfg(not(feature = "no-float"))]
+    #[test]
+    fn warm_up_values_are_discarded() {
+        // Start Measurement response, then twice a Read Measured Values response with all
+        // values at 0
+        static RX: [u8; 7 + 2 * 47] = {
+            let mut rx = [0; 7 + 2 * 47];
+            let start = [0x7E, 0x00, 0x00, 0x00, 0x00, 0xFF, 0x7E];
+            let mut i = 0;
+            while i < start.len() {
+                rx[i] = start[i];
+                i += 1;
+            }
+            let mut frame = 7;
+            while frame < rx.len() {
+                rx[frame] = 0x7E;
+                rx[frame + 2] = 0x03;
+                rx[frame + 4] = 0x28;
+                rx[frame + 45] = 0xD4;
+                rx[frame + 46] = 0x7E;
+                frame += 47;
+            }
+            rx
+        };
+        let mut sensor = Sps30::builder(Replay { rx: &RX })
+            .warm_up_reads(1)
+            .discard_warm_up(true)
+            .build()
+            .start_measurement(MeasurementFormat::Float)
+            .ok()
+            .unwrap();
+        assert!(!sensor.is_warmed_up());
+        assert!(matches!(sensor.read_measurement(), Err(Error::EmptyResult)));
+        assert!(!sensor.is_warmed_up());
+        assert!(sensor.read_measurement().is_ok());
+        assert!(sensor.is_warmed_up());
+    }
+
+    #[cfg(all(feature = "fan-clean", not(feature = "no-float")))]
+    #[test]
+    fn cleaning_until_values_read() {
+        let rx = &[
+            // Start Measurement response
+            0x7E, 0x00, 0x00, 0x00, 0x00, 0xFF, 0x7E, // Start Fan Cleaning response
+            0x7E, 0x00, 0x56, 0x00, 0x00, 0xA9, 0x7E,
+            // Read Measured Values response without data
+            0x7E, 0x00, 0x03, 0x00, 0x00, 0xFC, 0x7E,
+        ];
+        let mut sensor = Sps30::new(Replay { rx })
+            .start_measurement(MeasurementFormat::Float)
+            .ok()
+            .unwrap();
+        assert!(!sensor.cleaning_in_progress());
+        assert!(sensor.start_fan_cleaning().is_ok());
+        assert!(sensor.cleaning_in_progress());
+        assert!(matches!(sensor.read_measurement(), Err(Error::EmptyResult)));
+        assert!(sensor.cleaning_in_progress());
+    }
+
+    #[cfg(feature = "device-info")]
+    #[test]
+    fn self_test_outcomes() {
+        let mut sensor = Sps30::new(Replay { rx: &[] });
+        assert_eq!(sensor.self_test(), SelfTest::NoResponse);
+
+        // Bytes pending before any command
+        let mut sensor = Sps30::new(Replay { rx: &[0x12, 0x34] });
+        assert_eq!(sensor.self_test(), SelfTest::Wiring);
+    }
+
+    #[cfg(not(feature = "no-float"))]
+    #[test]
+    fn recovers_after_link_errors() {
+        let rx = &[
+            // Start Measurement response
+            0x7E, 0x00, 0x00, 0x00, 0x00, 0xFF, 0x7E,
+            // Corrupted Read Measured Values response
+            0x7E, 0x00, 0x03, 0x00, 0x00, 0x00, 0x7E,
+        ];
+        let mut sensor = Sps30::builder(Replay { rx })
+            .recover_after(1)
+            .timeout(NoDelay)
+            .build()
+            .start_measurement(MeasurementFormat::Float)
+            .ok()
+            .unwrap();
+        assert!(matches!(
+            sensor.read_measurement(),
+            Err(Error::ChecksumFailed)
+        ));
+        let stats = sensor.link_stats();
+        assert_eq!(stats.recoveries, 1);
+        // Counters cleared by the recovery, then the Reset is not answered
+        assert_eq!(stats.consecutive_errors, 1);
+        assert_eq!(stats.timeouts, 1);
+
+        // Without timer, the device startup time can't be waited for
+        let mut sensor = Sps30::builder(Replay { rx })
+            .recover_after(1)
+            .build()
+            .start_measurement(MeasurementFormat::Float)
+            .ok()
+            .unwrap();
+        assert!(sensor.read_measurement().is_err());
+        let stats = sensor.link_stats();
+        assert_eq!(stats.recoveries, 0);
+        assert_eq!(stats.consecutive_errors, 1);
+    }
+
+    #[test]
+    fn flush_rx_drops_stale_bytes() {
+        let rx = &[0x7E, 0x00, 0x03, 0x7E];
+        let mut sensor = Sps30::new(Replay { rx });
+        assert_eq!(sensor.flush_rx().ok(), Some(4));
+        assert_eq!(sensor.flush_rx().ok(), Some(0));
+    }
+
+    #[cfg(feature = "debug-frames")]
+    #[test]
+    fn frame_formatter_sees_raw_frames() {
+        use core::sync::atomic::{AtomicUsize, Ordering};
+
+        static MISO_BYTES: AtomicUsize = AtomicUsize::new(0);
+        fn count_miso(direction: Direction, frame: &[u8]) {
+            if direction == Direction::Rx {
+                MISO_BYTES.fetch_add(frame.len(), Ordering::Relaxed);
+            }
+        }
+
+        let rx = &[0x7E, 0x00, 0x00, 0x00, 0x00, 0xFF, 0x7E];
+        let sensor = Sps30::builder(Replay { rx })
+            .frame_formatter(count_miso)
+            .build();
+        assert!(sensor.start_measurement(MeasurementFormat::Float).is_ok());
+        assert_eq!(MISO_BYTES.load(Ordering::Relaxed), 7);
+    }
+
+    #[test]
+    fn observer_sees_raw_frames() {
+        use core::sync::atomic::{AtomicUsize, Ordering};
+
+        /// Count of bytes seen in each direction
+        struct Counter {
+            tx: AtomicUsize,
+            rx: AtomicUsize,
+        }
+
+        impl FrameObserver for Counter {
+            fn on_tx(&self, frame: &[u8]) {
+                self.tx.fetch_add(frame.len(), Ordering::Relaxed);
+            }
+
+            fn on_rx(&self, frame: &[u8]) {
+                self.rx.fetch_add(frame.len(), Ordering::Relaxed);
+            }
+        }
+
+        static COUNTER: Counter = Counter {
+            tx: AtomicUsize::new(0),
+            rx: AtomicUsize::new(0),
+        };
+        let rx = &[0x7E, 0x00, 0x00, 0x00, 0x00, 0xFF, 0x7E];
+        let sensor = Sps30::builder(Replay { rx }).observer(&COUNTER).build();
+        assert!(sensor.start_measurement(MeasurementFormat::Float).is_ok());
+        assert_eq!(COUNTER.tx.load(Ordering::Relaxed), 8);
+        assert_eq!(COUNTER.rx.load(Ordering::Relaxed), 7);
+    }
+
+    #[test]
+    fn yields_while_waiting() {
+        use core::sync::atomic::{AtomicU32, Ordering};
+
+        static YIELDS: AtomicU32 = AtomicU32::new(0);
+        fn yield_now() {
+            YIELDS.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let wait = WaitStrategy::Yield {
+            yield_now,
+            polls: 3,
+        };
+        let sensor = Sps30::builder(Replay { rx: &[] }).wait(wait).build();
+        let (error, _) = sensor
+            .start_measurement(MeasurementFormat::Float)
+            .err()
+            .unwrap();
+        assert!(matches!(error, Error::Timeout));
+        assert_eq!(YIELDS.load(Ordering::Relaxed), 3);
+    }
 }
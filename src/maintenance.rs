@@ -0,0 +1,193 @@
+//! Fan cleaning interval and schedule
+
+#[cfg(feature = "fan-clean")]
+use crate::{Error, Measuring, Sps30, Transport};
+use core::time::Duration;
+#[cfg(feature = "fan-clean")]
+use embedded_hal::delay::DelayNs;
+
+/// Fan cleaning interval of the built-in auto cleaning, one week in s
+const DEFAULT_CLEANING_INTERVAL_S: u32 = 604_800;
+
+/// Interval of the built-in fan auto cleaning, in s
+///
+/// An interval of 0 disables the auto cleaning, see [`AutoCleanInterval::DISABLED`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AutoCleanInterval(u32);
+
+impl AutoCleanInterval {
+    /// Auto cleaning disabled
+    pub const DISABLED: AutoCleanInterval = AutoCleanInterval(0);
+    /// Interval set at the factory, one week
+    pub const DEFAULT: AutoCleanInterval = AutoCleanInterval(DEFAULT_CLEANING_INTERVAL_S);
+
+    /// Clean the fan every `seconds`, 0 disables the auto cleaning
+    pub const fn from_secs(seconds: u32) -> Self {
+        AutoCleanInterval(seconds)
+    }
+
+    /// Interval in s, 0 when disabled
+    pub const fn as_secs(&self) -> u32 {
+        self.0
+    }
+
+    /// Whether the auto cleaning is disabled
+    pub const fn is_disabled(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Convert `duration`, fractions of a second are dropped and longer intervals than
+    /// `u32::MAX` s are capped
+    pub fn from_duration(duration: Duration) -> Self {
+        AutoCleanInterval(duration.as_secs().min(u64::from(u32::MAX)) as u32)
+    }
+
+    /// Interval as a [`Duration`], zero when disabled
+    pub fn as_duration(&self) -> Duration {
+        Duration::from_secs(u64::from(self.0))
+    }
+}
+
+impl Default for AutoCleanInterval {
+    fn default() -> Self {
+        AutoCleanInterval::DEFAULT
+    }
+}
+
+impl From<AutoCleanInterval> for Duration {
+    fn from(interval: AutoCleanInterval) -> Self {
+        interval.as_duration()
+    }
+}
+
+#[cfg(feature = "fugit")]
+impl From<fugit::SecsDurationU32> for AutoCleanInterval {
+    fn from(duration: fugit::SecsDurationU32) -> Self {
+        AutoCleanInterval(duration.ticks())
+    }
+}
+
+#[cfg(feature = "fugit")]
+impl From<AutoCleanInterval> for fugit::SecsDurationU32 {
+    fn from(interval: AutoCleanInterval) -> Self {
+        fugit::SecsDurationU32::secs(interval.0)
+    }
+}
+
+/// Start fan cleaning every interval of fan-on time
+///
+/// The built-in auto cleaning counts time since power-up, so a device powered down
+/// between measure cycles may never clean its fan. Instead, the application reports the
+/// time spent measuring with [`CleaningSchedule::tick`] and cleaning starts once the fan
+/// ran for the whole interval, whatever the power-downs in between. The state can be
+/// persisted, e.g. with the `serde` feature, to survive resets of the application.
+///
+/// The built-in auto cleaning can then be disabled with
+/// [`Sps30::disable_auto_cleaning`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CleaningSchedule {
+    /// Fan-on time between two cleanings, in ms
+    interval_ms: u64,
+    /// Fan-on time since the last cleaning, in ms
+    fan_on_ms: u64,
+}
+
+impl Default for CleaningSchedule {
+    fn default() -> Self {
+        CleaningSchedule::new(DEFAULT_CLEANING_INTERVAL_S)
+    }
+}
+
+impl CleaningSchedule {
+    /// Clean the fan every `interval_s` of fan-on time, one week by default
+    pub fn new(interval_s: u32) -> Self {
+        CleaningSchedule {
+            interval_ms: u64::from(interval_s) * 1000,
+            fan_on_ms: 0,
+        }
+    }
+
+    /// Fan-on time since the last cleaning, in s
+    pub fn fan_on_s(&self) -> u64 {
+        self.fan_on_ms / 1000
+    }
+
+    /// Count `elapsed_ms` of fan-on time
+    pub fn add_fan_on_time(&mut self, elapsed_ms: u32) {
+        self.fan_on_ms = self.fan_on_ms.saturating_add(u64::from(elapsed_ms));
+    }
+
+    /// Whether the fan ran for the whole interval since the last cleaning
+    pub fn is_due(&self) -> bool {
+        self.fan_on_ms >= self.interval_ms
+    }
+
+    #[cfg(feature = "fan-clean")]
+    /// Count `elapsed_ms` spent measuring and start fan cleaning if due
+    ///
+    /// Return whether cleaning was started. If starting it fails, it is attempted again on
+    /// the next tick.
+    pub fn tick<SERIAL, DELAY, E, F>(
+        &mut self,
+        sps30: &mut Sps30<SERIAL, Measuring, DELAY>,
+        elapsed_ms: u32,
+    ) -> Result<bool, Error<E, F>>
+    where
+        SERIAL: Transport<WriteError = E, ReadError = F>,
+        DELAY: DelayNs,
+    {
+        self.add_fan_on_time(elapsed_ms);
+        if !self.is_due() {
+            return Ok(false);
+        }
+        sps30.start_fan_cleaning()?;
+        self.fan_on_ms = 0;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn auto_clean_interval_conversions() {
+        assert!(AutoCleanInterval::from_secs(0).is_disabled());
+        assert_eq!(AutoCleanInterval::default().as_secs(), 604_800);
+        assert_eq!(
+            AutoCleanInterval::from_duration(Duration::from_millis(3_500)),
+            AutoCleanInterval::from_secs(3)
+        );
+        assert_eq!(
+            AutoCleanInterval::from_duration(Duration::from_secs(u64::MAX)).as_secs(),
+            u32::MAX
+        );
+        assert_eq!(
+            Duration::from(AutoCleanInterval::from_secs(60)),
+            Duration::from_secs(60)
+        );
+    }
+
+    #[test]
+    fn due_after_interval_of_fan_on_time() {
+        let mut schedule = CleaningSchedule::new(10);
+        schedule.add_fan_on_time(6_000);
+        assert!(!schedule.is_due());
+        schedule.add_fan_on_time(4_000);
+        assert!(schedule.is_due());
+        assert_eq!(schedule.fan_on_s(), 10);
+    }
+
+    #[test]
+    fn default_interval_is_one_week() {
+        let mut schedule = CleaningSchedule::default();
+        schedule.add_fan_on_time(604_799_999);
+        assert!(!schedule.is_due());
+        schedule.add_fan_on_time(1);
+        assert!(schedule.is_due());
+    }
+}
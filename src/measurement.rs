@@ -0,0 +1,393 @@
+//! Measured values
+
+use crate::{protocol, CommandType, Error};
+
+/// Output format of measured values, selected when starting measurement
+#[repr(u8)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MeasurementFormat {
+    /// Big-endian IEEE754 float values, read with `read_measurement`
+    #[default]
+    Float = 0x03,
+    /// Big-endian unsigned 16 bit integer values (firmware 2.0 or newer), read with
+    /// `read_measurement_u16`
+    UnsignedInteger16 = 0x05,
+}
+
+/// Measured values, as returned by Read Measured Values
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Measurement {
+    /// Mass Concentration PM1.0 [μg/m³]
+    pub mass_pm1_0: f32,
+    /// Mass Concentration PM2.5 [μg/m³]
+    pub mass_pm2_5: f32,
+    /// Mass Concentration PM4.0 [μg/m³]
+    pub mass_pm4_0: f32,
+    /// Mass Concentration PM10 [μg/m³]
+    pub mass_pm10: f32,
+    /// Number Concentration PM0.5 [#/cm³]
+    pub nc_pm0_5: f32,
+    /// Number Concentration PM1.0 [#/cm³]
+    pub nc_pm1_0: f32,
+    /// Number Concentration PM2.5 [#/cm³]
+    pub nc_pm2_5: f32,
+    /// Number Concentration PM4.0 [#/cm³]
+    pub nc_pm4_0: f32,
+    /// Number Concentration PM10 [#/cm³]
+    pub nc_pm10: f32,
+    /// Typical Particle Size [μm]
+    pub typical_particle_size: f32,
+}
+
+/// Measured quantity, one per field of [`Measurement`]
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MeasurementKind {
+    /// Mass Concentration PM1.0 [μg/m³]
+    MassPm1_0,
+    /// Mass Concentration PM2.5 [μg/m³]
+    MassPm2_5,
+    /// Mass Concentration PM4.0 [μg/m³]
+    MassPm4_0,
+    /// Mass Concentration PM10 [μg/m³]
+    MassPm10,
+    /// Number Concentration PM0.5 [#/cm³]
+    NumberPm0_5,
+    /// Number Concentration PM1.0 [#/cm³]
+    NumberPm1_0,
+    /// Number Concentration PM2.5 [#/cm³]
+    NumberPm2_5,
+    /// Number Concentration PM4.0 [#/cm³]
+    NumberPm4_0,
+    /// Number Concentration PM10 [#/cm³]
+    NumberPm10,
+    /// Typical Particle Size [μm]
+    TypicalParticleSize,
+}
+
+impl MeasurementKind {
+    /// All kinds, in the order the device sends them
+    pub const ALL: [MeasurementKind; 10] = [
+        MeasurementKind::MassPm1_0,
+        MeasurementKind::MassPm2_5,
+        MeasurementKind::MassPm4_0,
+        MeasurementKind::MassPm10,
+        MeasurementKind::NumberPm0_5,
+        MeasurementKind::NumberPm1_0,
+        MeasurementKind::NumberPm2_5,
+        MeasurementKind::NumberPm4_0,
+        MeasurementKind::NumberPm10,
+        MeasurementKind::TypicalParticleSize,
+    ];
+
+    /// Position of the value in [`Measurement::to_array`]
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// Unit of the value, for float format measurements
+    pub fn unit(self) -> &'static str {
+        match self {
+            MeasurementKind::MassPm1_0
+            | MeasurementKind::MassPm2_5
+            | MeasurementKind::MassPm4_0
+            | MeasurementKind::MassPm10 => "μg/m³",
+            MeasurementKind::NumberPm0_5
+            | MeasurementKind::NumberPm1_0
+            | MeasurementKind::NumberPm2_5
+            | MeasurementKind::NumberPm4_0
+            | MeasurementKind::NumberPm10 => "#/cm³",
+            MeasurementKind::TypicalParticleSize => "μm",
+        }
+    }
+
+    /// Upper bound of the datasheet range of the value
+    fn range_max(self) -> f32 {
+        match self {
+            MeasurementKind::MassPm1_0
+            | MeasurementKind::MassPm2_5
+            | MeasurementKind::MassPm4_0
+            | MeasurementKind::MassPm10 => MAX_MASS_CONCENTRATION,
+            MeasurementKind::NumberPm0_5
+            | MeasurementKind::NumberPm1_0
+            | MeasurementKind::NumberPm2_5
+            | MeasurementKind::NumberPm4_0
+            | MeasurementKind::NumberPm10 => MAX_NUMBER_CONCENTRATION,
+            MeasurementKind::TypicalParticleSize => MAX_PARTICLE_SIZE,
+        }
+    }
+}
+
+/// Upper bound of the mass concentration measurement range, in μg/m³
+const MAX_MASS_CONCENTRATION: f32 = 1000.0;
+
+/// Upper bound of the number concentration measurement range, in #/cm³
+const MAX_NUMBER_CONCENTRATION: f32 = 3000.0;
+
+/// Upper bound of the particle size range, in μm
+const MAX_PARTICLE_SIZE: f32 = 10.0;
+
+/// Implausible values of a [`Measurement`], see [`Measurement::plausibility`]
+///
+/// Bit `i` is set if the `i`-th value of [`Measurement::to_array`] is implausible.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Plausibility(pub u16);
+
+impl Plausibility {
+    /// All values are plausible
+    pub fn is_plausible(self) -> bool {
+        self.0 == 0
+    }
+
+    /// The `index`-th value, in the order of [`Measurement::to_array`], is plausible
+    pub fn is_value_plausible(self, index: usize) -> bool {
+        index >= 10 || self.0 & (1 << index) == 0
+    }
+}
+
+impl Measurement {
+    /// Check the values are within the datasheet ranges
+    ///
+    /// NaN, negative values, mass concentrations above 1000 μg/m³, number concentrations
+    /// above 3000 #/cm³ and typical particle sizes above 10 μm are implausible, e.g. the
+    /// result of a bit flip or a confused device.
+    pub fn plausibility(&self) -> Plausibility {
+        let bits = self
+            .to_array()
+            .iter()
+            .enumerate()
+            .filter(|&(index, &value)| {
+                let max = MeasurementKind::ALL[index].range_max();
+                // Also false for NaN
+                !(0.0..=max).contains(&value)
+            })
+            .fold(0, |bits, (index, _)| bits | 1 << index);
+        Plausibility(bits)
+    }
+
+    /// Convert the payload of a Read Measured Values response in float format
+    ///
+    /// `bytes` holds the 10 values as big-endian IEEE754 floats, in the order the device
+    /// sends them.
+    pub fn from_bytes(bytes: &[u8; 40]) -> Self {
+        let mut measurement = Measurement::default();
+        measurement.set_from_bytes(bytes);
+        measurement
+    }
+
+    /// Same as [`Measurement::from_bytes`], overwriting the values of `self` in place
+    pub fn set_from_bytes(&mut self, bytes: &[u8; 40]) {
+        for (value, chunk) in self.values_mut().iter_mut().zip(bytes.chunks_exact(4)) {
+            **value = f32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        }
+    }
+
+    /// Mutable references to the values, in the order the device sends them
+    fn values_mut(&mut self) -> [&mut f32; 10] {
+        [
+            &mut self.mass_pm1_0,
+            &mut self.mass_pm2_5,
+            &mut self.mass_pm4_0,
+            &mut self.mass_pm10,
+            &mut self.nc_pm0_5,
+            &mut self.nc_pm1_0,
+            &mut self.nc_pm2_5,
+            &mut self.nc_pm4_0,
+            &mut self.nc_pm10,
+            &mut self.typical_particle_size,
+        ]
+    }
+
+    /// Convert a complete Read Measured Values MISO Frame in float format
+    ///
+    /// `frame` is the frame as read from the wire, start and stop flags included, it is
+    /// checked as by [`parse_response`](crate::protocol::parse_response).
+    pub fn from_frame<E, F>(frame: &[u8]) -> Result<Self, Error<E, F>> {
+        let response = protocol::parse_response(frame, CommandType::ReadMeasuredData)?;
+        protocol::parse_measurement(response.data())
+    }
+
+    /// Value of `kind`
+    pub fn get(&self, kind: MeasurementKind) -> f32 {
+        self.to_array()[kind.index()]
+    }
+
+    /// Kinds and values, in the order the device sends them
+    pub fn iter(&self) -> impl Iterator<Item = (MeasurementKind, f32)> {
+        let values = self.to_array();
+        MeasurementKind::ALL
+            .iter()
+            .map(move |&kind| (kind, values[kind.index()]))
+    }
+
+    /// Values in the order the device sends them
+    pub fn to_array(&self) -> [f32; 10] {
+        [
+            self.mass_pm1_0,
+            self.mass_pm2_5,
+            self.mass_pm4_0,
+            self.mass_pm10,
+            self.nc_pm0_5,
+            self.nc_pm1_0,
+            self.nc_pm2_5,
+            self.nc_pm4_0,
+            self.nc_pm10,
+            self.typical_particle_size,
+        ]
+    }
+}
+
+/// Measured values in integer format, as returned by Read Measured Values
+///
+/// Read after starting measurement with [`MeasurementFormat::UnsignedInteger16`]
+/// (firmware 2.0 or newer). Values are rounded to integers: mass concentrations are in
+/// μg/m³ and number concentrations in #/cm³ like [`Measurement`], but the typical particle
+/// size is in nm instead of μm.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MeasurementU16 {
+    /// Mass Concentration PM1.0 [μg/m³]
+    pub mass_pm1_0: u16,
+    /// Mass Concentration PM2.5 [μg/m³]
+    pub mass_pm2_5: u16,
+    /// Mass Concentration PM4.0 [μg/m³]
+    pub mass_pm4_0: u16,
+    /// Mass Concentration PM10 [μg/m³]
+    pub mass_pm10: u16,
+    /// Number Concentration PM0.5 [#/cm³]
+    pub nc_pm0_5: u16,
+    /// Number Concentration PM1.0 [#/cm³]
+    pub nc_pm1_0: u16,
+    /// Number Concentration PM2.5 [#/cm³]
+    pub nc_pm2_5: u16,
+    /// Number Concentration PM4.0 [#/cm³]
+    pub nc_pm4_0: u16,
+    /// Number Concentration PM10 [#/cm³]
+    pub nc_pm10: u16,
+    /// Typical Particle Size [nm]
+    pub typical_particle_size: u16,
+}
+
+impl MeasurementU16 {
+    /// Convert the payload of a Read Measured Values response in integer format
+    ///
+    /// `bytes` holds the 10 values as big-endian unsigned 16 bit integers, in the order the
+    /// device sends them.
+    pub fn from_bytes(bytes: &[u8; 20]) -> Self {
+        let mut values = [0; 10];
+        for (value, chunk) in values.iter_mut().zip(bytes.chunks_exact(2)) {
+            *value = u16::from_be_bytes([chunk[0], chunk[1]]);
+        }
+        MeasurementU16::from(values)
+    }
+
+    /// Values in the order the device sends them
+    pub fn to_array(&self) -> [u16; 10] {
+        [
+            self.mass_pm1_0,
+            self.mass_pm2_5,
+            self.mass_pm4_0,
+            self.mass_pm10,
+            self.nc_pm0_5,
+            self.nc_pm1_0,
+            self.nc_pm2_5,
+            self.nc_pm4_0,
+            self.nc_pm10,
+            self.typical_particle_size,
+        ]
+    }
+}
+
+impl From<[u16; 10]> for MeasurementU16 {
+    fn from(v: [u16; 10]) -> Self {
+        MeasurementU16 {
+            mass_pm1_0: v[0],
+            mass_pm2_5: v[1],
+            mass_pm4_0: v[2],
+            mass_pm10: v[3],
+            nc_pm0_5: v[4],
+            nc_pm1_0: v[5],
+            nc_pm2_5: v[6],
+            nc_pm4_0: v[7],
+            nc_pm10: v[8],
+            typical_particle_size: v[9],
+        }
+    }
+}
+
+impl From<MeasurementU16> for [u16; 10] {
+    fn from(m: MeasurementU16) -> Self {
+        m.to_array()
+    }
+}
+
+impl From<[f32; 10]> for Measurement {
+    fn from(v: [f32; 10]) -> Self {
+        Measurement {
+            mass_pm1_0: v[0],
+            mass_pm2_5: v[1],
+            mass_pm4_0: v[2],
+            mass_pm10: v[3],
+            nc_pm0_5: v[4],
+            nc_pm1_0: v[5],
+            nc_pm2_5: v[6],
+            nc_pm4_0: v[7],
+            nc_pm10: v[8],
+            typical_particle_size: v[9],
+        }
+    }
+}
+
+impl From<Measurement> for [f32; 10] {
+    fn from(m: Measurement) -> Self {
+        m.to_array()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn implausible_values_are_flagged() {
+        let mut measurement = Measurement::from([1.0; 10]);
+        assert!(measurement.plausibility().is_plausible());
+
+        measurement.mass_pm2_5 = f32::NAN;
+        measurement.nc_pm0_5 = -1.0;
+        measurement.typical_particle_size = 11.0;
+        let plausibility = measurement.plausibility();
+        assert_eq!(plausibility, Plausibility(0b10_0001_0010));
+        assert!(!plausibility.is_value_plausible(1));
+        assert!(plausibility.is_value_plausible(0));
+    }
+
+    #[test]
+    fn integer_format_payload() {
+        let mut bytes = [0; 20];
+        bytes[2..4].copy_from_slice(&12u16.to_be_bytes());
+        bytes[18..].copy_from_slice(&650u16.to_be_bytes());
+        let measurement = MeasurementU16::from_bytes(&bytes);
+        assert_eq!(measurement.mass_pm2_5, 12);
+        assert_eq!(measurement.typical_particle_size, 650);
+    }
+
+    #[test]
+    fn values_by_kind() {
+        let measurement = Measurement::from([0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0]);
+        assert_eq!(measurement.get(MeasurementKind::MassPm2_5), 1.0);
+        assert_eq!(measurement.get(MeasurementKind::TypicalParticleSize), 9.0);
+        assert!(measurement
+            .iter()
+            .all(|(kind, value)| value == kind.index() as f32));
+    }
+}
@@ -0,0 +1,24 @@
+//! Hook on the frames sent and received by the UART driver
+
+/// Observer of the raw traffic of the [`Sps30`](crate::Sps30) driver
+///
+/// Set it with [`Sps30Builder::observer`](crate::Sps30Builder::observer) or
+/// [`Config::observer`](crate::Config::observer) to mirror frames to RTT, a second UART or a
+/// black-box buffer. Frames are passed SHDLC encoded, start and stop flags included, as
+/// they are on the wire. Both methods do nothing by default.
+///
+/// Methods take `&self`, as observers are usually statics: use atomics, cells or a
+/// critical section to keep state.
+pub trait FrameObserver {
+    /// MOSI Frame about to be sent
+    fn on_tx(&self, _frame: &[u8]) {}
+
+    /// MISO Frame received, before it is checked
+    fn on_rx(&self, _frame: &[u8]) {}
+}
+
+impl core::fmt::Debug for dyn FrameObserver {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("FrameObserver")
+    }
+}
@@ -0,0 +1,790 @@
+//! Transport independent SHDLC protocol core
+//!
+//! Everything needed to talk to the device over UART, working on byte slices only:
+//! build MOSI Frames with [`encode_request`], check the MISO Frames received back with
+//! [`parse_response`] and interpret their data with the `parse_*` functions.
+//!
+//! The drivers are built on this module. Use it directly to drive the sensor over DMA,
+//! RTOS message queues or any other custom transport. [`encode_request_into`] and
+//! [`parse_response_into`] work on caller-provided buffers, e.g. memory owned by a DMA
+//! channel, without any intermediate buffer.
+
+use crate::hdlc::{self, fixed_frame, HDLCError, SpecialChars};
+use crate::{
+    CommandType, DeviceError, DeviceStatus, Error, InfoString, Measurement, MeasurementU16,
+    Version, Versions,
+};
+use arrayvec::ArrayVec;
+
+/// Largest data carried by a frame: the 40 bytes of measured values in float format
+pub const MAX_DATA_LEN: usize = 40;
+
+/// Capacity of decoded frame buffers
+///
+/// ADR, CMD, State, L and checksum around up to [`MAX_DATA_LEN`] data bytes need 45 bytes.
+pub const FRAME_SIZE: usize = 48;
+
+/// Capacity of SHDLC encoded frame buffers
+///
+/// Start and stop flags around up to [`FRAME_SIZE`] bytes, each one possibly byte-stuffed
+/// into two, need 98 bytes.
+pub const ENCODED_FRAME_SIZE: usize = 100;
+
+/// Checksum implemented as per section 4.1 from spec
+pub fn compute_cksum(data: &[u8]) -> u8 {
+    let mut cksum: u8 = 0;
+    for &byte in data.iter() {
+        let val: u16 = cksum as u16 + byte as u16;
+        let lsb = val % 256;
+        cksum = lsb as u8;
+    }
+
+    255 - cksum
+}
+
+/// Direction of a frame on the UART, seen from the host
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Direction {
+    /// MOSI Frame, sent to the device
+    Tx,
+    /// MISO Frame, received from the device
+    Rx,
+}
+
+/// How strictly MISO Frames are checked
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Validation {
+    /// Checksum, State, CMD and L(ength) fields are all verified
+    #[default]
+    Strict,
+    /// Only checksum and State are verified, CMD and L(ength) fields are not
+    Lenient,
+}
+
+/// Build the MOSI Frame for `cmd_type` carrying `data`
+///
+/// Result is SHDLC encoded, start and stop flags included, ready to be sent.
+/// Fails with [`Error::BufferTooSmall`] if `data` is longer than [`MAX_DATA_LEN`].
+pub fn encode_request<E, F>(
+    cmd_type: CommandType,
+    data: &[u8],
+) -> Result<ArrayVec<[u8; ENCODED_FRAME_SIZE]>, Error<E, F>> {
+    encode_request_with(cmd_type, data, SpecialChars::default())
+}
+
+/// Same as [`encode_request`], using `s_chars` for SHDLC encoding
+pub fn encode_request_with<E, F>(
+    cmd_type: CommandType,
+    data: &[u8],
+    s_chars: SpecialChars,
+) -> Result<ArrayVec<[u8; ENCODED_FRAME_SIZE]>, Error<E, F>> {
+    encode_raw_request(0x00, cmd_type as u8, data, s_chars)
+}
+
+/// Same as [`encode_request_with`] for any command byte `cmd`, e.g. an undocumented one,
+/// sent to the device at `address` (0x00 for the SPS30)
+pub fn encode_raw_request<E, F>(
+    address: u8,
+    cmd: u8,
+    data: &[u8],
+    s_chars: SpecialChars,
+) -> Result<ArrayVec<[u8; ENCODED_FRAME_SIZE]>, Error<E, F>> {
+    if data.len() > MAX_DATA_LEN {
+        return Err(Error::BufferTooSmall);
+    }
+
+    let mut encoded = ArrayVec::from([0; ENCODED_FRAME_SIZE]);
+    let len = build_frame(address, cmd, data, s_chars, &mut encoded)?;
+    encoded.truncate(len);
+    Ok(encoded)
+}
+
+/// SHDLC encode the MOSI Frame of `cmd` carrying `data` to `address` into `buf`
+///
+/// Shared by all commands, the checksum is computed while the bytes are stuffed.
+fn build_frame<E, F>(
+    address: u8,
+    cmd: u8,
+    data: &[u8],
+    s_chars: SpecialChars,
+    buf: &mut [u8],
+) -> Result<usize, Error<E, F>> {
+    let header = [address, cmd, data.len() as u8];
+    hdlc::encode_frame(&header, data, s_chars, buf).map_err(|e| match e {
+        HDLCError::TooMuchData => Error::BufferTooSmall,
+        e => Error::Encode(e),
+    })
+}
+
+/// Start Measurement MOSI Frame, float format, SHDLC encoded
+///
+/// Like the other pre-encoded frames, for the device at address 0x00 with the default
+/// special characters: ready to be handed to a DMA TX channel as is.
+pub const START_MEASUREMENT_FLOAT: [u8; 8] = fixed_frame(0x00, &[0x01, 0x03]);
+
+/// Start Measurement MOSI Frame, unsigned 16-bit integer format, SHDLC encoded
+pub const START_MEASUREMENT_U16: [u8; 8] = fixed_frame(0x00, &[0x01, 0x05]);
+
+/// Stop Measurement MOSI Frame, SHDLC encoded
+pub const STOP_MEASUREMENT: [u8; 6] = fixed_frame(0x01, &[]);
+
+/// Read Measured Values MOSI Frame, SHDLC encoded
+pub const READ_MEASURED_VALUES: [u8; 6] = fixed_frame(0x03, &[]);
+
+/// Start Fan Cleaning MOSI Frame, SHDLC encoded
+pub const START_FAN_CLEANING: [u8; 6] = fixed_frame(0x56, &[]);
+
+/// Device Reset MOSI Frame, SHDLC encoded
+pub const RESET: [u8; 6] = fixed_frame(0xD3, &[]);
+
+/// Pre-encoded MOSI Frame of command byte `cmd` carrying `data` to `address`, if any
+///
+/// Same bytes as [`encode_raw_request`] returns, without encoding at runtime.
+pub fn pre_encoded(
+    address: u8,
+    cmd: u8,
+    data: &[u8],
+    s_chars: SpecialChars,
+) -> Option<&'static [u8]> {
+    if address != 0x00 || !hdlc::same_chars(s_chars, SpecialChars::default()) {
+        return None;
+    }
+    let frame: &[u8] = match (cmd, data) {
+        (0x00, [0x01, 0x03]) => &START_MEASUREMENT_FLOAT,
+        (0x00, [0x01, 0x05]) => &START_MEASUREMENT_U16,
+        (0x01, []) => &STOP_MEASUREMENT,
+        (0x03, []) => &READ_MEASURED_VALUES,
+        (0x56, []) => &START_FAN_CLEANING,
+        (0xD3, []) => &RESET,
+        _ => return None,
+    };
+    Some(frame)
+}
+
+/// Build the MOSI Frame for `cmd_type` carrying `data` into `buf`
+///
+/// Same as [`encode_request_with`], without any intermediate buffer. Returns the number of
+/// bytes written to `buf`, [`Error::BufferTooSmall`] if the frame does not fit.
+pub fn encode_request_into<E, F>(
+    cmd_type: CommandType,
+    data: &[u8],
+    s_chars: SpecialChars,
+    buf: &mut [u8],
+) -> Result<usize, Error<E, F>> {
+    build_frame(0x00, cmd_type as u8, data, s_chars, buf)
+}
+
+/// MISO Frame, SHDLC decoded and checked by [`parse_response`]
+#[derive(Debug, Clone)]
+pub struct ResponseFrame {
+    /// Decoded frame: ADR, CMD, State, L, data and checksum
+    frame: ArrayVec<[u8; FRAME_SIZE]>,
+}
+
+impl ResponseFrame {
+    /// Command the device responded to
+    pub fn command(&self) -> u8 {
+        self.frame.get(1).copied().unwrap_or_default()
+    }
+
+    /// Data carried by the response, empty for most commands
+    pub fn data(&self) -> &[u8] {
+        let end = self.frame.len().saturating_sub(1);
+        self.frame.get(4..end).unwrap_or_default()
+    }
+}
+
+/// SHDLC decoded frame with a valid checksum, produced by [`FrameParser`]
+///
+/// MOSI and MISO Frames are both accepted, use [`Frame::check`] to verify a MISO Frame
+/// is a successful response to a command.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    /// Decoded frame, checksum included
+    frame: ArrayVec<[u8; FRAME_SIZE]>,
+}
+
+impl Frame {
+    /// Decoded bytes of the frame, from ADR up to and including the checksum
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.frame
+    }
+
+    /// Check this MISO Frame is a successful response to `cmd_type`
+    pub fn check<E, F>(self, cmd_type: CommandType) -> Result<ResponseFrame, Error<E, F>> {
+        check_miso_frame(&self.frame, 0x00, cmd_type as u8, Validation::Strict)?;
+        Ok(ResponseFrame { frame: self.frame })
+    }
+}
+
+/// Push style frame parser
+///
+/// Feed it received bytes one at a time, e.g. from an UART RX interrupt, and it hands back
+/// every complete frame once its stop flag is seen. Bytes received before a start flag are
+/// discarded, frames failing SHDLC decode or checksum verification are dropped.
+#[derive(Debug, Default)]
+pub struct FrameParser {
+    /// Raw bytes of the frame being received, start flag included
+    buffer: ArrayVec<[u8; ENCODED_FRAME_SIZE]>,
+}
+
+impl FrameParser {
+    /// Create a parser waiting for a start flag
+    pub fn new() -> Self {
+        FrameParser {
+            buffer: ArrayVec::new(),
+        }
+    }
+
+    /// Discard the frame being received, wait for a new start flag
+    pub fn reset(&mut self) {
+        self.buffer.clear();
+    }
+
+    /// Feed one received byte, returns a frame when `byte` completes one
+    pub fn feed_byte(&mut self, byte: u8) -> Option<Frame> {
+        let s_chars = SpecialChars::default();
+        match sync_byte(&mut self.buffer, byte, s_chars.fend, ENCODED_FRAME_SIZE) {
+            FrameSync::Complete => {
+                let mut frame = ArrayVec::new();
+                let complete = match decode_frame::<(), ()>(&mut self.buffer, s_chars) {
+                    Ok(decoded) => frame.try_extend_from_slice(decoded).is_ok(),
+                    Err(_) => false,
+                };
+                self.buffer.clear();
+                if complete {
+                    Some(Frame { frame })
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Result of feeding a byte to [`sync_byte`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FrameSync {
+    /// Byte received before a start flag, dropped
+    Discarded,
+    /// Frame still being received
+    Pending,
+    /// Byte is the stop flag, the buffer holds a complete frame
+    Complete,
+    /// Frame longer than allowed, dropped
+    Overflow,
+}
+
+/// Accumulate one received byte of a raw SHDLC frame into `buffer`
+///
+/// Start-flag/stop-flag state machine: bytes before a start flag are dropped, back to back
+/// flags restart the frame (the first one may be the stop flag of a previous frame) and a
+/// frame reaching `max_len` bytes is dropped.
+pub(crate) fn sync_byte(
+    buffer: &mut ArrayVec<[u8; ENCODED_FRAME_SIZE]>,
+    byte: u8,
+    fend: u8,
+    max_len: usize,
+) -> FrameSync {
+    if byte == fend {
+        if buffer.len() > 1 {
+            buffer.push(byte);
+            return FrameSync::Complete;
+        }
+
+        // Start flag, or back to back flags: (re)start the frame
+        buffer.clear();
+        buffer.push(byte);
+        FrameSync::Pending
+    } else if buffer.is_empty() {
+        FrameSync::Discarded
+    } else if buffer.len() + 1 >= max_len.min(ENCODED_FRAME_SIZE) {
+        // No room left for the stop flag
+        buffer.clear();
+        FrameSync::Overflow
+    } else {
+        buffer.push(byte);
+        FrameSync::Pending
+    }
+}
+
+/// SHDLC decode a frame in place and verify its checksum
+///
+/// Returns the decoded bytes, checksum included, at the beginning of `frame`.
+pub(crate) fn decode_frame<E, F>(
+    frame: &mut [u8],
+    s_chars: SpecialChars,
+) -> Result<&[u8], Error<E, F>> {
+    let len = hdlc::unstuff_in_place(frame, s_chars).map_err(Error::SHDLC)?;
+    let decoded = &frame[..len];
+    verify_cksum(decoded)?;
+    Ok(decoded)
+}
+
+/// Verify the checksum ending the decoded frame `decoded`
+fn verify_cksum<E, F>(decoded: &[u8]) -> Result<(), Error<E, F>> {
+    let (&cksum, content) = decoded.split_last().ok_or(Error::InvalidRespose)?;
+    if cksum != compute_cksum(content) {
+        return Err(Error::ChecksumFailed);
+    }
+    Ok(())
+}
+
+/// Perform checks on MISO Frame
+///  * lenght >=5
+///  * ADR must match sent MOSI Frame ADR (strict only)
+///  * CMD must match sent MOSI Frame CMD (strict only)
+///  * State should be 0 (No Error)
+///  * L(ength) must be valid (strict only)
+fn check_miso_frame<E, F>(
+    data: &[u8],
+    address: u8,
+    cmd_type: u8,
+    validation: Validation,
+) -> Result<&[u8], Error<E, F>> {
+    #[cfg(feature = "log")]
+    log::trace!("MISO {:02x?}", data);
+
+    let (adr, cmd, state, len) = match data {
+        [adr, cmd, state, len, _, ..] => (*adr, *cmd, *state, *len as usize),
+        _ => return Err(Error::InvalidRespose),
+    };
+
+    let strict = validation == Validation::Strict;
+    if strict && (adr != address || cmd != cmd_type) {
+        return Err(Error::InvalidRespose);
+    }
+    if state != 0 {
+        return Err(Error::StatusError(DeviceError::from(state)));
+    }
+
+    if strict && len != data.len() - 5 {
+        return Err(Error::InvalidRespose);
+    }
+
+    Ok(data)
+}
+
+/// Check the MISO Frame received in response to `cmd_type`
+///
+/// `frame` is the frame as read from the wire, start and stop flags included. It is SHDLC
+/// decoded, its checksum is verified and the frame is checked to be a successful response
+/// to `cmd_type`.
+pub fn parse_response<E, F>(
+    frame: &[u8],
+    cmd_type: CommandType,
+) -> Result<ResponseFrame, Error<E, F>> {
+    parse_response_with(frame, cmd_type, SpecialChars::default(), Validation::Strict)
+}
+
+/// Same as [`parse_response`], using `s_chars` for SHDLC decoding and checking the frame
+/// as required by `validation`
+pub fn parse_response_with<E, F>(
+    frame: &[u8],
+    cmd_type: CommandType,
+    s_chars: SpecialChars,
+    validation: Validation,
+) -> Result<ResponseFrame, Error<E, F>> {
+    parse_raw_response(frame, 0x00, cmd_type as u8, s_chars, validation)
+}
+
+/// Same as [`parse_response_with`] for any command byte `cmd`, e.g. an undocumented one,
+/// sent to the device at `address` (0x00 for the SPS30)
+pub fn parse_raw_response<E, F>(
+    frame: &[u8],
+    address: u8,
+    cmd: u8,
+    s_chars: SpecialChars,
+    validation: Validation,
+) -> Result<ResponseFrame, Error<E, F>> {
+    let mut buf = [0; FRAME_SIZE];
+    let len = hdlc::unstuff(frame, s_chars, &mut buf).map_err(|e| match e {
+        HDLCError::TooMuchData => Error::InvalidFrame,
+        e => Error::SHDLC(e),
+    })?;
+    verify_cksum(&buf[..len])?;
+    response_frame(&buf[..len], address, cmd, validation)
+}
+
+/// Same as [`parse_raw_response`], SHDLC decoding `frame` in place
+pub(crate) fn parse_raw_response_in_place<E, F>(
+    frame: &mut [u8],
+    address: u8,
+    cmd: u8,
+    s_chars: SpecialChars,
+    validation: Validation,
+) -> Result<ResponseFrame, Error<E, F>> {
+    let decoded = decode_frame(frame, s_chars)?;
+    response_frame(decoded, address, cmd, validation)
+}
+
+/// Check the decoded MISO Frame `decoded` is a response to `cmd` and keep it
+fn response_frame<E, F>(
+    decoded: &[u8],
+    address: u8,
+    cmd: u8,
+    validation: Validation,
+) -> Result<ResponseFrame, Error<E, F>> {
+    check_miso_frame(decoded, address, cmd, validation)?;
+    let mut frame = ArrayVec::new();
+    frame
+        .try_extend_from_slice(decoded)
+        .map_err(|_| Error::InvalidFrame)?;
+    Ok(ResponseFrame { frame })
+}
+
+/// Convert measured values (10 big-endian IEEE754 floats)
+///
+/// Empty data means no new measured values are available yet.
+pub fn parse_measurement<E, F>(data: &[u8]) -> Result<Measurement, Error<E, F>> {
+    parse_measurement_raw(data).map(|bytes| Measurement::from_bytes(&bytes))
+}
+
+/// Copy the payload of measured values in float format, without converting it
+///
+/// Empty data means no new measured values are available yet.
+pub fn parse_measurement_raw<E, F>(data: &[u8]) -> Result<[u8; 40], Error<E, F>> {
+    match data.len() {
+        40 => {
+            let mut bytes = [0; 40];
+            bytes.copy_from_slice(data);
+            Ok(bytes)
+        }
+        0 => Err(Error::EmptyResult),
+        _ => Err(Error::InvalidFrame),
+    }
+}
+
+/// Get the raw IEEE754 bit patterns of measured values (10 big-endian IEEE754 floats)
+///
+/// Integer only, no float operation is involved. Empty data means no new measured values
+/// are available yet.
+pub fn parse_measurement_bits<E, F>(data: &[u8]) -> Result<[u32; 10], Error<E, F>> {
+    let bytes = parse_measurement_raw(data)?;
+    let mut res: [u32; 10] = [0; 10];
+    for (item, chunk) in res.iter_mut().zip(bytes.chunks_exact(4)) {
+        *item = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+    }
+    Ok(res)
+}
+
+/// Convert measured values in integer format (10 big-endian unsigned 16 bit integers)
+///
+/// Empty data means no new measured values are available yet.
+pub fn parse_measurement_u16<E, F>(data: &[u8]) -> Result<MeasurementU16, Error<E, F>> {
+    match data.len() {
+        20 => {
+            let mut bytes = [0; 20];
+            bytes.copy_from_slice(data);
+            Ok(MeasurementU16::from_bytes(&bytes))
+        }
+        0 => Err(Error::EmptyResult),
+        _ => Err(Error::InvalidFrame),
+    }
+}
+
+/// Convert the auto cleaning interval, in seconds
+pub fn parse_cleaning_interval<E, F>(data: &[u8]) -> Result<u32, Error<E, F>> {
+    if data.len() != 4 {
+        return Err(Error::InvalidRespose);
+    }
+
+    let mut ret: u32 = 0;
+    for &byte in data.iter() {
+        ret = ret * 256 + byte as u32;
+    }
+    Ok(ret)
+}
+
+/// Copy the Device Information string, null terminated ASCII
+pub fn parse_device_info<E, F>(data: &[u8]) -> Result<[u8; 32], Error<E, F>> {
+    let mut ret: [u8; 32] = [0; 32];
+    if data.len() <= ret.len() {
+        ret[..data.len()].copy_from_slice(data);
+        return Ok(ret);
+    }
+    Err(Error::EmptyResult)
+}
+
+/// Convert the Device Information string, trimmed at the first NUL
+///
+/// Fails with [`Error::InvalidRespose`] if the string is longer than 32 bytes or not ASCII.
+pub fn parse_device_info_string<E, F>(data: &[u8]) -> Result<InfoString, Error<E, F>> {
+    let len = data
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(data.len());
+    let text = &data[..len];
+    if !text.is_ascii() {
+        return Err(Error::InvalidRespose);
+    }
+    let text = core::str::from_utf8(text).map_err(|_| Error::InvalidRespose)?;
+    let mut string = InfoString::new();
+    string.push_str(text).map_err(|_| Error::InvalidRespose)?;
+    Ok(string)
+}
+
+/// Get the versions out of Read Version data
+pub fn parse_versions<E, F>(data: &[u8]) -> Result<Versions, Error<E, F>> {
+    if data.len() != 7 {
+        return Err(Error::InvalidRespose);
+    }
+
+    Ok(Versions {
+        firmware: Version::new(data[0], data[1]),
+        hardware_revision: data[3],
+        shdlc: Version::new(data[5], data[6]),
+    })
+}
+
+/// Get the Device Status Register out of Read Device Status Register data
+///
+/// The register is followed by a reserved byte, ignored.
+pub fn parse_device_status<E, F>(data: &[u8]) -> Result<DeviceStatus, Error<E, F>> {
+    if data.len() != 5 {
+        return Err(Error::InvalidRespose);
+    }
+
+    Ok(DeviceStatus(u32::from_be_bytes([
+        data[0], data[1], data[2], data[3],
+    ])))
+}
+
+/// Check the MISO Frame received in response to `cmd_type`, decoding it into `buf`
+///
+/// Same as [`parse_response_with`], without any intermediate buffer. Returns the data
+/// carried by the response, borrowed from `buf`, [`Error::BufferTooSmall`] if the decoded
+/// frame does not fit.
+pub fn parse_response_into<'a, E, F>(
+    frame: &[u8],
+    cmd_type: CommandType,
+    s_chars: SpecialChars,
+    validation: Validation,
+    buf: &'a mut [u8],
+) -> Result<&'a [u8], Error<E, F>> {
+    let len = hdlc::unstuff(frame, s_chars, buf).map_err(|e| match e {
+        HDLCError::TooMuchData => Error::BufferTooSmall,
+        e => Error::SHDLC(e),
+    })?;
+    let decoded: &'a [u8] = &buf[..len];
+    verify_cksum(decoded)?;
+    check_miso_frame(decoded, 0x00, cmd_type as u8, validation)?;
+    decoded.get(4..len - 1).ok_or(Error::InvalidRespose)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pre_encoded_frames_match_encoder() {
+        let s_chars = SpecialChars::default();
+        let commands: [(CommandType, &[u8]); 6] = [
+            (CommandType::StartMeasurement, &[0x01, 0x03]),
+            (CommandType::StartMeasurement, &[0x01, 0x05]),
+            (CommandType::StopMeasurement, &[]),
+            (CommandType::ReadMeasuredData, &[]),
+            (CommandType::StartFanCleaning, &[]),
+            (CommandType::Reset, &[]),
+        ];
+        for &(cmd_type, data) in commands.iter() {
+            let cmd = cmd_type as u8;
+            let encoded = encode_raw_request::<(), ()>(0x00, cmd, data, s_chars).unwrap();
+            assert_eq!(pre_encoded(0x00, cmd, data, s_chars), Some(&encoded[..]));
+        }
+        assert_eq!(pre_encoded(0x01, 0x03, &[], s_chars), None);
+        assert_eq!(pre_encoded(0x00, 0x80, &[0x00], s_chars), None);
+    }
+
+    #[test]
+    fn start_measurement_round_trip() {
+        let request =
+            encode_request::<(), ()>(CommandType::StartMeasurement, &[0x01, 0x03]).unwrap();
+        assert_eq!(
+            &request[..],
+            &[0x7E, 0x00, 0x00, 0x02, 0x01, 0x03, 0xF9, 0x7E]
+        );
+
+        let miso = [0x7E, 0x00, 0x00, 0x00, 0x00, 0xFF, 0x7E];
+        let response = parse_response::<(), ()>(&miso, CommandType::StartMeasurement).unwrap();
+        assert_eq!(response.command(), 0x00);
+        assert!(response.data().is_empty());
+
+        let other_address = parse_raw_response::<(), ()>(
+            &miso,
+            0x01,
+            CommandType::StartMeasurement as u8,
+            SpecialChars::default(),
+            Validation::Strict,
+        );
+        assert!(matches!(other_address, Err(Error::InvalidRespose)));
+    }
+
+    #[test]
+    fn caller_provided_buffers() {
+        // 0x7E in data is byte-stuffed
+        let data = [0x00, 0x00, 0x00, 0x7E];
+        let mut buf = [0; 20];
+        let len = encode_request_into::<(), ()>(
+            CommandType::ReadWriteAutoCleaningInterval,
+            &data,
+            SpecialChars::default(),
+            &mut buf,
+        )
+        .unwrap();
+        let expected =
+            encode_request::<(), ()>(CommandType::ReadWriteAutoCleaningInterval, &data).unwrap();
+        assert_eq!(&buf[..len], &expected[..]);
+
+        let mut small = [0; 8];
+        assert!(matches!(
+            encode_request_into::<(), ()>(
+                CommandType::ReadWriteAutoCleaningInterval,
+                &data,
+                SpecialChars::default(),
+                &mut small,
+            ),
+            Err(Error::BufferTooSmall)
+        ));
+
+        let miso = [
+            0x7E, 0x00, 0x80, 0x00, 0x04, 0x00, 0x00, 0x00, 0x7D, 0x5E, 0xFD, 0x7E,
+        ];
+        let mut decoded = [0; 16];
+        let response = parse_response_into::<(), ()>(
+            &miso,
+            CommandType::ReadWriteAutoCleaningInterval,
+            SpecialChars::default(),
+            Validation::Strict,
+            &mut decoded,
+        )
+        .unwrap();
+        assert_eq!(response, &data);
+    }
+
+    #[test]
+    fn truncated_frames_do_not_panic() {
+        let mut miso = [0x00; 47];
+        miso[0] = 0x7E;
+        miso[46] = 0x7E;
+        miso[1..5].copy_from_slice(&[0x00, 0x03, 0x00, 40]);
+        miso[45] = compute_cksum(&miso[1..45]);
+        assert!(parse_response::<(), ()>(&miso, CommandType::ReadMeasuredData).is_ok());
+
+        let mut buf = [0; FRAME_SIZE];
+        for len in 0..miso.len() - 1 {
+            let mut truncated = [0x7E; 47];
+            truncated[..len].copy_from_slice(&miso[..len]);
+            let frame = &truncated[..len.max(1) + 1];
+            for validation in [Validation::Strict, Validation::Lenient].iter() {
+                let response = parse_response_with::<(), ()>(
+                    frame,
+                    CommandType::ReadMeasuredData,
+                    SpecialChars::default(),
+                    *validation,
+                );
+                if let Ok(response) = response {
+                    assert!(parse_measurement::<(), ()>(response.data()).is_err());
+                }
+                let _ = parse_response_into::<(), ()>(
+                    frame,
+                    CommandType::ReadMeasuredData,
+                    SpecialChars::default(),
+                    *validation,
+                    &mut buf,
+                );
+            }
+        }
+
+        for data in [&[][..], &[0x00], &[0x00; 6], &[0x00; 39], &[0x00; 41]].iter() {
+            assert!(parse_measurement::<(), ()>(data).is_err());
+            assert!(parse_measurement_u16::<(), ()>(data).is_err());
+            assert!(parse_cleaning_interval::<(), ()>(data).is_err());
+            assert!(parse_versions::<(), ()>(data).is_err());
+            assert!(parse_device_status::<(), ()>(data).is_err());
+        }
+        assert!(parse_device_info::<(), ()>(&[0x41; 33]).is_err());
+    }
+
+    #[test]
+    fn device_status_ignores_reserved_byte() {
+        let status = parse_device_status::<(), ()>(&[0x00, 0x20, 0x00, 0x10, 0xFF]).unwrap();
+        assert!(status.fan_speed_warning());
+        assert!(status.fan_error());
+        assert!(!status.laser_error());
+    }
+
+    #[test]
+    fn sync_handles_noise_and_back_to_back_flags() {
+        let mut buffer = ArrayVec::new();
+        let stream = [0x13, 0x7E, 0x7E, 0x00, 0x01, 0x7E];
+        let sync: ArrayVec<[FrameSync; 8]> = stream
+            .iter()
+            .map(|&byte| sync_byte(&mut buffer, byte, 0x7E, ENCODED_FRAME_SIZE))
+            .collect();
+        assert_eq!(
+            &sync[..],
+            &[
+                FrameSync::Discarded,
+                FrameSync::Pending,
+                FrameSync::Pending,
+                FrameSync::Pending,
+                FrameSync::Pending,
+                FrameSync::Complete
+            ]
+        );
+        assert_eq!(&buffer[..], &[0x7E, 0x00, 0x01, 0x7E]);
+
+        buffer.clear();
+        let sync: ArrayVec<[FrameSync; 8]> = [0x7E, 0x00, 0x01, 0x02, 0x03]
+            .iter()
+            .map(|&byte| sync_byte(&mut buffer, byte, 0x7E, 4))
+            .collect();
+        assert_eq!(sync[3], FrameSync::Overflow);
+        assert_eq!(sync[4], FrameSync::Discarded);
+    }
+
+    #[test]
+    fn measurement_from_frame() {
+        let mut miso = [0x00; 47];
+        miso[0] = 0x7E;
+        miso[46] = 0x7E;
+        miso[1..5].copy_from_slice(&[0x00, 0x03, 0x00, 40]);
+        // Mass Concentration PM2.5 is 1.0
+        miso[9..13].copy_from_slice(&[0x3F, 0x80, 0x00, 0x00]);
+        miso[45] = compute_cksum(&miso[1..45]);
+
+        let measurement = Measurement::from_frame::<(), ()>(&miso).unwrap();
+        assert_eq!(measurement.mass_pm2_5, 1.0);
+        assert_eq!(measurement.mass_pm1_0, 0.0);
+    }
+
+    #[test]
+    fn device_info_is_trimmed() {
+        let info = parse_device_info_string::<(), ()>(b"00080000\0\0\0").unwrap();
+        assert_eq!(info.as_str(), "00080000");
+        assert!(parse_device_info_string::<(), ()>(&[0x41, 0xC3, 0xA9, 0x00]).is_err());
+    }
+
+    #[test]
+    fn status_is_decoded() {
+        let miso = [0x7E, 0x00, 0x01, 0x43, 0x00, 0xBB, 0x7E];
+        match parse_response::<(), ()>(&miso, CommandType::StopMeasurement) {
+            Err(Error::StatusError(DeviceError::NotAllowedInState)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn frame_parser_skips_garbage() {
+        let mut parser = FrameParser::new();
+        let stream = [0x13, 0x00, 0x7E, 0x7E, 0x00, 0x01, 0x00, 0x00, 0xFE, 0x7E];
+
+        let mut frames = stream.iter().filter_map(|&byte| parser.feed_byte(byte));
+        let frame = frames.next().unwrap();
+        assert!(frames.next().is_none());
+        assert!(frame.check::<(), ()>(CommandType::StopMeasurement).is_ok());
+    }
+}
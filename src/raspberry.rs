@@ -0,0 +1,37 @@
+//! Raspberry Pi UART setup through `rppal`
+//!
+//! Enabled with the `rppal` feature.
+
+use crate::{Idle, Legacy, NoDelay, Sps30};
+use core::time::Duration;
+use rppal::uart::{Parity, Result, Uart};
+use std::path::Path;
+
+/// Time a read waits for a byte, in ms
+const READ_TIMEOUT_MS: u64 = 100;
+
+/// Set `uart` up for the SPS30: no flow control, blocking writes, timed reads
+fn configure(mut uart: Uart) -> Result<Sps30<Legacy<Uart>, Idle, NoDelay>> {
+    uart.set_hardware_flow_control(false)?;
+    uart.set_software_flow_control(false)?;
+    uart.set_rts(false)?;
+    uart.set_write_mode(true)?;
+    uart.set_read_mode(0, Duration::from_millis(READ_TIMEOUT_MS))?;
+    Ok(Sps30::new(Legacy(uart)))
+}
+
+impl Sps30<Legacy<Uart>, Idle, NoDelay> {
+    /// Create a driver talking through the primary UART of a Raspberry Pi
+    ///
+    /// The UART is set to 115200 baud 8N1 without flow control. Reads time out after
+    /// 100ms, so commands fail instead of hanging when the device does not answer. Enable
+    /// the UART with `raspi-config` first. The device is expected to be in idle mode.
+    pub fn open_rppal() -> Result<Self> {
+        configure(Uart::new(crate::BAUD_RATE, Parity::None, 8, 1)?)
+    }
+
+    /// Same as [`Sps30::open_rppal`], through the UART at `path`, e.g. `/dev/ttyAMA1`
+    pub fn open_rppal_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        configure(Uart::with_path(path, crate::BAUD_RATE, Parity::None, 8, 1)?)
+    }
+}
@@ -0,0 +1,221 @@
+//! Transport reopening its port after a disconnection, e.g. an unplugged USB-serial adapter
+//!
+//! Enabled with the `std` feature.
+
+use crate::Transport;
+use std::io;
+use std::time::{Duration, Instant};
+
+/// Delay before the first attempt to reopen a lost port
+const MIN_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Longest delay between two attempts to reopen a lost port
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Transport wrapper reopening its port when it is lost
+///
+/// `open` creates the wrapped transport, e.g. `|| Ok(SerialPort::open(path)?)`. Any
+/// error other than a timeout drops the port: the command fails with
+/// [`Error::Disconnected`](crate::Error::Disconnected), and so do the following ones until
+/// `open` succeeds again. Attempts to reopen are made when a command is sent, no more often
+/// than the backoff allows; the backoff doubles after every failed attempt, from 100ms up
+/// to 5s. Nothing blocks, so a daemon can keep its loop running through cable glitches.
+///
+/// The device may have been power cycled meanwhile, back in idle mode; compare
+/// [`reconnections`](Self::reconnections) before and after to restart the measurement.
+#[derive(Debug)]
+pub struct Reconnecting<T, O> {
+    port: Option<T>,
+    open: O,
+    backoff: Duration,
+    min_backoff: Duration,
+    max_backoff: Duration,
+    retry_at: Option<Instant>,
+    reconnections: u32,
+}
+
+impl<T, O> Reconnecting<T, O>
+where
+    O: FnMut() -> io::Result<T>,
+{
+    /// Open the port with `open`, failing if it cannot be opened at once
+    pub fn new(mut open: O) -> io::Result<Self> {
+        let port = open()?;
+        Ok(Reconnecting {
+            port: Some(port),
+            open,
+            backoff: MIN_BACKOFF,
+            min_backoff: MIN_BACKOFF,
+            max_backoff: MAX_BACKOFF,
+            retry_at: None,
+            reconnections: 0,
+        })
+    }
+
+    /// Set the delay before the first attempt to reopen, and the longest delay between two
+    pub fn set_backoff(&mut self, min: Duration, max: Duration) {
+        self.min_backoff = min;
+        self.max_backoff = max.max(min);
+        self.backoff = min;
+    }
+
+    /// Whether the port is currently open
+    pub fn is_connected(&self) -> bool {
+        self.port.is_some()
+    }
+
+    /// Number of times the port was reopened after being lost
+    pub fn reconnections(&self) -> u32 {
+        self.reconnections
+    }
+
+    /// Wrapped transport, `None` while disconnected
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        self.port.as_mut()
+    }
+
+    /// Port, reopened first if lost and the backoff elapsed
+    fn port(&mut self) -> io::Result<&mut T> {
+        let due = match self.retry_at {
+            Some(at) => Instant::now() >= at,
+            None => true,
+        };
+        if self.port.is_none() && due {
+            match (self.open)() {
+                Ok(port) => {
+                    self.port = Some(port);
+                    self.backoff = self.min_backoff;
+                    self.retry_at = None;
+                    self.reconnections = self.reconnections.wrapping_add(1);
+                }
+                Err(_) => {
+                    self.retry_at = Some(Instant::now() + self.backoff);
+                    self.backoff = (self.backoff * 2).min(self.max_backoff);
+                }
+            }
+        }
+        self.port
+            .as_mut()
+            .ok_or_else(|| io::ErrorKind::NotConnected.into())
+    }
+
+    /// Drop the port if `error` means it is lost
+    fn check(&mut self, error: &io::Error) {
+        if is_lost(error) {
+            self.port = None;
+            self.retry_at = Some(Instant::now() + self.backoff);
+        }
+    }
+}
+
+/// Whether `error` means the port is gone, rather than a transient condition
+fn is_lost(error: &io::Error) -> bool {
+    !matches!(
+        error.kind(),
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted
+    )
+}
+
+impl<T, O> Transport for Reconnecting<T, O>
+where
+    T: Transport<WriteError = io::Error, ReadError = io::Error>,
+    O: FnMut() -> io::Result<T>,
+{
+    type WriteError = io::Error;
+    type ReadError = io::Error;
+
+    fn write_all(&mut self, data: &[u8]) -> io::Result<()> {
+        let result = self.port()?.write_all(data);
+        if let Err(e) = &result {
+            self.check(e);
+        }
+        result
+    }
+
+    fn read_byte(&mut self) -> nb::Result<u8, io::Error> {
+        let result = self.port()?.read_byte();
+        if let Err(nb::Error::Other(e)) = &result {
+            self.check(e);
+        }
+        result
+    }
+
+    fn is_disconnected(&self) -> bool {
+        self.port.is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Error, MeasurementFormat, Sps30};
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    /// Port failing with `BrokenPipe` once unplugged
+    struct Port {
+        plugged: Rc<Cell<bool>>,
+        rx: &'static [u8],
+    }
+
+    impl Transport for Port {
+        type WriteError = io::Error;
+        type ReadError = io::Error;
+
+        fn write_all(&mut self, _data: &[u8]) -> io::Result<()> {
+            if self.plugged.get() {
+                Ok(())
+            } else {
+                Err(io::ErrorKind::BrokenPipe.into())
+            }
+        }
+
+        fn read_byte(&mut self) -> nb::Result<u8, io::Error> {
+            let (&byte, rest) = self.rx.split_first().ok_or(nb::Error::WouldBlock)?;
+            self.rx = rest;
+            Ok(byte)
+        }
+    }
+
+    #[test]
+    fn rides_through_unplug() {
+        // Start Measurement response, for every port opened
+        let rx = &[0x7E, 0x00, 0x00, 0x00, 0x00, 0xFF, 0x7E];
+        let plugged = Rc::new(Cell::new(true));
+        let cable = plugged.clone();
+        let open = move || {
+            if cable.get() {
+                Ok(Port {
+                    plugged: cable.clone(),
+                    rx,
+                })
+            } else {
+                Err(io::ErrorKind::NotFound.into())
+            }
+        };
+        let mut port = Reconnecting::new(open).unwrap();
+        port.set_backoff(Duration::ZERO, Duration::ZERO);
+        let sensor = Sps30::new(port);
+
+        plugged.set(false);
+        let (error, sensor) = sensor
+            .start_measurement(MeasurementFormat::Float)
+            .err()
+            .unwrap();
+        assert!(matches!(error, Error::Disconnected));
+        let (error, sensor) = sensor
+            .start_measurement(MeasurementFormat::Float)
+            .err()
+            .unwrap();
+        assert!(matches!(error, Error::Disconnected));
+
+        plugged.set(true);
+        let sensor = sensor
+            .start_measurement(MeasurementFormat::Float)
+            .ok()
+            .unwrap();
+        let port = sensor.release();
+        assert!(port.is_connected());
+        assert_eq!(port.reconnections(), 1);
+    }
+}
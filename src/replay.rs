@@ -0,0 +1,24 @@
+//! Transport shared by the unit tests
+
+use crate::Transport;
+
+/// Transport replaying canned MISO bytes, MOSI Frames are dropped
+pub(crate) struct Replay {
+    /// MISO bytes not read yet
+    pub(crate) rx: &'static [u8],
+}
+
+impl Transport for Replay {
+    type WriteError = ();
+    type ReadError = ();
+
+    fn write_all(&mut self, _data: &[u8]) -> Result<(), ()> {
+        Ok(())
+    }
+
+    fn read_byte(&mut self) -> nb::Result<u8, ()> {
+        let (&byte, rest) = self.rx.split_first().ok_or(nb::Error::WouldBlock)?;
+        self.rx = rest;
+        Ok(byte)
+    }
+}
@@ -0,0 +1,158 @@
+//! Non-blocking command execution, for RTIC tasks and idle loops
+
+use crate::protocol::{self, FrameSync, ResponseFrame, ENCODED_FRAME_SIZE};
+use crate::{CommandType, Error, Sps30, Transport};
+use arrayvec::ArrayVec;
+use embedded_hal::delay::DelayNs;
+
+/// Command sent with [`Sps30::start_command`], waiting for its MISO Frame
+///
+/// Holds the bytes of the response received so far, between calls to
+/// [`Sps30::poll_command`].
+#[derive(Debug, Clone)]
+pub struct PendingCommand {
+    command: CommandType,
+    frame: ArrayVec<[u8; ENCODED_FRAME_SIZE]>,
+    discarded: usize,
+}
+
+impl PendingCommand {
+    /// Command waiting for its response
+    pub fn command(&self) -> CommandType {
+        self.command
+    }
+
+    /// Number of response bytes received so far, start flag included
+    pub fn received(&self) -> usize {
+        self.frame.len()
+    }
+}
+
+impl<SERIAL, MODE, DELAY, E, F> Sps30<SERIAL, MODE, DELAY>
+where
+    SERIAL: Transport<WriteError = E, ReadError = F>,
+    DELAY: DelayNs,
+{
+    /// Send `cmd_type` carrying `data`, without waiting for the response
+    ///
+    /// Drive the transaction to completion with [`Sps30::poll_command`], e.g. from an idle
+    /// loop or an RTIC software task, instead of blocking for the whole MISO Frame. The
+    /// command is not retried and no timer is used: give up once
+    /// [`CommandType::max_response_time_ms`] elapsed. As with [`Sps30::send_raw_command`],
+    /// the driver mode is not changed.
+    pub fn start_command(
+        &mut self,
+        cmd_type: CommandType,
+        data: &[u8],
+    ) -> Result<PendingCommand, Error<E, F>> {
+        self.send_request(cmd_type as u8, data)?;
+        Ok(PendingCommand {
+            command: cmd_type,
+            frame: ArrayVec::new(),
+            discarded: 0,
+        })
+    }
+
+    /// Read the bytes available for `pending`, returns the response once complete
+    ///
+    /// Returns `nb::Error::WouldBlock` as soon as the serial port has no more bytes, the
+    /// bytes read so far are kept in `pending` for the next call.
+    pub fn poll_command(
+        &mut self,
+        pending: &mut PendingCommand,
+    ) -> nb::Result<ResponseFrame, Error<E, F>> {
+        let max_frame_size = self.config.max_frame_size;
+        let fend = self.config.special_chars.fend;
+        loop {
+            let byte = match self.serial.read_byte() {
+                Ok(byte) => byte,
+                Err(nb::Error::WouldBlock) => return Err(nb::Error::WouldBlock),
+                Err(e) => return Err(self.fail(self.port_error(Error::from(e)))),
+            };
+            match protocol::sync_byte(&mut pending.frame, byte, fend, max_frame_size) {
+                FrameSync::Complete => {
+                    let mut frame = core::mem::take(&mut pending.frame);
+                    pending.discarded = 0;
+                    let result = self.check_response(&mut frame, pending.command as u8);
+                    self.record_outcome(&result);
+                    return result.map_err(nb::Error::Other);
+                }
+                FrameSync::Pending => {}
+                FrameSync::Discarded => {
+                    pending.discarded += 1;
+                    if pending.discarded > max_frame_size {
+                        return Err(self.fail(Error::InvalidFrame));
+                    }
+                }
+                FrameSync::Overflow => return Err(self.fail(Error::InvalidFrame)),
+            }
+        }
+    }
+
+    /// Record `error` in the link statistics
+    fn fail(&mut self, error: Error<E, F>) -> nb::Error<Error<E, F>> {
+        self.stats.record_error(&error);
+        nb::Error::Other(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Port delivering the response in chunks, `None` standing for no byte available
+    struct Trickle {
+        rx: &'static [Option<u8>],
+    }
+
+    impl Transport for Trickle {
+        type WriteError = ();
+        type ReadError = ();
+
+        fn write_all(&mut self, _data: &[u8]) -> Result<(), ()> {
+            Ok(())
+        }
+
+        fn read_byte(&mut self) -> nb::Result<u8, ()> {
+            let (&byte, rest) = self.rx.split_first().ok_or(nb::Error::WouldBlock)?;
+            self.rx = rest;
+            byte.ok_or(nb::Error::WouldBlock)
+        }
+    }
+
+    #[test]
+    fn resumes_across_polls() {
+        // Read Auto Cleaning Interval response, 604800s
+        let rx = &[
+            Some(0x7E),
+            Some(0x00),
+            Some(0x80),
+            None,
+            Some(0x00),
+            Some(0x04),
+            Some(0x00),
+            None,
+            None,
+            Some(0x09),
+            Some(0x3A),
+            Some(0x80),
+            Some(0xB8),
+            Some(0x7E),
+        ];
+        let mut sensor = Sps30::new(Trickle { rx });
+        let mut pending = sensor
+            .start_command(CommandType::ReadWriteAutoCleaningInterval, &[0x00])
+            .unwrap();
+        let mut polls = 1;
+        let response = loop {
+            match sensor.poll_command(&mut pending) {
+                Err(nb::Error::WouldBlock) => polls += 1,
+                result => break result.ok().unwrap(),
+            }
+        };
+        assert_eq!(polls, 4);
+        assert_eq!(response.data(), &[0x00, 0x09, 0x3A, 0x80]);
+        assert_eq!(pending.received(), 0);
+        assert_eq!(sensor.link_stats().frames_received, 1);
+    }
+}
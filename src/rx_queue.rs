@@ -0,0 +1,141 @@
+//! Receive queue filled from a UART RX interrupt
+//!
+//! Enabled with the `critical-section` feature.
+
+use core::cell::RefCell;
+use critical_section::Mutex;
+use embedded_hal_nb::serial::{ErrorKind, ErrorType, Read};
+use heapless::Deque;
+
+/// Bytes received by an interrupt handler, waiting for the driver
+///
+/// Place it in a `static`, push every received byte from the UART RX interrupt with
+/// [`RxQueue::push`], and hand a reference to the driver along with the transmitter half:
+/// `Sps30::new(Split::new(tx, &RX_QUEUE))`. Commands are then issued from the main
+/// context while the interrupt keeps receiving; both sides only touch the queue inside a
+/// critical section. To also issue commands from several contexts, keep the driver itself
+/// in a `critical_section::Mutex<RefCell<Option<Sps30<..>>>>`.
+///
+/// Bytes received while the queue is full are dropped, the next read then fails with
+/// `ErrorKind::Overrun`, reported by the driver as [`Error::SerialR`](crate::Error::SerialR).
+#[derive(Debug)]
+pub struct RxQueue<const N: usize> {
+    state: Mutex<RefCell<State<N>>>,
+}
+
+#[derive(Debug)]
+struct State<const N: usize> {
+    bytes: Deque<u8, N>,
+    overrun: bool,
+}
+
+impl<const N: usize> RxQueue<N> {
+    /// Empty queue holding up to `N` bytes
+    pub const fn new() -> Self {
+        RxQueue {
+            state: Mutex::new(RefCell::new(State {
+                bytes: Deque::new(),
+                overrun: false,
+            })),
+        }
+    }
+
+    /// Queue `byte`, returns `false` if it was dropped because the queue is full
+    pub fn push(&self, byte: u8) -> bool {
+        critical_section::with(|cs| {
+            let mut state = self.state.borrow_ref_mut(cs);
+            let queued = state.bytes.push_back(byte).is_ok();
+            state.overrun |= !queued;
+            queued
+        })
+    }
+
+    /// Number of bytes waiting
+    pub fn len(&self) -> usize {
+        critical_section::with(|cs| self.state.borrow_ref(cs).bytes.len())
+    }
+
+    /// Whether no byte is waiting
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drop all waiting bytes and the overrun condition
+    pub fn clear(&self) {
+        critical_section::with(|cs| {
+            let mut state = self.state.borrow_ref_mut(cs);
+            state.bytes.clear();
+            state.overrun = false;
+        })
+    }
+}
+
+impl<const N: usize> Default for RxQueue<N> {
+    fn default() -> Self {
+        RxQueue::new()
+    }
+}
+
+impl<const N: usize> ErrorType for &RxQueue<N> {
+    type Error = ErrorKind;
+}
+
+impl<const N: usize> Read<u8> for &RxQueue<N> {
+    fn read(&mut self) -> nb::Result<u8, ErrorKind> {
+        critical_section::with(|cs| {
+            let mut state = self.state.borrow_ref_mut(cs);
+            if state.overrun {
+                state.overrun = false;
+                return Err(nb::Error::Other(ErrorKind::Overrun));
+            }
+            state.bytes.pop_front().ok_or(nb::Error::WouldBlock)
+        })
+    }
+}
+
+#[cfg(all(test, not(feature = "no-float")))]
+mod tests {
+    use super::*;
+    use crate::{Error, MeasurementFormat, Split, Sps30};
+    use core::convert::Infallible;
+
+    /// Transmitter pushing the Start Measurement response to the queue, as an interrupt would
+    struct Tx(&'static RxQueue<8>);
+
+    impl embedded_hal_nb::serial::ErrorType for Tx {
+        type Error = Infallible;
+    }
+
+    impl embedded_hal_nb::serial::Write<u8> for Tx {
+        fn write(&mut self, _byte: u8) -> nb::Result<(), Infallible> {
+            Ok(())
+        }
+
+        fn flush(&mut self) -> nb::Result<(), Infallible> {
+            if self.0.is_empty() {
+                for &byte in &[0x7E, 0x00, 0x00, 0x00, 0x00, 0xFF, 0x7E] {
+                    self.0.push(byte);
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn driver_reads_interrupt_bytes() {
+        static RX: RxQueue<8> = RxQueue::new();
+        let sensor = Sps30::new(Split::new(Tx(&RX), &RX));
+        let mut sensor = sensor
+            .start_measurement(MeasurementFormat::Float)
+            .ok()
+            .unwrap();
+        assert!(RX.is_empty());
+
+        assert!((0..8).all(|byte| RX.push(byte)));
+        assert!(!RX.push(8));
+        assert!(matches!(
+            sensor.read_measurement(),
+            Err(Error::SerialR(nb::Error::Other(ErrorKind::Overrun)))
+        ));
+    }
+}
@@ -0,0 +1,301 @@
+//! Sampling with statistics over the last measurements
+
+use crate::aqi;
+use crate::filter::{Filter, Unfiltered};
+use crate::{Error, Measurement, Measuring, Sps30, Transport};
+use embedded_hal::delay::DelayNs;
+use heapless::HistoryBuffer;
+
+/// Driver sampling measured values on every [`Sampler::tick`], keeping the last `N`
+///
+/// Statistics are computed per channel, i.e. each field of the returned [`Measurement`]
+/// is the statistic of that field over the stored measurements. With a [`Filter`], see
+/// [`Sampler::with_filter`], the filtered values are stored instead of the measured ones.
+pub struct Sampler<SERIAL, DELAY, const N: usize, FILTER = Unfiltered> {
+    /// Driver values are read from
+    sps30: Sps30<SERIAL, Measuring, DELAY>,
+    /// Filter applied to measured values before they are stored
+    filter: FILTER,
+    /// Last `N` measurements, oldest first
+    history: HistoryBuffer<Measurement, N>,
+}
+
+impl<SERIAL, DELAY, E, F, const N: usize> Sampler<SERIAL, DELAY, N>
+where
+    SERIAL: Transport<WriteError = E, ReadError = F>,
+    DELAY: DelayNs,
+{
+    /// Sample from a measuring driver, nothing stored yet
+    pub fn new(sps30: Sps30<SERIAL, Measuring, DELAY>) -> Self {
+        Sampler::with_filter(sps30, Unfiltered)
+    }
+}
+
+impl<SERIAL, DELAY, E, F, const N: usize, FILTER> Sampler<SERIAL, DELAY, N, FILTER>
+where
+    SERIAL: Transport<WriteError = E, ReadError = F>,
+    DELAY: DelayNs,
+    FILTER: Filter,
+{
+    /// Sample from a measuring driver, smoothing measured values with `filter`
+    pub fn with_filter(sps30: Sps30<SERIAL, Measuring, DELAY>, filter: FILTER) -> Self {
+        Sampler {
+            sps30,
+            filter,
+            history: HistoryBuffer::new(),
+        }
+    }
+
+    /// Read measured values, storing them in place of the oldest ones once `N` are stored
+    ///
+    /// Call it at the sampling rate, at most once per second. Returns the values stored,
+    /// or `None` if no new values were available.
+    pub fn tick(&mut self) -> Result<Option<Measurement>, Error<E, F>> {
+        match self.sps30.read_measurement() {
+            Ok(measurement) => {
+                let measurement = self.filter.update(&measurement);
+                self.history.write(measurement);
+                Ok(Some(measurement))
+            }
+            Err(Error::EmptyResult) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Driver, e.g. to start fan cleaning between ticks
+    pub fn sps30_mut(&mut self) -> &mut Sps30<SERIAL, Measuring, DELAY> {
+        &mut self.sps30
+    }
+
+    /// Destroy the sampler and return the driver
+    pub fn release(self) -> Sps30<SERIAL, Measuring, DELAY> {
+        self.sps30
+    }
+}
+
+impl<SERIAL, DELAY, const N: usize, FILTER: Filter> Sampler<SERIAL, DELAY, N, FILTER> {
+    /// Number of stored measurements, at most `N`
+    pub fn len(&self) -> usize {
+        self.history.len()
+    }
+
+    /// No measurement stored yet
+    pub fn is_empty(&self) -> bool {
+        self.history.len() == 0
+    }
+
+    /// Drop the stored measurements and reset the filter
+    pub fn clear(&mut self) {
+        self.history.clear();
+        self.filter.reset();
+    }
+
+    /// Stored measurements, oldest first
+    pub fn iter(&self) -> impl Iterator<Item = &Measurement> {
+        self.history.oldest_ordered()
+    }
+
+    /// Last measurement stored
+    pub fn latest(&self) -> Option<Measurement> {
+        self.history.recent().copied()
+    }
+
+    /// EPA NowCast of a channel, see [`aqi::nowcast`](crate::aqi::nowcast)
+    ///
+    /// Each stored measurement counts as the mean of one hour, the latest one as the most
+    /// recent hour: tick the sampler every hour, e.g. with a filter averaging over the hour.
+    /// `channel` selects the value, e.g. `|m| m.mass_pm2_5`.
+    pub fn nowcast(&self, channel: impl Fn(&Measurement) -> f32) -> Option<f32> {
+        let (older, newer) = self.history.as_slices();
+        let recent_first = newer.iter().rev().chain(older.iter().rev());
+        aqi::nowcast(recent_first.map(|measurement| Some(channel(measurement))))
+    }
+
+    /// Minimum of each channel
+    pub fn min(&self) -> Option<Measurement> {
+        self.fold(f32::min)
+    }
+
+    /// Maximum of each channel
+    pub fn max(&self) -> Option<Measurement> {
+        self.fold(f32::max)
+    }
+
+    /// Arithmetic mean of each channel
+    pub fn mean(&self) -> Option<Measurement> {
+        let count = self.len() as f32;
+        self.fold(|sum, value| sum + value).map(|sum| {
+            let mut values = sum.to_array();
+            for value in values.iter_mut() {
+                *value /= count;
+            }
+            Measurement::from(values)
+        })
+    }
+
+    /// Combine the stored measurements channel by channel with `f`
+    fn fold(&self, f: impl Fn(f32, f32) -> f32) -> Option<Measurement> {
+        let mut iter = self.history.oldest_ordered();
+        let mut acc = iter.next()?.to_array();
+        for measurement in iter {
+            for (acc, value) in acc.iter_mut().zip(measurement.to_array().iter()) {
+                *acc = f(*acc, *value);
+            }
+        }
+        Some(Measurement::from(acc))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::MovingAverage;
+    use crate::protocol::ENCODED_FRAME_SIZE;
+    use crate::{hdlc, MeasurementFormat, SpecialChars};
+
+    /// Transport answering Read Measurement with the next of `values`, all channels set to
+    /// it, `None` being an empty result; other commands get an empty MISO Frame
+    struct Readings {
+        values: &'static [Option<f32>],
+        frame: [u8; ENCODED_FRAME_SIZE],
+        len: usize,
+        pos: usize,
+    }
+
+    impl Transport for Readings {
+        type WriteError = ();
+        type ReadError = ();
+
+        fn write_all(&mut self, data: &[u8]) -> Result<(), ()> {
+            let cmd = data[2];
+            let mut payload = [0; 40];
+            let mut len = 0;
+            if cmd == 0x03 {
+                let (&value, rest) = self.values.split_first().ok_or(())?;
+                self.values = rest;
+                if let Some(value) = value {
+                    for chunk in payload.chunks_mut(4) {
+                        chunk.copy_from_slice(&value.to_be_bytes());
+                    }
+                    len = payload.len();
+                }
+            }
+            let header = [0x00, cmd, 0x00, len as u8];
+            let s_chars = SpecialChars::default();
+            self.len = hdlc::encode_frame(&header, &payload[..len], s_chars, &mut self.frame)
+                .map_err(|_| ())?;
+            self.pos = 0;
+            Ok(())
+        }
+
+        fn read_byte(&mut self) -> nb::Result<u8, ()> {
+            if self.pos == self.len {
+                return Err(nb::Error::WouldBlock);
+            }
+            self.pos += 1;
+            Ok(self.frame[self.pos - 1])
+        }
+    }
+
+    fn measuring(values: &'static [Option<f32>]) -> Sps30<Readings, Measuring> {
+        let serial = Readings {
+            values,
+            frame: [0; ENCODED_FRAME_SIZE],
+            len: 0,
+            pos: 0,
+        };
+        Sps30::new(serial)
+            .start_measurement(MeasurementFormat::Float)
+            .ok()
+            .unwrap()
+    }
+
+    fn measurement(value: f32) -> Measurement {
+        Measurement::from([value; 10])
+    }
+
+    #[test]
+    fn keeps_the_last_n_measurements() {
+        let values = &[Some(1.0), None, Some(2.0), Some(4.0), Some(8.0), Some(16.0)];
+        let mut sampler = Sampler::<_, _, 3>::new(measuring(values));
+        assert!(sampler.is_empty());
+        assert_eq!(sampler.mean(), None);
+
+        assert_eq!(sampler.tick().unwrap(), Some(measurement(1.0)));
+        assert_eq!(sampler.tick().unwrap(), None);
+        assert_eq!(sampler.len(), 1);
+        for _ in 0..4 {
+            assert!(sampler.tick().unwrap().is_some());
+        }
+
+        // 1 and 2 overwritten
+        assert_eq!(sampler.len(), 3);
+        let stored: [f32; 3] = [4.0, 8.0, 16.0];
+        assert!(sampler
+            .iter()
+            .map(|m| m.mass_pm2_5)
+            .eq(stored.iter().copied()));
+        assert_eq!(sampler.latest(), Some(measurement(16.0)));
+        assert_eq!(sampler.min(), Some(measurement(4.0)));
+        assert_eq!(sampler.max(), Some(measurement(16.0)));
+        assert_eq!(sampler.mean(), Some(measurement(28.0 / 3.0)));
+
+        sampler.clear();
+        assert!(sampler.is_empty());
+        assert_eq!(sampler.latest(), None);
+        assert_eq!(sampler.min(), None);
+    }
+
+    #[test]
+    fn stores_filtered_values() {
+        let values = &[Some(2.0), Some(4.0), Some(8.0), Some(10.0)];
+        let filter = MovingAverage::<2>::new();
+        let mut sampler = Sampler::<_, _, 4, _>::with_filter(measuring(values), filter);
+        for _ in 0..3 {
+            sampler.tick().unwrap();
+        }
+        let stored: [f32; 3] = [2.0, 3.0, 6.0];
+        assert!(sampler
+            .iter()
+            .map(|m| m.mass_pm2_5)
+            .eq(stored.iter().copied()));
+
+        // The filter starts over after clearing
+        sampler.clear();
+        assert_eq!(sampler.tick().unwrap(), Some(measurement(10.0)));
+        assert_eq!(sampler.len(), 1);
+    }
+
+    #[test]
+    fn nowcast_weighs_the_latest_hour_most() {
+        const HOURS: usize = aqi::NOWCAST_HOURS;
+        let values = &[
+            Some(20.0),
+            Some(21.0),
+            Some(22.0),
+            Some(23.0),
+            Some(25.0),
+            Some(28.0),
+            Some(32.0),
+            Some(37.0),
+            Some(43.0),
+            Some(50.0),
+            Some(58.0),
+            Some(67.0),
+            Some(77.0),
+            Some(88.0),
+            Some(100.0),
+        ];
+        let mut sampler = Sampler::<_, _, HOURS>::new(measuring(values));
+        for _ in 0..values.len() {
+            sampler.tick().unwrap();
+        }
+
+        // The 3 oldest hours overwritten, the most recent one first
+        let recent_first = values.iter().rev().take(HOURS).copied();
+        let expected = aqi::nowcast(recent_first);
+        assert_eq!(sampler.nowcast(|m| m.mass_pm2_5), expected);
+        let oldest_first = aqi::nowcast(values[values.len() - HOURS..].iter().copied());
+        assert_ne!(expected, oldest_first);
+    }
+}
@@ -0,0 +1,44 @@
+//! Communications self-test
+
+use crate::DeviceError;
+#[cfg(feature = "device-info")]
+use crate::Error;
+
+/// Outcome of [`Sps30::self_test`](crate::Sps30::self_test)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SelfTest {
+    /// Commands were answered with valid MISO Frames
+    Passed,
+    /// The serial port failed to send or receive
+    Port,
+    /// Nothing was received: sensor not powered or not connected, or TX and RX swapped
+    NoResponse,
+    /// Bytes were received outside of frames: noise, floating RX line or wrong baud rate
+    Wiring,
+    /// Frames were received but corrupted: checksum, SHDLC or length errors
+    Framing,
+    /// The device answered with an error state
+    Device(DeviceError),
+}
+
+impl SelfTest {
+    /// Whether the link works end-to-end
+    pub fn passed(&self) -> bool {
+        *self == SelfTest::Passed
+    }
+
+    /// Outcome of a command failing with `error`
+    #[cfg(feature = "device-info")]
+    pub(crate) fn from_error<E, F>(error: &Error<E, F>) -> Self {
+        match error {
+            Error::SerialR(nb::Error::WouldBlock) | Error::Timeout => SelfTest::NoResponse,
+            Error::SerialR(nb::Error::Other(_)) | Error::SerialW(_) | Error::Disconnected => {
+                SelfTest::Port
+            }
+            Error::InvalidFrame => SelfTest::Wiring,
+            Error::StatusError(e) => SelfTest::Device(*e),
+            _ => SelfTest::Framing,
+        }
+    }
+}
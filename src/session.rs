@@ -0,0 +1,118 @@
+//! Measurement bound to a scope
+
+use crate::commands::Command;
+#[cfg(not(feature = "no-float"))]
+use crate::Measurement;
+use crate::{Error, Idle, MeasurementU16, Sps30, Transport};
+use embedded_hal::delay::DelayNs;
+
+/// Measurement started with [`Sps30::start_measurement_session`]
+///
+/// Stop Measurement is sent by [`MeasurementSession::end`], or when the session is dropped
+/// without being ended, in which case errors are ignored.
+pub struct MeasurementSession<'a, SERIAL, DELAY>
+where
+    SERIAL: Transport,
+    DELAY: DelayNs,
+{
+    /// Driver the session was started from, back in idle mode once the session is over
+    sps30: &'a mut Sps30<SERIAL, Idle, DELAY>,
+    /// Stop Measurement already sent
+    ended: bool,
+}
+
+impl<'a, SERIAL, DELAY, E, F> MeasurementSession<'a, SERIAL, DELAY>
+where
+    SERIAL: Transport<WriteError = E, ReadError = F>,
+    DELAY: DelayNs,
+{
+    /// Session over a device already measuring
+    pub(crate) fn new(sps30: &'a mut Sps30<SERIAL, Idle, DELAY>) -> Self {
+        MeasurementSession {
+            sps30,
+            ended: false,
+        }
+    }
+
+    /// Read measuring, see [`Sps30::read_measurement`]
+    #[cfg(not(feature = "no-float"))]
+    pub fn read_measurement(&mut self) -> Result<Measurement, Error<E, F>> {
+        let measurement = self.sps30.read_measured_data(Command::READ_MEASUREMENT)?;
+        self.sps30.calibrate(&measurement)
+    }
+
+    /// Read measuring, waiting with `delay` until new values are available, see
+    /// [`Sps30::wait_for_first_measurement`]
+    #[cfg(not(feature = "no-float"))]
+    pub fn wait_for_first_measurement<D: DelayNs>(
+        &mut self,
+        delay: &mut D,
+        max_wait_ms: u32,
+    ) -> Result<Measurement, Error<E, F>> {
+        crate::iter::wait_for_values(delay, max_wait_ms, || self.read_measurement())
+    }
+
+    /// Read measuring into `measurement`, see [`Sps30::read_measurement_into`]
+    #[cfg(not(feature = "no-float"))]
+    pub fn read_measurement_into(
+        &mut self,
+        measurement: &mut Measurement,
+    ) -> Result<(), Error<E, F>> {
+        let bytes = self.read_measurement_raw()?;
+        *measurement = self.sps30.calibrate(&Measurement::from_bytes(&bytes))?;
+        Ok(())
+    }
+
+    /// Read measuring without conversion, see [`Sps30::read_measurement_raw`]
+    pub fn read_measurement_raw(&mut self) -> Result<[u8; 40], Error<E, F>> {
+        self.sps30.read_measured_data(Command::READ_MEASUREMENT_RAW)
+    }
+
+    /// Read measuring in integer format, see [`Sps30::read_measurement_u16`]
+    pub fn read_measurement_u16(&mut self) -> Result<MeasurementU16, Error<E, F>> {
+        self.sps30.read_measured_data(Command::READ_MEASUREMENT_U16)
+    }
+
+    /// Whether the values last read were measured after the fan warm-up, see
+    /// [`Sps30::is_warmed_up`]
+    pub fn is_warmed_up(&self) -> bool {
+        self.sps30.warmed_up()
+    }
+
+    #[cfg(feature = "fan-clean")]
+    /// Start fan cleaning
+    pub fn start_fan_cleaning(&mut self) -> Result<(), Error<E, F>> {
+        self.sps30.execute(Command::START_FAN_CLEANING, &[])?;
+        self.sps30.cleaning = true;
+        Ok(())
+    }
+
+    /// Whether a fan cleaning may still be running, see [`Sps30::cleaning_in_progress`]
+    pub fn cleaning_in_progress(&self) -> bool {
+        self.sps30.cleaning
+    }
+
+    /// Stop measuring, reporting the error dropping the session would ignore
+    pub fn end(mut self) -> Result<(), Error<E, F>> {
+        self.stop()
+    }
+
+    /// Send Stop Measurement, once
+    fn stop(&mut self) -> Result<(), Error<E, F>> {
+        if self.ended {
+            return Ok(());
+        }
+        self.ended = true;
+        self.sps30.execute(Command::STOP_MEASUREMENT, &[])
+    }
+}
+
+impl<SERIAL, DELAY> Drop for MeasurementSession<'_, SERIAL, DELAY>
+where
+    SERIAL: Transport,
+    DELAY: DelayNs,
+{
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
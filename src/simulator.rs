@@ -0,0 +1,477 @@
+//! Simulated SPS30, for host-side testing
+//!
+//! Enabled with the `simulator` feature. [`SimulatedSps30`] is a serial port answering MOSI
+//! Frames like a real device would, so applications can be tested without hardware.
+
+use crate::hdlc;
+use crate::protocol::{FrameParser, ENCODED_FRAME_SIZE};
+use crate::{DeviceInfo, InfoString, Measurement, MeasurementFormat, SpecialChars, Version};
+use core::convert::Infallible;
+use heapless::Deque;
+
+/// State codes sent in MISO Frames, as per section 4.2 from spec
+const STATE_OK: u8 = 0x00;
+const STATE_WRONG_DATA_LENGTH: u8 = 0x01;
+const STATE_UNKNOWN_COMMAND: u8 = 0x02;
+const STATE_ILLEGAL_PARAMETER: u8 = 0x04;
+const STATE_NOT_ALLOWED: u8 = 0x43;
+
+/// Maximum number of faults queued with [`SimulatedSps30::inject_fault`]
+const MAX_FAULTS: usize = 8;
+
+/// Fault applied to a response of [`SimulatedSps30`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// No response at all, the command is still executed
+    NoResponse,
+    /// One byte in the middle of the frame is lost
+    DropByte,
+    /// The checksum is wrong
+    CorruptChecksum,
+    /// The frame is cut in half, the stop flag is never sent
+    Truncate,
+    /// The command is not executed and answered with State `code`, e.g. 0x43 (command not
+    /// allowed in current state)
+    State(u8),
+    /// The response is available only after that many reads reported no byte
+    Delay(u32),
+}
+
+/// Operating mode of the simulated device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Idle,
+    Measuring(MeasurementFormat),
+    Sleeping,
+}
+
+/// Serial port behaving like a SPS30
+///
+/// Implements the `embedded-hal-nb` serial traits, so it can be handed to
+/// [`Sps30::new`](crate::Sps30::new). MOSI Frames written to it are answered with MISO
+/// Frames read back from it, as a real device would, errors included (e.g. Read Measured
+/// Values outside of measurement mode). Frames with a wrong checksum or address, and
+/// commands sent while asleep, other than Wake-up, are not answered.
+///
+/// Line and device faults are simulated with [`SimulatedSps30::inject_fault`], to test
+/// retry and recovery logic.
+#[derive(Debug)]
+pub struct SimulatedSps30 {
+    /// MOSI Frame being received
+    parser: FrameParser,
+    /// MISO bytes not read yet
+    rx: Deque<u8, ENCODED_FRAME_SIZE>,
+    /// Operating mode
+    mode: Mode,
+    /// Values returned by Read Measured Values
+    measurement: Measurement,
+    /// Reads returning no values after each Start Measurement
+    empty_reads: u32,
+    /// Reads still returning no values
+    pending_empty_reads: u32,
+    /// Auto cleaning interval, in s
+    cleaning_interval: u32,
+    /// Firmware version
+    firmware: Version,
+    /// Serial Number
+    serial_number: InfoString,
+    /// Device Status Register
+    status: u32,
+    /// Fan cleanings started
+    cleanings: u32,
+    /// Faults applied to the next responses, one each
+    faults: Deque<Fault, MAX_FAULTS>,
+    /// Fault applied to every response once no more are queued
+    persistent_fault: Option<Fault>,
+    /// Reads still reporting no byte before the pending response is available
+    delay_reads: u32,
+    /// Response being queued gets a wrong checksum
+    corrupt_checksum: bool,
+}
+
+impl Default for SimulatedSps30 {
+    fn default() -> Self {
+        SimulatedSps30::new()
+    }
+}
+
+impl SimulatedSps30 {
+    /// Idle device with firmware 2.2, measuring all values at 0
+    pub fn new() -> Self {
+        SimulatedSps30 {
+            parser: FrameParser::new(),
+            rx: Deque::new(),
+            mode: Mode::Idle,
+            measurement: Measurement::default(),
+            empty_reads: 0,
+            pending_empty_reads: 0,
+            cleaning_interval: 604_800,
+            firmware: Version::new(2, 2),
+            serial_number: info_string("SIMULATED0000000"),
+            status: 0,
+            cleanings: 0,
+            faults: Deque::new(),
+            persistent_fault: None,
+            delay_reads: 0,
+            corrupt_checksum: false,
+        }
+    }
+
+    /// Values returned by Read Measured Values from now on
+    ///
+    /// In integer format, values are rounded and the typical particle size is in nm.
+    pub fn set_measurement(&mut self, measurement: Measurement) {
+        self.measurement = measurement;
+    }
+
+    /// Number of Read Measured Values returning no values after each Start Measurement,
+    /// 0 by default
+    ///
+    /// A real device produces its first values about 1s after measurement started.
+    pub fn set_empty_reads(&mut self, reads: u32) {
+        self.empty_reads = reads;
+    }
+
+    /// Firmware version reported by Read Version, 2.2 by default
+    ///
+    /// Sleep, Wake-up and integer format fail on firmware older than 2.0, Read Device
+    /// Status Register on firmware older than 2.2.
+    pub fn set_firmware(&mut self, firmware: Version) {
+        self.firmware = firmware;
+    }
+
+    /// Serial Number reported by Device Information
+    pub fn set_serial_number(&mut self, serial_number: &str) {
+        self.serial_number = info_string(serial_number);
+    }
+
+    /// Device Status Register bits, see [`DeviceStatus`](crate::DeviceStatus)
+    pub fn set_status(&mut self, status: u32) {
+        self.status = status;
+    }
+
+    /// Whether the simulated device is in measurement mode
+    pub fn is_measuring(&self) -> bool {
+        matches!(self.mode, Mode::Measuring(_))
+    }
+
+    /// Whether the simulated device is in sleep mode
+    pub fn is_sleeping(&self) -> bool {
+        self.mode == Mode::Sleeping
+    }
+
+    /// Auto cleaning interval, in s, as last written
+    pub fn cleaning_interval(&self) -> u32 {
+        self.cleaning_interval
+    }
+
+    /// Number of fan cleanings started
+    pub fn cleanings(&self) -> u32 {
+        self.cleanings
+    }
+
+    /// Apply `fault` to the next response, after the faults already queued
+    ///
+    /// Up to 8 faults are queued, returns `fault` back if the queue is full.
+    pub fn inject_fault(&mut self, fault: Fault) -> Result<(), Fault> {
+        self.faults.push_back(fault)
+    }
+
+    /// Apply `fault` to every response once no more faults are queued, `None` to stop
+    pub fn set_persistent_fault(&mut self, fault: Option<Fault>) {
+        self.persistent_fault = fault;
+    }
+
+    /// Receive one MOSI byte, answering once a frame is complete
+    fn receive(&mut self, byte: u8) {
+        let frame = match self.parser.feed_byte(byte) {
+            Some(frame) => frame,
+            None => return,
+        };
+        let bytes = frame.as_bytes();
+        if bytes.len() < 4 || bytes[0] != 0x00 {
+            return;
+        }
+        let (cmd, data) = (bytes[1], &bytes[3..bytes.len() - 1]);
+        if usize::from(bytes[2]) != data.len() {
+            return self.respond(cmd, STATE_WRONG_DATA_LENGTH, &[]);
+        }
+        if self.mode == Mode::Sleeping && cmd != 0x11 {
+            return;
+        }
+        match self.faults.pop_front().or(self.persistent_fault) {
+            Some(Fault::State(code)) => self.respond(cmd, code, &[]),
+            Some(Fault::CorruptChecksum) => {
+                self.corrupt_checksum = true;
+                self.execute(cmd, data);
+                self.corrupt_checksum = false;
+            }
+            Some(fault) => {
+                let start = self.rx.len();
+                self.execute(cmd, data);
+                self.apply(fault, start);
+            }
+            None => self.execute(cmd, data),
+        }
+    }
+
+    /// Execute command `cmd` carrying `data` and queue the response
+    fn execute(&mut self, cmd: u8, data: &[u8]) {
+        let firmware_2_0 = self.firmware >= Version::new(2, 0);
+        match (cmd, data) {
+            (0x00, &[0x01, format]) if self.mode == Mode::Idle => {
+                let format = match format {
+                    0x03 => MeasurementFormat::Float,
+                    0x05 if firmware_2_0 => MeasurementFormat::UnsignedInteger16,
+                    _ => return self.respond(cmd, STATE_ILLEGAL_PARAMETER, &[]),
+                };
+                self.mode = Mode::Measuring(format);
+                self.pending_empty_reads = self.empty_reads;
+                self.respond(cmd, STATE_OK, &[]);
+            }
+            (0x01, &[]) if self.is_measuring() => {
+                self.mode = Mode::Idle;
+                self.respond(cmd, STATE_OK, &[]);
+            }
+            (0x03, &[]) => match self.mode {
+                Mode::Measuring(_) if self.pending_empty_reads > 0 => {
+                    self.pending_empty_reads -= 1;
+                    self.respond(cmd, STATE_OK, &[]);
+                }
+                Mode::Measuring(format) => self.respond_measurement(format),
+                _ => self.respond(cmd, STATE_NOT_ALLOWED, &[]),
+            },
+            (0x10, &[]) if firmware_2_0 && self.mode == Mode::Idle => {
+                self.mode = Mode::Sleeping;
+                self.respond(cmd, STATE_OK, &[]);
+            }
+            (0x11, &[]) if firmware_2_0 && self.mode == Mode::Sleeping => {
+                self.mode = Mode::Idle;
+                self.respond(cmd, STATE_OK, &[]);
+            }
+            (0x56, &[]) if self.is_measuring() => {
+                self.cleanings += 1;
+                self.respond(cmd, STATE_OK, &[]);
+            }
+            (0x80, &[0x00]) => {
+                let interval = self.cleaning_interval.to_be_bytes();
+                self.respond(cmd, STATE_OK, &interval);
+            }
+            (0x80, &[0x00, a, b, c, d]) => {
+                self.cleaning_interval = u32::from_be_bytes([a, b, c, d]);
+                self.respond(cmd, STATE_OK, &[]);
+            }
+            (0xD0, &[info]) => {
+                let string = match info {
+                    i if i == DeviceInfo::ProductName as u8 => {
+                        info_string(crate::SPS30_PRODUCT_NAME)
+                    }
+                    i if i == DeviceInfo::ArticleCode as u8 => InfoString::new(),
+                    i if i == DeviceInfo::SerialNumber as u8 => self.serial_number.clone(),
+                    _ => return self.respond(cmd, STATE_ILLEGAL_PARAMETER, &[]),
+                };
+                let mut response = [0; 33];
+                response[..string.len()].copy_from_slice(string.as_bytes());
+                self.respond(cmd, STATE_OK, &response[..=string.len()]);
+            }
+            (0xD1, &[]) => {
+                let Version { major, minor } = self.firmware;
+                self.respond(cmd, STATE_OK, &[major, minor, 0x00, 0x07, 0x00, 0x02, 0x00]);
+            }
+            (0xD2, &[clear]) if self.firmware >= Version::new(2, 2) => {
+                let status = self.status.to_be_bytes();
+                if clear == 0x01 {
+                    self.status = 0;
+                }
+                self.respond(
+                    cmd,
+                    STATE_OK,
+                    &[status[0], status[1], status[2], status[3], 0x00],
+                );
+            }
+            (0xD3, &[]) => {
+                self.mode = Mode::Idle;
+                self.respond(cmd, STATE_OK, &[]);
+            }
+            (0x00, _) | (0x01, _) | (0x10, _) | (0x11, _) | (0x56, _) => {
+                self.respond(cmd, STATE_NOT_ALLOWED, &[])
+            }
+            (0x80, _) | (0xD0, _) | (0xD1, _) | (0xD3, _) => {
+                self.respond(cmd, STATE_WRONG_DATA_LENGTH, &[])
+            }
+            _ => self.respond(cmd, STATE_UNKNOWN_COMMAND, &[]),
+        }
+    }
+
+    /// Apply `fault` to the response queued from index `start`
+    fn apply(&mut self, fault: Fault, start: usize) {
+        let mut response = Deque::<u8, ENCODED_FRAME_SIZE>::new();
+        while self.rx.len() > start {
+            if let Some(byte) = self.rx.pop_back() {
+                let _ = response.push_front(byte);
+            }
+        }
+        let len = response.len();
+        for (i, byte) in response.into_iter().enumerate() {
+            let byte = match fault {
+                Fault::NoResponse => continue,
+                Fault::DropByte if i == len / 2 => continue,
+                Fault::Truncate if i >= len / 2 => continue,
+                _ => byte,
+            };
+            let _ = self.rx.push_back(byte);
+        }
+        if let Fault::Delay(reads) = fault {
+            self.delay_reads = reads;
+        }
+    }
+
+    /// Queue the response to Read Measured Values in `format`
+    fn respond_measurement(&mut self, format: MeasurementFormat) {
+        let values = self.measurement.to_array();
+        let mut data = [0; 40];
+        let len = match format {
+            MeasurementFormat::Float => {
+                for (chunk, value) in data.chunks_exact_mut(4).zip(values.iter()) {
+                    chunk.copy_from_slice(&value.to_be_bytes());
+                }
+                40
+            }
+            MeasurementFormat::UnsignedInteger16 => {
+                for (i, (chunk, value)) in data.chunks_exact_mut(2).zip(values.iter()).enumerate() {
+                    let value = if i == 9 { value * 1000.0 } else { *value };
+                    chunk.copy_from_slice(&((value + 0.5) as u16).to_be_bytes());
+                }
+                20
+            }
+        };
+        self.respond(0x03, STATE_OK, &data[..len]);
+    }
+
+    /// Queue the MISO Frame answering `cmd` with `state` and `data`
+    fn respond(&mut self, cmd: u8, state: u8, data: &[u8]) {
+        let mut encoded = [0; ENCODED_FRAME_SIZE];
+        let header = [0x00, cmd, state, data.len() as u8];
+        let encode = if self.corrupt_checksum {
+            hdlc::encode_corrupted_frame
+        } else {
+            hdlc::encode_frame
+        };
+        if let Ok(len) = encode(&header, data, SpecialChars::default(), &mut encoded) {
+            for &byte in &encoded[..len] {
+                let _ = self.rx.push_back(byte);
+            }
+        }
+    }
+}
+
+/// Device Information string holding `value`, cut at 32 characters
+fn info_string(value: &str) -> InfoString {
+    let mut string = InfoString::new();
+    for c in value.chars() {
+        if string.push(c).is_err() {
+            break;
+        }
+    }
+    string
+}
+
+impl embedded_hal_nb::serial::ErrorType for SimulatedSps30 {
+    type Error = Infallible;
+}
+
+impl embedded_hal_nb::serial::Read<u8> for SimulatedSps30 {
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        if self.delay_reads > 0 && !self.rx.is_empty() {
+            self.delay_reads -= 1;
+            return Err(nb::Error::WouldBlock);
+        }
+        self.rx.pop_front().ok_or(nb::Error::WouldBlock)
+    }
+}
+
+impl embedded_hal_nb::serial::Write<u8> for SimulatedSps30 {
+    fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+        self.receive(byte);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DeviceError, Error, Sps30};
+
+    #[cfg(not(feature = "no-float"))]
+    #[test]
+    fn answers_like_a_device() {
+        let mut simulator = SimulatedSps30::new();
+        simulator.set_measurement(Measurement {
+            mass_pm2_5: 12.5,
+            ..Measurement::default()
+        });
+        simulator.set_empty_reads(1);
+        let mut sensor = Sps30::new(simulator);
+        #[cfg(feature = "device-info")]
+        assert_eq!(
+            sensor.device_info(DeviceInfo::ProductName).unwrap(),
+            "00080000"
+        );
+        assert_eq!(sensor.version().unwrap().firmware, Version::new(2, 2));
+
+        let mut sensor = sensor
+            .start_measurement(MeasurementFormat::Float)
+            .ok()
+            .unwrap();
+        assert!(matches!(sensor.read_measurement(), Err(Error::EmptyResult)));
+        assert_eq!(sensor.read_measurement().unwrap().mass_pm2_5, 12.5);
+        assert!(sensor.serial_mut().is_measuring());
+
+        let sensor = sensor.stop_measurement().ok().unwrap();
+        assert!(!sensor.release().is_measuring());
+    }
+
+    #[test]
+    fn injected_faults_are_retried() {
+        let mut simulator = SimulatedSps30::new();
+        assert!(simulator.inject_fault(Fault::CorruptChecksum).is_ok());
+        assert!(simulator.inject_fault(Fault::CorruptChecksum).is_ok());
+        let mut sensor = Sps30::builder(simulator).retries(2).build();
+        assert!(sensor.version().is_ok());
+        let stats = sensor.link_stats();
+        assert_eq!(stats.checksum_failures, 2);
+        assert_eq!(stats.retries, 2);
+
+        // Write Auto Cleaning Interval response checksum is 0x7F, corrupted to the flag value
+        assert!(sensor
+            .serial_mut()
+            .inject_fault(Fault::CorruptChecksum)
+            .is_ok());
+        let interval = [0x00, 0x00, 0x00, 0x00, 0x00];
+        assert!(sensor.send_raw_command(0x80, &interval).is_ok());
+        assert_eq!(sensor.link_stats().checksum_failures, 3);
+
+        // Read Version data is 02 02 00 07 00 02 00, the middle 0x00 is lost
+        assert!(sensor.serial_mut().inject_fault(Fault::DropByte).is_ok());
+        assert!(matches!(sensor.version(), Err(Error::InvalidRespose)));
+
+        assert!(sensor.serial_mut().inject_fault(Fault::State(0x43)).is_ok());
+        assert!(matches!(
+            sensor.version(),
+            Err(Error::StatusError(DeviceError::NotAllowedInState))
+        ));
+    }
+
+    #[test]
+    fn rejects_commands_not_allowed() {
+        let mut sensor = Sps30::new(SimulatedSps30::new());
+        assert!(matches!(
+            sensor.send_raw_command(0x56, &[]),
+            Err(Error::StatusError(DeviceError::NotAllowedInState))
+        ));
+    }
+}
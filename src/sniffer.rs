@@ -0,0 +1,179 @@
+//! Passive decoding of the frames exchanged between a host and a SPS30
+
+use crate::protocol::{
+    decode_frame, sync_byte, Direction, FrameSync, ENCODED_FRAME_SIZE, MAX_DATA_LEN,
+};
+use crate::{CommandType, DeviceError, ErrorKind, SpecialChars};
+use arrayvec::ArrayVec;
+
+/// Frame seen on a tapped UART line, decoded by [`Sniffer`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SniffedFrame {
+    direction: Direction,
+    address: u8,
+    cmd: u8,
+    state: u8,
+    data: heapless::Vec<u8, MAX_DATA_LEN>,
+}
+
+impl SniffedFrame {
+    /// MOSI Frame from the host, or MISO Frame from the device
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    /// ADR field, always 0 for the SPS30
+    pub fn address(&self) -> u8 {
+        self.address
+    }
+
+    /// CMD field
+    pub fn cmd(&self) -> u8 {
+        self.cmd
+    }
+
+    /// Command identified from the CMD field, `None` if unknown
+    pub fn command(&self) -> Option<CommandType> {
+        CommandType::from_code(self.cmd)
+    }
+
+    /// Error reported by the device in a MISO Frame, `None` on success and for MOSI Frames
+    pub fn error(&self) -> Option<DeviceError> {
+        match self.state {
+            0 => None,
+            state => Some(DeviceError::from(state)),
+        }
+    }
+
+    /// Data carried by the frame
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+/// Decode-only observer of the UART between a host and a SPS30
+///
+/// Feed it the bytes of the tapped TX line (MOSI) and RX line (MISO), as they come; each
+/// line is reassembled separately. Every complete frame is handed back, or the reason it
+/// could not be decoded: [`ErrorKind::Framing`] for SHDLC errors and overlong frames,
+/// [`ErrorKind::Checksum`], [`ErrorKind::InvalidResponse`] for L(ength) mismatches.
+#[derive(Debug, Default)]
+pub struct Sniffer {
+    mosi: ArrayVec<[u8; ENCODED_FRAME_SIZE]>,
+    miso: ArrayVec<[u8; ENCODED_FRAME_SIZE]>,
+}
+
+impl Sniffer {
+    /// Create a sniffer waiting for start flags on both lines
+    pub fn new() -> Self {
+        Sniffer::default()
+    }
+
+    /// Feed one byte seen on the MOSI line
+    pub fn feed_mosi(&mut self, byte: u8) -> Option<Result<SniffedFrame, ErrorKind>> {
+        self.feed(Direction::Tx, byte)
+    }
+
+    /// Feed one byte seen on the MISO line
+    pub fn feed_miso(&mut self, byte: u8) -> Option<Result<SniffedFrame, ErrorKind>> {
+        self.feed(Direction::Rx, byte)
+    }
+
+    /// Feed one byte seen in `direction`, returns a frame when `byte` completes one
+    pub fn feed(
+        &mut self,
+        direction: Direction,
+        byte: u8,
+    ) -> Option<Result<SniffedFrame, ErrorKind>> {
+        let buffer = match direction {
+            Direction::Tx => &mut self.mosi,
+            Direction::Rx => &mut self.miso,
+        };
+        let s_chars = SpecialChars::default();
+        match sync_byte(buffer, byte, s_chars.fend, ENCODED_FRAME_SIZE) {
+            FrameSync::Complete => {
+                let sniffed = decode_frame::<(), ()>(buffer, s_chars)
+                    .map_err(|e| e.kind())
+                    .and_then(|frame| split(direction, frame));
+                buffer.clear();
+                Some(sniffed)
+            }
+            FrameSync::Overflow => Some(Err(ErrorKind::Framing)),
+            FrameSync::Discarded | FrameSync::Pending => None,
+        }
+    }
+}
+
+/// Split a decoded frame, checksum included, into its fields
+fn split(direction: Direction, frame: &[u8]) -> Result<SniffedFrame, ErrorKind> {
+    let (address, cmd, state, len, data) = match (direction, frame) {
+        (Direction::Tx, [adr, cmd, len, data @ .., _]) => (*adr, *cmd, 0, *len, data),
+        (Direction::Rx, [adr, cmd, state, len, data @ .., _]) => (*adr, *cmd, *state, *len, data),
+        _ => return Err(ErrorKind::InvalidResponse),
+    };
+    if usize::from(len) != data.len() {
+        return Err(ErrorKind::InvalidResponse);
+    }
+    Ok(SniffedFrame {
+        direction,
+        address,
+        cmd,
+        state,
+        data: heapless::Vec::from_slice(data).map_err(|_| ErrorKind::InvalidResponse)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sniffs_both_lines() {
+        let mut sniffer = Sniffer::new();
+        // Read Auto Cleaning Interval, the MOSI checksum is byte-stuffed
+        let mosi = [0x7E, 0x00, 0x80, 0x01, 0x00, 0x7D, 0x5E, 0x7E];
+        let miso = [
+            0x7E, 0x00, 0x80, 0x00, 0x04, 0x00, 0x09, 0x3A, 0x80, 0xB8, 0x7E,
+        ];
+        let (last, head) = mosi.split_last().unwrap();
+        assert!(head.iter().all(|&byte| sniffer.feed_mosi(byte).is_none()));
+        let request = sniffer.feed_mosi(*last).unwrap().unwrap();
+        assert_eq!(request.direction(), Direction::Tx);
+        assert_eq!(
+            request.command(),
+            Some(CommandType::ReadWriteAutoCleaningInterval)
+        );
+        assert_eq!(request.data(), &[0x00]);
+
+        let response = miso
+            .iter()
+            .find_map(|&byte| sniffer.feed_miso(byte))
+            .unwrap()
+            .unwrap();
+        assert_eq!(response.direction(), Direction::Rx);
+        assert_eq!(response.error(), None);
+        assert_eq!(response.data(), &[0x00, 0x09, 0x3A, 0x80]);
+
+        // Start Measurement while measuring
+        let refused = [0x7E, 0x00, 0x00, 0x43, 0x00, 0xBC, 0x7E];
+        let refused = refused.iter().find_map(|&byte| sniffer.feed_miso(byte));
+        assert_eq!(
+            refused.unwrap().unwrap().error(),
+            Some(DeviceError::NotAllowedInState)
+        );
+    }
+
+    #[test]
+    fn reports_bad_frames() {
+        let mut sniffer = Sniffer::new();
+        let corrupted = [0x7E, 0x00, 0x01, 0x00, 0xFF, 0x7E];
+        let outcome = corrupted.iter().find_map(|&byte| sniffer.feed_mosi(byte));
+        assert_eq!(outcome, Some(Err(ErrorKind::Checksum)));
+
+        // L says 2 bytes, none follow
+        let short = [0x7E, 0x00, 0x01, 0x02, 0xFC, 0x7E];
+        let outcome = short.iter().find_map(|&byte| sniffer.feed_mosi(byte));
+        assert_eq!(outcome, Some(Err(ErrorKind::InvalidResponse)));
+    }
+}
@@ -0,0 +1,49 @@
+//! Link statistics
+
+/// Counters of the UART link, see [`Sps30::link_stats`](crate::Sps30::link_stats)
+///
+/// Counters wrap around on overflow.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LinkStats {
+    /// MOSI Frames sent
+    pub frames_sent: u32,
+    /// MISO Frames received, whether valid or not
+    pub frames_received: u32,
+    /// MISO Frames with a wrong checksum
+    pub checksum_failures: u32,
+    /// MISO Frames failing SHDLC decode
+    pub shdlc_errors: u32,
+    /// Commands without a response before their deadline
+    pub timeouts: u32,
+    /// Commands sent again after a failure
+    pub retries: u32,
+    /// Link errors in a row, since the last MISO Frame received intact
+    ///
+    /// Does not wrap around, set back to 0 by a recovery, see [`Config::recover_after`].
+    ///
+    /// [`Config::recover_after`]: crate::Config::recover_after
+    pub consecutive_errors: u32,
+    /// Recoveries run, see [`Sps30::recover`](crate::Sps30::recover)
+    pub recoveries: u32,
+}
+
+impl LinkStats {
+    /// Count the failure of a command
+    pub(crate) fn record_error<E, F>(&mut self, error: &crate::Error<E, F>) {
+        if error.is_link_error() {
+            self.consecutive_errors = self.consecutive_errors.saturating_add(1);
+        } else {
+            self.consecutive_errors = 0;
+        }
+        match error {
+            crate::Error::ChecksumFailed => {
+                self.checksum_failures = self.checksum_failures.wrapping_add(1)
+            }
+            crate::Error::SHDLC(_) => self.shdlc_errors = self.shdlc_errors.wrapping_add(1),
+            crate::Error::Timeout => self.timeouts = self.timeouts.wrapping_add(1),
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,96 @@
+//! Device Status Register and health summary
+
+/// Device Status Register (firmware 2.2 or newer)
+///
+/// Flags are set by the device when it detects a problem, and kept until cleared or
+/// the device is reset. Bits without a documented meaning are kept as read.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DeviceStatus(pub u32);
+
+impl DeviceStatus {
+    /// Fan speed out of range, e.g. blocked by dust or aging, warning
+    pub const FAN_SPEED: u32 = 1 << 21;
+    /// Laser current out of range, error
+    pub const LASER: u32 = 1 << 5;
+    /// Fan switched on but not turning, error
+    pub const FAN: u32 = 1 << 4;
+
+    /// Raw register value
+    pub fn bits(&self) -> u32 {
+        self.0
+    }
+
+    /// Fan speed too high or too low
+    ///
+    /// Measured values are still produced but may be less accurate.
+    pub fn fan_speed_warning(&self) -> bool {
+        self.0 & Self::FAN_SPEED != 0
+    }
+
+    /// Laser current out of range
+    pub fn laser_error(&self) -> bool {
+        self.0 & Self::LASER != 0
+    }
+
+    /// Fan switched on but measured speed is 0
+    pub fn fan_error(&self) -> bool {
+        self.0 & Self::FAN != 0
+    }
+
+    /// No flag set
+    pub fn is_ok(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+/// Health of the device, as summarized by [`Sps30::health_check`](crate::Sps30::health_check)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Health {
+    /// No problem detected
+    Ok,
+    /// Measured values are produced but may be less accurate, e.g. fan speed out of range
+    Degraded(DeviceStatus),
+    /// Laser or fan failure, or no measured values produced
+    ///
+    /// The status is the one read from the device, without any flag set when the device
+    /// itself reported no problem but stopped producing measured values.
+    Failed(DeviceStatus),
+}
+
+impl Health {
+    /// Whether measured values can be trusted
+    pub fn is_ok(&self) -> bool {
+        *self == Health::Ok
+    }
+}
+
+impl From<DeviceStatus> for Health {
+    fn from(status: DeviceStatus) -> Self {
+        if status.laser_error() || status.fan_error() {
+            Health::Failed(status)
+        } else if !status.is_ok() {
+            Health::Degraded(status)
+        } else {
+            Health::Ok
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn health_from_status() {
+        assert_eq!(Health::from(DeviceStatus(0)), Health::Ok);
+        let speed = DeviceStatus(DeviceStatus::FAN_SPEED);
+        assert!(speed.fan_speed_warning());
+        assert_eq!(Health::from(speed), Health::Degraded(speed));
+        let fan = DeviceStatus(DeviceStatus::FAN_SPEED | DeviceStatus::FAN);
+        assert!(fan.fan_error() && !fan.laser_error());
+        assert_eq!(Health::from(fan), Health::Failed(fan));
+    }
+}
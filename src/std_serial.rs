@@ -0,0 +1,128 @@
+//! Support for desktop and single-board computer serial ports through `serialport`
+//!
+//! Enabled with the `serialport` feature.
+
+use crate::Transport;
+#[cfg(feature = "linux")]
+use crate::{Idle, NoDelay, Sps30};
+use std::boxed::Box;
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+/// Read timeout of ports opened by [`SerialPort::open`], in ms
+pub(crate) const READ_TIMEOUT_MS: u64 = 100;
+
+/// Wrapper making a `std::io` serial port, e.g. a `serialport::SerialPort`, usable by the
+/// [`Sps30`](crate::Sps30) driver
+///
+/// `read` blocks until a byte is received or the port's read timeout expires. Timeouts,
+/// interrupted reads and reads returning no bytes are reported as `nb::Error::WouldBlock`,
+/// so the driver fails with [`Error::SerialR`](crate::Error::SerialR) when the device does
+/// not answer in time.
+#[derive(Debug, Default)]
+pub struct SerialPort<T>(pub T);
+
+impl SerialPort<Box<dyn serialport::SerialPort>> {
+    /// Open `path`, e.g. `/dev/ttyUSB0` or `COM3`, at 115200 baud 8N1 without flow control
+    ///
+    /// Reads time out after 100ms, the longest response time of a command plus the transfer
+    /// of the longest frame.
+    pub fn open(path: &str) -> serialport::Result<Self> {
+        serialport::new(path, crate::BAUD_RATE)
+            .data_bits(serialport::DataBits::Eight)
+            .parity(serialport::Parity::None)
+            .stop_bits(serialport::StopBits::One)
+            .flow_control(serialport::FlowControl::None)
+            .timeout(Duration::from_millis(READ_TIMEOUT_MS))
+            .open()
+            .map(SerialPort)
+    }
+}
+
+impl<T> SerialPort<T> {
+    /// Return the wrapped port
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: Read + Write> Transport for SerialPort<T> {
+    type WriteError = io::Error;
+    type ReadError = io::Error;
+
+    fn write_all(&mut self, data: &[u8]) -> io::Result<()> {
+        self.0.write_all(data)?;
+        self.0.flush()
+    }
+
+    fn read_byte(&mut self) -> nb::Result<u8, io::Error> {
+        let mut byte = [0; 1];
+        match self.0.read(&mut byte) {
+            Ok(0) => Err(nb::Error::WouldBlock),
+            Ok(_) => Ok(byte[0]),
+            Err(e) => match e.kind() {
+                io::ErrorKind::TimedOut
+                | io::ErrorKind::WouldBlock
+                | io::ErrorKind::Interrupted => Err(nb::Error::WouldBlock),
+                _ => Err(nb::Error::Other(e)),
+            },
+        }
+    }
+}
+
+#[cfg(feature = "linux")]
+impl Sps30<SerialPort<Box<dyn serialport::SerialPort>>, Idle, NoDelay> {
+    /// Open the serial port at `path`, e.g. `/dev/ttyUSB0` or `/dev/ttyAMA0`, and create a
+    /// driver talking through it
+    ///
+    /// The port is set up as done by [`SerialPort::open`]: 115200 baud 8N1, no flow control
+    /// and reads timing out after 100ms, so commands fail instead of hanging when the
+    /// device does not answer. The device is expected to be in idle mode.
+    pub fn open_linux(path: &str) -> serialport::Result<Self> {
+        SerialPort::open(path).map(Sps30::new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Port answering reads with the next canned outcome
+    struct Canned(std::vec::Vec<io::Result<u8>>);
+
+    impl Read for Canned {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.0.pop() {
+                Some(Ok(byte)) => {
+                    buf[0] = byte;
+                    Ok(1)
+                }
+                Some(Err(e)) => Err(e),
+                None => Ok(0),
+            }
+        }
+    }
+
+    impl Write for Canned {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn timeouts_would_block() {
+        let mut port = SerialPort(Canned(std::vec![
+            Err(io::ErrorKind::BrokenPipe.into()),
+            Err(io::ErrorKind::TimedOut.into()),
+            Ok(0x7E),
+        ]));
+        assert_eq!(port.read_byte().ok(), Some(0x7E));
+        assert!(matches!(port.read_byte(), Err(nb::Error::WouldBlock)));
+        assert!(matches!(port.read_byte(), Err(nb::Error::Other(_))));
+        assert!(matches!(port.read_byte(), Err(nb::Error::WouldBlock)));
+    }
+}
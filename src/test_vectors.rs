@@ -0,0 +1,252 @@
+//! MOSI and MISO Frames of every command, for validating transports
+//!
+//! Frames follow the examples of section 5.3 of the datasheet, byte-stuffing included.
+//! Send [`TestVector::mosi`] through a transport and feed [`TestVector::miso`] back to
+//! check encoding, decoding and error handling end-to-end, without a device.
+
+use crate::CommandType;
+
+/// Request and response of one command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TestVector {
+    /// Command sent
+    pub command: CommandType,
+    /// Data carried by the MOSI Frame
+    pub request: &'static [u8],
+    /// MOSI Frame, SHDLC encoded with start and stop flags
+    pub mosi: &'static [u8],
+    /// State of the MISO Frame, 0 on success
+    pub state: u8,
+    /// Data carried by the MISO Frame
+    pub response: &'static [u8],
+    /// MISO Frame, SHDLC encoded with start and stop flags
+    pub miso: &'static [u8],
+}
+
+/// Start Measurement, float output format
+pub const START_MEASUREMENT: TestVector = TestVector {
+    command: CommandType::StartMeasurement,
+    request: &[0x01, 0x03],
+    mosi: &[0x7E, 0x00, 0x00, 0x02, 0x01, 0x03, 0xF9, 0x7E],
+    state: 0x00,
+    response: &[],
+    miso: &[0x7E, 0x00, 0x00, 0x00, 0x00, 0xFF, 0x7E],
+};
+
+/// Start Measurement while already measuring, answered with State 0x43
+pub const START_MEASUREMENT_NOT_ALLOWED: TestVector = TestVector {
+    command: CommandType::StartMeasurement,
+    request: &[0x01, 0x03],
+    mosi: &[0x7E, 0x00, 0x00, 0x02, 0x01, 0x03, 0xF9, 0x7E],
+    state: 0x43,
+    response: &[],
+    miso: &[0x7E, 0x00, 0x00, 0x43, 0x00, 0xBC, 0x7E],
+};
+
+/// Stop Measurement
+pub const STOP_MEASUREMENT: TestVector = TestVector {
+    command: CommandType::StopMeasurement,
+    request: &[],
+    mosi: &[0x7E, 0x00, 0x01, 0x00, 0xFE, 0x7E],
+    state: 0x00,
+    response: &[],
+    miso: &[0x7E, 0x00, 0x01, 0x00, 0x00, 0xFE, 0x7E],
+};
+
+/// Read Measured Values: 5, 10, 12.5 and 13 µg/m³, 30, 45, 50, 51 and 52 #/cm³, 0.5 µm
+pub const READ_MEASURED_VALUES: TestVector = TestVector {
+    command: CommandType::ReadMeasuredData,
+    request: &[],
+    mosi: &[0x7E, 0x00, 0x03, 0x00, 0xFC, 0x7E],
+    state: 0x00,
+    response: &[
+        0x40, 0xA0, 0x00, 0x00, 0x41, 0x20, 0x00, 0x00, 0x41, 0x48, 0x00, 0x00, 0x41, 0x50, 0x00,
+        0x00, 0x41, 0xF0, 0x00, 0x00, 0x42, 0x34, 0x00, 0x00, 0x42, 0x48, 0x00, 0x00, 0x42, 0x4C,
+        0x00, 0x00, 0x42, 0x50, 0x00, 0x00, 0x3F, 0x00, 0x00, 0x00,
+    ],
+    miso: &[
+        0x7E, 0x00, 0x03, 0x00, 0x28, 0x40, 0xA0, 0x00, 0x00, 0x41, 0x20, 0x00, 0x00, 0x41, 0x48,
+        0x00, 0x00, 0x41, 0x50, 0x00, 0x00, 0x41, 0xF0, 0x00, 0x00, 0x42, 0x34, 0x00, 0x00, 0x42,
+        0x48, 0x00, 0x00, 0x42, 0x4C, 0x00, 0x00, 0x42, 0x50, 0x00, 0x00, 0x3F, 0x00, 0x00, 0x00,
+        0xE9, 0x7E,
+    ],
+};
+
+/// Read Measured Values while no new values are available
+pub const READ_MEASURED_VALUES_EMPTY: TestVector = TestVector {
+    command: CommandType::ReadMeasuredData,
+    request: &[],
+    mosi: &[0x7E, 0x00, 0x03, 0x00, 0xFC, 0x7E],
+    state: 0x00,
+    response: &[],
+    miso: &[0x7E, 0x00, 0x03, 0x00, 0x00, 0xFC, 0x7E],
+};
+
+/// Read Auto Cleaning Interval: 604800 s, the default
+pub const READ_CLEANING_INTERVAL: TestVector = TestVector {
+    command: CommandType::ReadWriteAutoCleaningInterval,
+    request: &[0x00],
+    mosi: &[0x7E, 0x00, 0x80, 0x01, 0x00, 0x7D, 0x5E, 0x7E],
+    state: 0x00,
+    response: &[0x00, 0x09, 0x3A, 0x80],
+    miso: &[
+        0x7E, 0x00, 0x80, 0x00, 0x04, 0x00, 0x09, 0x3A, 0x80, 0xB8, 0x7E,
+    ],
+};
+
+/// Write Auto Cleaning Interval: 0 s, disabled
+pub const WRITE_CLEANING_INTERVAL: TestVector = TestVector {
+    command: CommandType::ReadWriteAutoCleaningInterval,
+    request: &[0x00, 0x00, 0x00, 0x00, 0x00],
+    mosi: &[
+        0x7E, 0x00, 0x80, 0x05, 0x00, 0x00, 0x00, 0x00, 0x00, 0x7A, 0x7E,
+    ],
+    state: 0x00,
+    response: &[],
+    miso: &[0x7E, 0x00, 0x80, 0x00, 0x00, 0x7F, 0x7E],
+};
+
+/// Start Fan Cleaning
+pub const START_FAN_CLEANING: TestVector = TestVector {
+    command: CommandType::StartFanCleaning,
+    request: &[],
+    mosi: &[0x7E, 0x00, 0x56, 0x00, 0xA9, 0x7E],
+    state: 0x00,
+    response: &[],
+    miso: &[0x7E, 0x00, 0x56, 0x00, 0x00, 0xA9, 0x7E],
+};
+
+/// Device Information, Product Type: `00080000`
+pub const PRODUCT_TYPE: TestVector = TestVector {
+    command: CommandType::DeviceInformation,
+    request: &[0x00],
+    mosi: &[0x7E, 0x00, 0xD0, 0x01, 0x00, 0x2E, 0x7E],
+    state: 0x00,
+    response: &[0x30, 0x30, 0x30, 0x38, 0x30, 0x30, 0x30, 0x30, 0x00],
+    miso: &[
+        0x7E, 0x00, 0xD0, 0x00, 0x09, 0x30, 0x30, 0x30, 0x38, 0x30, 0x30, 0x30, 0x30, 0x00, 0x9E,
+        0x7E,
+    ],
+};
+
+/// Device Information, Serial Number: `ABCDEF0123456789`, length byte-stuffed
+pub const SERIAL_NUMBER: TestVector = TestVector {
+    command: CommandType::DeviceInformation,
+    request: &[0x03],
+    mosi: &[0x7E, 0x00, 0xD0, 0x01, 0x03, 0x2B, 0x7E],
+    state: 0x00,
+    response: &[
+        0x41, 0x42, 0x43, 0x44, 0x45, 0x46, 0x30, 0x31, 0x32, 0x33, 0x34, 0x35, 0x36, 0x37, 0x38,
+        0x39, 0x00,
+    ],
+    miso: &[
+        0x7E, 0x00, 0xD0, 0x00, 0x7D, 0x31, 0x41, 0x42, 0x43, 0x44, 0x45, 0x46, 0x30, 0x31, 0x32,
+        0x33, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x00, 0x7C, 0x7E,
+    ],
+};
+
+/// Read Version: firmware 2.2, hardware revision 7, SHDLC 2.0
+pub const READ_VERSION: TestVector = TestVector {
+    command: CommandType::ReadVersion,
+    request: &[],
+    mosi: &[0x7E, 0x00, 0xD1, 0x00, 0x2E, 0x7E],
+    state: 0x00,
+    response: &[0x02, 0x02, 0x00, 0x07, 0x00, 0x02, 0x00],
+    miso: &[
+        0x7E, 0x00, 0xD1, 0x00, 0x07, 0x02, 0x02, 0x00, 0x07, 0x00, 0x02, 0x00, 0x1A, 0x7E,
+    ],
+};
+
+/// Read Device Status Register: fan speed warning
+pub const READ_DEVICE_STATUS: TestVector = TestVector {
+    command: CommandType::ReadDeviceStatusRegister,
+    request: &[0x00],
+    mosi: &[0x7E, 0x00, 0xD2, 0x01, 0x00, 0x2C, 0x7E],
+    state: 0x00,
+    response: &[0x00, 0x20, 0x00, 0x00, 0x00],
+    miso: &[
+        0x7E, 0x00, 0xD2, 0x00, 0x05, 0x00, 0x20, 0x00, 0x00, 0x00, 0x08, 0x7E,
+    ],
+};
+
+/// Device Reset
+pub const RESET: TestVector = TestVector {
+    command: CommandType::Reset,
+    request: &[],
+    mosi: &[0x7E, 0x00, 0xD3, 0x00, 0x2C, 0x7E],
+    state: 0x00,
+    response: &[],
+    miso: &[0x7E, 0x00, 0xD3, 0x00, 0x00, 0x2C, 0x7E],
+};
+
+/// Sleep
+pub const SLEEP: TestVector = TestVector {
+    command: CommandType::Sleep,
+    request: &[],
+    mosi: &[0x7E, 0x00, 0x10, 0x00, 0xEF, 0x7E],
+    state: 0x00,
+    response: &[],
+    miso: &[0x7E, 0x00, 0x10, 0x00, 0x00, 0xEF, 0x7E],
+};
+
+/// Wake-up, CMD byte-stuffed
+pub const WAKE_UP: TestVector = TestVector {
+    command: CommandType::WakeUp,
+    request: &[],
+    mosi: &[0x7E, 0x00, 0x7D, 0x31, 0x00, 0xEE, 0x7E],
+    state: 0x00,
+    response: &[],
+    miso: &[0x7E, 0x00, 0x7D, 0x31, 0x00, 0x00, 0xEE, 0x7E],
+};
+
+/// All test vectors
+pub const ALL: &[TestVector] = &[
+    START_MEASUREMENT,
+    START_MEASUREMENT_NOT_ALLOWED,
+    STOP_MEASUREMENT,
+    READ_MEASURED_VALUES,
+    READ_MEASURED_VALUES_EMPTY,
+    READ_CLEANING_INTERVAL,
+    WRITE_CLEANING_INTERVAL,
+    START_FAN_CLEANING,
+    PRODUCT_TYPE,
+    SERIAL_NUMBER,
+    READ_VERSION,
+    READ_DEVICE_STATUS,
+    RESET,
+    SLEEP,
+    WAKE_UP,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{self, FrameParser};
+    use crate::{DeviceError, Error};
+
+    #[test]
+    fn vectors_match_protocol() {
+        for vector in ALL {
+            let mosi = protocol::encode_request::<(), ()>(vector.command, vector.request).unwrap();
+            assert_eq!(&mosi[..], vector.mosi, "{:?}", vector.command);
+
+            let response = protocol::parse_response::<(), ()>(vector.miso, vector.command);
+            match response {
+                Ok(response) => {
+                    assert_eq!(vector.state, 0);
+                    assert_eq!(response.data(), vector.response);
+                }
+                Err(Error::StatusError(e)) => assert_eq!(e, DeviceError::from(vector.state)),
+                Err(e) => panic!("{:?}: {:?}", vector.command, e),
+            }
+
+            let mut parser = FrameParser::new();
+            let frames = vector
+                .miso
+                .iter()
+                .filter_map(|&byte| parser.feed_byte(byte))
+                .count();
+            assert_eq!(frames, 1);
+        }
+    }
+}
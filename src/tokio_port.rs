@@ -0,0 +1,80 @@
+//! Support for `tokio-serial` ports with the async driver
+//!
+//! Enabled with the `tokio-serial` feature.
+
+use core::time::Duration;
+use embedded_io_async::{ErrorType, Read, Write};
+use std::io;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio_serial::{SerialPortBuilderExt, SerialStream};
+
+/// Read timeout of new [`TokioSerial`] ports, in ms
+const READ_TIMEOUT_MS: u64 = 100;
+
+/// Wrapper making a tokio byte stream, by default a `tokio_serial::SerialStream`, usable by
+/// the [`Sps30Async`](crate::Sps30Async) driver
+///
+/// Reads fail with `std::io::ErrorKind::TimedOut` when no byte is received within the read
+/// timeout, so a missing device does not hang the daemon.
+#[derive(Debug)]
+pub struct TokioSerial<T = SerialStream> {
+    port: T,
+    read_timeout: Duration,
+}
+
+impl TokioSerial<SerialStream> {
+    /// Open `path`, e.g. `/dev/ttyUSB0`, at 115200 baud 8N1 without flow control
+    ///
+    /// Must be called from within a tokio runtime.
+    pub fn open(path: &str) -> tokio_serial::Result<Self> {
+        tokio_serial::new(path, crate::BAUD_RATE)
+            .data_bits(tokio_serial::DataBits::Eight)
+            .parity(tokio_serial::Parity::None)
+            .stop_bits(tokio_serial::StopBits::One)
+            .flow_control(tokio_serial::FlowControl::None)
+            .open_native_async()
+            .map(TokioSerial::new)
+    }
+}
+
+impl<T> TokioSerial<T> {
+    /// Wrap `port`, reads time out after 100ms
+    pub fn new(port: T) -> Self {
+        TokioSerial {
+            port,
+            read_timeout: Duration::from_millis(READ_TIMEOUT_MS),
+        }
+    }
+
+    /// Set the time a read waits for a byte
+    pub fn set_read_timeout(&mut self, timeout: Duration) {
+        self.read_timeout = timeout;
+    }
+
+    /// Return the wrapped port
+    pub fn into_inner(self) -> T {
+        self.port
+    }
+}
+
+impl<T> ErrorType for TokioSerial<T> {
+    type Error = io::Error;
+}
+
+impl<T: AsyncRead + Unpin> Read for TokioSerial<T> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, io::Error> {
+        tokio::time::timeout(self.read_timeout, self.port.read(buf))
+            .await
+            .unwrap_or_else(|_| Err(io::ErrorKind::TimedOut.into()))
+    }
+}
+
+impl<T: AsyncWrite + Unpin> Write for TokioSerial<T> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, io::Error> {
+        self.port.write(buf).await
+    }
+
+    async fn flush(&mut self) -> Result<(), io::Error> {
+        self.port.flush().await
+    }
+}
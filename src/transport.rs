@@ -0,0 +1,145 @@
+//! Serial transport used by the UART driver
+
+/// Byte oriented serial port the [`Sps30`](crate::Sps30) driver talks through
+///
+/// Implemented for every serial port implementing the embedded-hal 1.0 `embedded-hal-nb`
+/// serial traits (feature `embedded-hal-nb`, enabled by default).
+///
+/// Any other byte stream, e.g. Web Serial from WASM or an RTT channel tunneled to a host,
+/// plugs in by implementing these two methods, without embedded-hal:
+///
+/// - `write_all` hands a whole MOSI Frame over; it may queue the bytes, as long as they
+///   are eventually sent in order.
+/// - `read_byte` is polled: it returns `nb::Error::WouldBlock` while no byte is available,
+///   never waits itself. The driver polls again every 100μs until the response deadline
+///   when given a timer with [`Sps30::with_timeout`](crate::Sps30::with_timeout), and
+///   gives up at the first `WouldBlock` otherwise. A queue filled from a receive callback
+///   is enough.
+pub trait Transport {
+    /// Error returned when writing
+    type WriteError;
+    /// Error returned when reading
+    type ReadError;
+
+    /// Write all bytes, blocking until they are sent
+    fn write_all(&mut self, data: &[u8]) -> Result<(), Self::WriteError>;
+
+    /// Read a single byte
+    fn read_byte(&mut self) -> nb::Result<u8, Self::ReadError>;
+
+    /// Whether the port is gone, e.g. an unplugged USB-serial adapter
+    ///
+    /// Checked after a failed read or write: the driver then reports
+    /// [`Error::Disconnected`](crate::Error::Disconnected) instead of the port error.
+    fn is_disconnected(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(feature = "embedded-hal-nb")]
+impl<SERIAL> Transport for SERIAL
+where
+    SERIAL: embedded_hal_nb::serial::Read<u8> + embedded_hal_nb::serial::Write<u8>,
+{
+    type WriteError = SERIAL::Error;
+    type ReadError = SERIAL::Error;
+
+    fn write_all(&mut self, data: &[u8]) -> Result<(), Self::WriteError> {
+        for &byte in data.iter() {
+            nb::block!(self.write(byte))?;
+        }
+        nb::block!(self.flush())
+    }
+
+    fn read_byte(&mut self) -> nb::Result<u8, Self::ReadError> {
+        self.read()
+    }
+}
+
+/// Serial port made of separate transmitter and receiver halves
+///
+/// Many HALs hand out independent Tx and Rx types once the UART is split, pass both to
+/// [`Sps30::new`](crate::Sps30::new) with `Sps30::new(Split::new(tx, rx))`.
+#[derive(Debug, Default)]
+pub struct Split<TX, RX> {
+    /// Transmitter half
+    pub tx: TX,
+    /// Receiver half
+    pub rx: RX,
+}
+
+impl<TX, RX> Split<TX, RX> {
+    /// Combine the transmitter and receiver halves
+    pub fn new(tx: TX, rx: RX) -> Self {
+        Split { tx, rx }
+    }
+
+    /// Return the transmitter and receiver halves
+    pub fn into_inner(self) -> (TX, RX) {
+        (self.tx, self.rx)
+    }
+}
+
+#[cfg(feature = "embedded-hal-nb")]
+impl<TX, RX> Transport for Split<TX, RX>
+where
+    TX: embedded_hal_nb::serial::Write<u8>,
+    RX: embedded_hal_nb::serial::Read<u8>,
+{
+    type WriteError = TX::Error;
+    type ReadError = RX::Error;
+
+    fn write_all(&mut self, data: &[u8]) -> Result<(), Self::WriteError> {
+        for &byte in data.iter() {
+            nb::block!(self.tx.write(byte))?;
+        }
+        nb::block!(self.tx.flush())
+    }
+
+    fn read_byte(&mut self) -> nb::Result<u8, Self::ReadError> {
+        self.rx.read()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MeasurementFormat, Sps30};
+    use heapless::Deque;
+
+    /// Byte queues standing for a stream fed by callbacks, e.g. Web Serial
+    #[derive(Default)]
+    struct Queued {
+        tx: Deque<u8, 64>,
+        rx: Deque<u8, 64>,
+    }
+
+    impl Transport for Queued {
+        type WriteError = u8;
+        type ReadError = ();
+
+        fn write_all(&mut self, data: &[u8]) -> Result<(), u8> {
+            data.iter().try_for_each(|&byte| self.tx.push_back(byte))
+        }
+
+        fn read_byte(&mut self) -> nb::Result<u8, ()> {
+            self.rx.pop_front().ok_or(nb::Error::WouldBlock)
+        }
+    }
+
+    #[test]
+    fn queue_backed_transport() {
+        let mut port = Queued::default();
+        // Start Measurement response, received before the driver polls
+        for &byte in &[0x7E, 0x00, 0x00, 0x00, 0x00, 0xFF, 0x7E] {
+            port.rx.push_back(byte).unwrap();
+        }
+        let sensor = Sps30::new(port)
+            .start_measurement(MeasurementFormat::Float)
+            .ok()
+            .unwrap();
+        let port = sensor.release();
+        assert_eq!(port.tx.front(), Some(&0x7E));
+        assert!(port.rx.is_empty());
+    }
+}
@@ -0,0 +1,75 @@
+//! Measured values as physical quantities
+//!
+//! Enabled with the `uom` feature, so unit mistakes (μg/m³ vs mg/m³) are caught at compile
+//! time.
+
+use crate::{AutoCleanInterval, Measurement};
+use uom::si::f32::{Length, MassConcentration, Time, VolumetricNumberDensity};
+use uom::si::length::micrometer;
+use uom::si::mass_concentration::microgram_per_cubic_meter;
+use uom::si::time::second;
+use uom::si::volumetric_number_density::per_cubic_centimeter;
+
+/// [`Measurement`] with each value as a quantity
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quantities {
+    /// Mass Concentration PM1.0
+    pub mass_pm1_0: MassConcentration,
+    /// Mass Concentration PM2.5
+    pub mass_pm2_5: MassConcentration,
+    /// Mass Concentration PM4.0
+    pub mass_pm4_0: MassConcentration,
+    /// Mass Concentration PM10
+    pub mass_pm10: MassConcentration,
+    /// Number Concentration PM0.5
+    pub nc_pm0_5: VolumetricNumberDensity,
+    /// Number Concentration PM1.0
+    pub nc_pm1_0: VolumetricNumberDensity,
+    /// Number Concentration PM2.5
+    pub nc_pm2_5: VolumetricNumberDensity,
+    /// Number Concentration PM4.0
+    pub nc_pm4_0: VolumetricNumberDensity,
+    /// Number Concentration PM10
+    pub nc_pm10: VolumetricNumberDensity,
+    /// Typical Particle Size
+    pub typical_particle_size: Length,
+}
+
+impl Measurement {
+    /// Values as quantities
+    pub fn to_quantities(&self) -> Quantities {
+        let mass = MassConcentration::new::<microgram_per_cubic_meter>;
+        let number = VolumetricNumberDensity::new::<per_cubic_centimeter>;
+        Quantities {
+            mass_pm1_0: mass(self.mass_pm1_0),
+            mass_pm2_5: mass(self.mass_pm2_5),
+            mass_pm4_0: mass(self.mass_pm4_0),
+            mass_pm10: mass(self.mass_pm10),
+            nc_pm0_5: number(self.nc_pm0_5),
+            nc_pm1_0: number(self.nc_pm1_0),
+            nc_pm2_5: number(self.nc_pm2_5),
+            nc_pm4_0: number(self.nc_pm4_0),
+            nc_pm10: number(self.nc_pm10),
+            typical_particle_size: Length::new::<micrometer>(self.typical_particle_size),
+        }
+    }
+}
+
+impl From<Measurement> for Quantities {
+    fn from(measurement: Measurement) -> Self {
+        measurement.to_quantities()
+    }
+}
+
+/// Cleaning interval, as returned by `read_cleaning_interval`, as a quantity
+pub fn cleaning_interval(interval: AutoCleanInterval) -> Time {
+    Time::new::<second>(interval.as_secs() as f32)
+}
+
+/// Cleaning interval in whole seconds, as taken by `write_cleaning_interval`
+///
+/// Negative intervals count as 0, disabling the auto cleaning, fractions of a second are
+/// dropped.
+pub fn cleaning_interval_seconds(interval: Time) -> AutoCleanInterval {
+    AutoCleanInterval::from_secs(interval.get::<second>() as u32)
+}
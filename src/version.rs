@@ -0,0 +1,34 @@
+//! Firmware, hardware and protocol versions
+
+/// A major.minor version number
+///
+/// Versions compare by major first, then minor.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Version {
+    /// Major version
+    pub major: u8,
+    /// Minor version
+    pub minor: u8,
+}
+
+impl Version {
+    /// Create a version number
+    pub const fn new(major: u8, minor: u8) -> Self {
+        Version { major, minor }
+    }
+}
+
+/// Versions returned by the Read Version command
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Versions {
+    /// Firmware version, sleep/wake-up and unsigned 16 bit output need 2.0 or newer
+    pub firmware: Version,
+    /// Hardware revision
+    pub hardware_revision: u8,
+    /// SHDLC protocol version
+    pub shdlc: Version,
+}
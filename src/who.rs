@@ -0,0 +1,170 @@
+//! Comparison with the WHO 2021 air quality guidelines
+
+use crate::Measurement;
+
+/// WHO 2021 guideline for 24 hours mean PM2.5, in μg/m³
+pub const PM2_5_24H: f32 = 15.0;
+
+/// WHO 2021 guideline for 24 hours mean PM10, in μg/m³
+pub const PM10_24H: f32 = 45.0;
+
+/// Comparison of a 24 hours mean with its guideline value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum GuidelineStatus {
+    /// Mean at or below the guideline value
+    Within,
+    /// Mean above the guideline value
+    Exceeded,
+}
+
+impl GuidelineStatus {
+    /// Compare `mean` with `guideline`
+    fn of(mean: f32, guideline: f32) -> Self {
+        if mean > guideline {
+            GuidelineStatus::Exceeded
+        } else {
+            GuidelineStatus::Within
+        }
+    }
+}
+
+/// Status of each pollutant, `None` without data for the last 24 hours
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Exceedance {
+    /// PM2.5 against [`PM2_5_24H`]
+    pub pm2_5: Option<GuidelineStatus>,
+    /// PM10 against [`PM10_24H`]
+    pub pm10: Option<GuidelineStatus>,
+}
+
+/// Sums of the measurements of one hour
+#[derive(Debug, Default, Clone, Copy)]
+struct Hour {
+    /// Hour number, `time_s / 3600`
+    hour: u32,
+    /// Sum of PM2.5 mass concentrations
+    pm2_5: f32,
+    /// Sum of PM10 mass concentrations
+    pm10: f32,
+    /// Number of measurements summed
+    count: u32,
+}
+
+/// Rolling 24 hours means of PM2.5 and PM10
+///
+/// Measurements are grouped by hour, the means are the means of the hourly means of the
+/// last 24 hours, so irregular sampling does not bias them.
+#[derive(Debug, Default, Clone)]
+pub struct DailyMean {
+    /// One slot per hour of the day, `hour % 24`
+    hours: [Hour; 24],
+    /// Most recent hour a measurement was added for
+    latest: Option<u32>,
+}
+
+impl DailyMean {
+    /// No measurement yet
+    pub fn new() -> Self {
+        DailyMean::default()
+    }
+
+    /// Add `measurement`, taken at `time_s` seconds
+    ///
+    /// Time is provided by the caller, from any origin, only its hour is used. Measurements
+    /// older than 24 hours before the most recent one are ignored.
+    pub fn add(&mut self, time_s: u32, measurement: &Measurement) {
+        let hour = time_s / 3600;
+        let latest = self.latest.map_or(hour, |latest| latest.max(hour));
+        if latest - hour >= 24 {
+            return;
+        }
+        self.latest = Some(latest);
+
+        let slot = &mut self.hours[(hour % 24) as usize];
+        if slot.hour != hour || slot.count == 0 {
+            *slot = Hour {
+                hour,
+                ..Hour::default()
+            };
+        }
+        slot.pm2_5 += measurement.mass_pm2_5;
+        slot.pm10 += measurement.mass_pm10;
+        slot.count += 1;
+    }
+
+    /// Mean PM2.5 mass concentration over the last 24 hours
+    pub fn pm2_5(&self) -> Option<f32> {
+        self.mean(|hour| hour.pm2_5)
+    }
+
+    /// Mean PM10 mass concentration over the last 24 hours
+    pub fn pm10(&self) -> Option<f32> {
+        self.mean(|hour| hour.pm10)
+    }
+
+    /// Compare the means with the WHO 2021 24 hours guidelines
+    pub fn exceedance(&self) -> Exceedance {
+        Exceedance {
+            pm2_5: self
+                .pm2_5()
+                .map(|mean| GuidelineStatus::of(mean, PM2_5_24H)),
+            pm10: self.pm10().map(|mean| GuidelineStatus::of(mean, PM10_24H)),
+        }
+    }
+
+    /// Mean of the hourly means of `sum` over the last 24 hours
+    fn mean(&self, sum: impl Fn(&Hour) -> f32) -> Option<f32> {
+        let latest = self.latest?;
+        let (total, hours) = self
+            .hours
+            .iter()
+            .filter(|hour| hour.count > 0 && latest - hour.hour < 24)
+            .fold((0.0, 0), |(total, hours), hour| {
+                (total + sum(hour) / hour.count as f32, hours + 1)
+            });
+        if hours == 0 {
+            return None;
+        }
+        Some(total / hours as f32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn measurement(pm2_5: f32, pm10: f32) -> Measurement {
+        Measurement {
+            mass_pm2_5: pm2_5,
+            mass_pm10: pm10,
+            ..Measurement::default()
+        }
+    }
+
+    #[test]
+    fn rolling_daily_mean() {
+        let mut daily = DailyMean::new();
+        assert_eq!(daily.exceedance().pm2_5, None);
+
+        // Hourly means of 10 and 30
+        daily.add(0, &measurement(10.0, 20.0));
+        daily.add(3600, &measurement(20.0, 20.0));
+        daily.add(3601, &measurement(40.0, 20.0));
+        assert_eq!(daily.pm2_5(), Some(20.0));
+        assert_eq!(
+            daily.exceedance(),
+            Exceedance {
+                pm2_5: Some(GuidelineStatus::Exceeded),
+                pm10: Some(GuidelineStatus::Within),
+            }
+        );
+
+        // First hour out of the window
+        daily.add(24 * 3600, &measurement(10.0, 20.0));
+        assert_eq!(daily.pm2_5(), Some(20.0));
+    }
+}
@@ -0,0 +1,140 @@
+//! Property-based tests of the checksum and SHDLC framing
+
+use proptest::prelude::*;
+use sps30::protocol::{self, FrameParser, Validation};
+use sps30::{CommandType, SpecialChars};
+
+/// Commands of the datasheet
+const COMMANDS: [CommandType; 11] = [
+    CommandType::StartMeasurement,
+    CommandType::StopMeasurement,
+    CommandType::ReadMeasuredData,
+    CommandType::ReadWriteAutoCleaningInterval,
+    CommandType::StartFanCleaning,
+    CommandType::DeviceInformation,
+    CommandType::Reset,
+    CommandType::Sleep,
+    CommandType::WakeUp,
+    CommandType::ReadVersion,
+    CommandType::ReadDeviceStatusRegister,
+];
+
+/// Checksum as defined in the datasheet: LSB of the sum of all bytes, inverted
+fn reference_cksum(data: &[u8]) -> u8 {
+    let sum: u32 = data.iter().map(|&byte| u32::from(byte)).sum();
+    !(sum as u8)
+}
+
+/// Byte-stuffing as defined in the datasheet, start and stop flags included
+fn reference_stuff(frame: &[u8]) -> Vec<u8> {
+    let mut stuffed = vec![0x7E];
+    for &byte in frame {
+        match byte {
+            0x7E => stuffed.extend_from_slice(&[0x7D, 0x5E]),
+            0x7D => stuffed.extend_from_slice(&[0x7D, 0x5D]),
+            0x11 => stuffed.extend_from_slice(&[0x7D, 0x31]),
+            0x13 => stuffed.extend_from_slice(&[0x7D, 0x33]),
+            _ => stuffed.push(byte),
+        }
+    }
+    stuffed.push(0x7E);
+    stuffed
+}
+
+/// Payload bytes, special characters being as likely as all other bytes together
+fn payload() -> impl Strategy<Value = Vec<u8>> {
+    let byte = prop_oneof![
+        any::<u8>(),
+        prop::sample::select(vec![0x7E, 0x7D, 0x11, 0x13]),
+    ];
+    prop::collection::vec(byte, 0..=protocol::MAX_DATA_LEN)
+}
+
+fn command() -> impl Strategy<Value = CommandType> {
+    prop::sample::select(COMMANDS.to_vec())
+}
+
+proptest! {
+    #[test]
+    fn cksum_matches_datasheet(data in prop::collection::vec(any::<u8>(), 0..64)) {
+        prop_assert_eq!(protocol::compute_cksum(&data), reference_cksum(&data));
+    }
+
+    #[test]
+    fn mosi_frames_are_stuffed_as_datasheet(cmd in command(), data in payload()) {
+        let encoded = protocol::encode_request::<(), ()>(cmd, &data).unwrap();
+        let mut frame = vec![0x00, cmd as u8, data.len() as u8];
+        frame.extend_from_slice(&data);
+        frame.push(reference_cksum(&frame));
+        prop_assert_eq!(&encoded[..], &reference_stuff(&frame)[..]);
+
+        let mut buf = [0; protocol::ENCODED_FRAME_SIZE];
+        let len = protocol::encode_request_into::<(), ()>(
+            cmd,
+            &data,
+            SpecialChars::default(),
+            &mut buf,
+        )
+        .unwrap();
+        prop_assert_eq!(&buf[..len], &encoded[..]);
+    }
+
+    #[test]
+    fn mosi_frames_round_trip(cmd in command(), data in payload()) {
+        let encoded = protocol::encode_request::<(), ()>(cmd, &data).unwrap();
+        let inner = &encoded[1..encoded.len() - 1];
+        prop_assert!(!inner.iter().any(|byte| [0x7E, 0x11, 0x13].contains(byte)));
+
+        let mut parser = FrameParser::new();
+        let (last, head) = encoded.split_last().unwrap();
+        for &byte in head {
+            prop_assert!(parser.feed_byte(byte).is_none());
+        }
+        let frame = parser.feed_byte(*last).unwrap();
+        let decoded = frame.as_bytes();
+        prop_assert_eq!(decoded[1], cmd as u8);
+        prop_assert_eq!(&decoded[3..decoded.len() - 1], &data[..]);
+    }
+
+    #[test]
+    fn miso_frames_round_trip(
+        cmd in command(),
+        data in payload(),
+        lenient in any::<bool>(),
+    ) {
+        let mut frame = vec![0x00, cmd as u8, 0x00, data.len() as u8];
+        frame.extend_from_slice(&data);
+        frame.push(reference_cksum(&frame));
+        let wire = reference_stuff(&frame);
+        let validation = if lenient { Validation::Lenient } else { Validation::Strict };
+
+        let response = protocol::parse_response_with::<(), ()>(
+            &wire,
+            cmd,
+            SpecialChars::default(),
+            validation,
+        )
+        .unwrap();
+        prop_assert_eq!(response.data(), &data[..]);
+
+        let mut buf = [0; protocol::FRAME_SIZE];
+        let data_into = protocol::parse_response_into::<(), ()>(
+            &wire,
+            cmd,
+            SpecialChars::default(),
+            validation,
+            &mut buf,
+        )
+        .unwrap();
+        prop_assert_eq!(data_into, &data[..]);
+    }
+
+    #[test]
+    fn corrupted_checksum_is_rejected(cmd in command(), data in payload(), flip in 1..=255u8) {
+        let mut frame = vec![0x00, cmd as u8, 0x00, data.len() as u8];
+        frame.extend_from_slice(&data);
+        frame.push(reference_cksum(&frame) ^ flip);
+        let wire = reference_stuff(&frame);
+        prop_assert!(protocol::parse_response::<(), ()>(&wire, cmd).is_err());
+    }
+}
@@ -0,0 +1,360 @@
+//! UART driver against `embedded-hal-mock` serial transactions
+//!
+//! MOSI Frames are checked byte for byte. Frames are taken from `sps30::test_vectors` when
+//! one covers the command, otherwise written in the datasheet format:
+//! 0x7E ADR CMD State L data CHK 0x7E, with 0x7E, 0x7D, 0x11 and 0x13 escaped.
+
+#![cfg(all(feature = "embedded-hal-nb", feature = "test-vectors"))]
+
+use embedded_hal_mock::eh1::serial::{Mock, Transaction};
+use embedded_hal_nb::nb;
+use embedded_hal_nb::serial::ErrorKind;
+use sps30::test_vectors::{self, TestVector};
+#[cfg(feature = "cleaning")]
+use sps30::AutoCleanInterval;
+#[cfg(feature = "device-info")]
+use sps30::DeviceInfo;
+use sps30::{DeviceError, Error, MeasurementFormat, Sps30, Version};
+
+/// Read Version response, firmware 1.0
+const VERSION_1_0: &[u8] = &[
+    0x7E, 0x00, 0xD1, 0x00, 0x07, 0x01, 0x00, 0x00, 0x07, 0x00, 0x02, 0x00, 0x1D, 0x7E,
+];
+
+/// Transactions of a command: MOSI Frame written and flushed, then MISO Frame read
+fn command(request: &[u8], response: &[u8]) -> Vec<Transaction<u8>> {
+    vec![
+        Transaction::write_many(request),
+        Transaction::flush(),
+        Transaction::read_many(response),
+    ]
+}
+
+/// Transactions of the command of `vector`
+fn exchange(vector: TestVector) -> Vec<Transaction<u8>> {
+    command(vector.mosi, vector.miso)
+}
+
+/// Mock expecting `commands` in order
+fn mock(commands: impl IntoIterator<Item = Vec<Transaction<u8>>>) -> Mock<u8> {
+    let transactions: Vec<_> = commands.into_iter().flatten().collect();
+    Mock::new(&transactions)
+}
+
+/// Driver in measurement mode, float format, after the Start Measurement transactions
+fn measuring(serial: &Mock<u8>) -> Sps30<Mock<u8>, sps30::Measuring> {
+    Sps30::new(serial.clone())
+        .start_measurement(MeasurementFormat::Float)
+        .ok()
+        .unwrap()
+}
+
+#[test]
+fn start_and_stop_measurement() {
+    let mut serial = mock([
+        exchange(test_vectors::START_MEASUREMENT),
+        exchange(test_vectors::STOP_MEASUREMENT),
+    ]);
+    let sensor = measuring(&serial);
+    assert_eq!(sensor.active_format(), MeasurementFormat::Float);
+    assert!(sensor.stop_measurement().is_ok());
+    serial.done();
+}
+
+#[cfg(not(feature = "no-float"))]
+#[test]
+fn read_measurement_float() {
+    let mut serial = mock([
+        exchange(test_vectors::START_MEASUREMENT),
+        command(
+            test_vectors::READ_MEASURED_VALUES.mosi,
+            &[
+                0x7E, 0x00, 0x03, 0x00, 0x28, 0x3F, 0xC0, 0x00, 0x00, 0x40, 0x20, 0x00, 0x00, 0x40,
+                0x40, 0x00, 0x00, 0x40, 0x80, 0x00, 0x00, 0x40, 0xA0, 0x00, 0x00, 0x40, 0xC0, 0x00,
+                0x00, 0x40, 0xE0, 0x00, 0x00, 0x41, 0x00, 0x00, 0x00, 0x41, 0x10, 0x00, 0x00, 0x3F,
+                0x00, 0x00, 0x00, 0x64, 0x7E,
+            ],
+        ),
+    ]);
+    let mut sensor = measuring(&serial);
+    let measurement = sensor.read_measurement().unwrap();
+    assert_eq!(
+        measurement.to_array(),
+        [1.5, 2.5, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 0.5]
+    );
+    serial.done();
+}
+
+#[test]
+fn read_measurement_u16_reads_version_first() {
+    let mut serial = mock([
+        exchange(test_vectors::READ_VERSION),
+        command(
+            &[0x7E, 0x00, 0x00, 0x02, 0x01, 0x05, 0xF7, 0x7E],
+            test_vectors::START_MEASUREMENT.miso,
+        ),
+        command(
+            test_vectors::READ_MEASURED_VALUES.mosi,
+            &[
+                0x7E, 0x00, 0x03, 0x00, 0x14, 0x00, 0x01, 0x00, 0x02, 0x00, 0x03, 0x00, 0x04, 0x00,
+                0x05, 0x00, 0x06, 0x00, 0x07, 0x00, 0x08, 0x00, 0x09, 0x01, 0xF4, 0xC6, 0x7E,
+            ],
+        ),
+    ]);
+    let mut sensor = Sps30::new(serial.clone())
+        .start_measurement(MeasurementFormat::UnsignedInteger16)
+        .ok()
+        .unwrap();
+    let measurement = sensor.read_measurement_u16().unwrap();
+    assert_eq!(measurement.to_array(), [1, 2, 3, 4, 5, 6, 7, 8, 9, 500]);
+    serial.done();
+}
+
+#[test]
+fn integer_format_refused_on_firmware_1() {
+    let mut serial = mock([command(test_vectors::READ_VERSION.mosi, VERSION_1_0)]);
+    let (error, _sensor) = Sps30::new(serial.clone())
+        .start_measurement(MeasurementFormat::UnsignedInteger16)
+        .err()
+        .unwrap();
+    assert!(matches!(
+        error,
+        Error::UnsupportedFirmware {
+            required: Version { major: 2, minor: 0 },
+            actual: Version { major: 1, minor: 0 },
+        }
+    ));
+    serial.done();
+}
+
+#[cfg(feature = "cleaning")]
+#[test]
+fn cleaning_interval() {
+    let mut serial = mock([
+        // Checksum 0x7E of the request is escaped
+        exchange(test_vectors::READ_CLEANING_INTERVAL),
+        command(
+            &[
+                0x7E, 0x00, 0x80, 0x05, 0x00, 0x00, 0x00, 0x0E, 0x10, 0x5C, 0x7E,
+            ],
+            test_vectors::WRITE_CLEANING_INTERVAL.miso,
+        ),
+        exchange(test_vectors::WRITE_CLEANING_INTERVAL),
+    ]);
+    let mut sensor = Sps30::new(serial.clone());
+    assert_eq!(
+        sensor.read_cleaning_interval().unwrap(),
+        AutoCleanInterval::DEFAULT
+    );
+    assert!(sensor
+        .write_cleaning_interval(AutoCleanInterval::from_secs(3600))
+        .is_ok());
+    assert!(sensor.disable_auto_cleaning().is_ok());
+    serial.done();
+}
+
+#[cfg(feature = "fan-clean")]
+#[test]
+fn start_fan_cleaning() {
+    let mut serial = mock([
+        exchange(test_vectors::START_MEASUREMENT),
+        exchange(test_vectors::START_FAN_CLEANING),
+    ]);
+    let mut sensor = measuring(&serial);
+    assert!(sensor.start_fan_cleaning().is_ok());
+    assert!(sensor.cleaning_in_progress());
+    serial.done();
+}
+
+#[cfg(feature = "device-info")]
+#[test]
+fn device_information() {
+    let mut serial = mock([
+        // Length 0x11 of the response is escaped
+        exchange(test_vectors::SERIAL_NUMBER),
+        command(
+            &[0x7E, 0x00, 0xD0, 0x01, 0x01, 0x2D, 0x7E],
+            &[
+                0x7E, 0x00, 0xD0, 0x00, 0x09, 0x30, 0x30, 0x30, 0x38, 0x30, 0x30, 0x30, 0x30, 0x00,
+                0x9E, 0x7E,
+            ],
+        ),
+    ]);
+    let mut sensor = Sps30::new(serial.clone());
+    assert_eq!(
+        sensor.device_info(DeviceInfo::SerialNumber).unwrap(),
+        "ABCDEF0123456789"
+    );
+    assert_eq!(
+        sensor.device_info(DeviceInfo::ProductName).unwrap(),
+        sps30::SPS30_PRODUCT_NAME
+    );
+    serial.done();
+}
+
+#[test]
+fn version() {
+    let mut serial = mock([exchange(test_vectors::READ_VERSION)]);
+    let mut sensor = Sps30::new(serial.clone());
+    let versions = sensor.version().unwrap();
+    assert_eq!(versions.firmware, Version::new(2, 2));
+    assert_eq!(versions.hardware_revision, 7);
+    assert_eq!(versions.shdlc, Version::new(2, 0));
+    assert_eq!(sensor.firmware_version(), Some(Version::new(2, 2)));
+    serial.done();
+}
+
+#[test]
+fn device_status() {
+    let mut serial = mock([
+        exchange(test_vectors::READ_VERSION),
+        command(
+            test_vectors::READ_DEVICE_STATUS.mosi,
+            &[
+                0x7E, 0x00, 0xD2, 0x00, 0x05, 0x00, 0x20, 0x00, 0x10, 0x00, 0xF8, 0x7E,
+            ],
+        ),
+    ]);
+    let mut sensor = Sps30::new(serial.clone());
+    let status = sensor.read_device_status(false).unwrap();
+    assert!(status.fan_speed_warning());
+    assert!(status.fan_error());
+    assert!(!status.laser_error());
+    serial.done();
+}
+
+#[test]
+fn reset() {
+    let mut serial = mock([
+        exchange(test_vectors::START_MEASUREMENT),
+        exchange(test_vectors::RESET),
+    ]);
+    let sensor = measuring(&serial);
+    assert!(sensor.reset().is_ok());
+    serial.done();
+}
+
+#[test]
+fn sleep_and_wake_up() {
+    let mut serial = mock([
+        exchange(test_vectors::READ_VERSION),
+        exchange(test_vectors::SLEEP),
+        // Low pulse activating the interface
+        vec![Transaction::write(0xFF), Transaction::flush()],
+        // Command 0x11 is escaped
+        exchange(test_vectors::WAKE_UP),
+    ]);
+    let sensor = Sps30::new(serial.clone()).sleep().ok().unwrap();
+    assert!(sensor.wake_up().is_ok());
+    serial.done();
+}
+
+#[cfg(not(feature = "no-float"))]
+#[test]
+fn empty_result() {
+    let mut serial = mock([
+        exchange(test_vectors::START_MEASUREMENT),
+        exchange(test_vectors::READ_MEASURED_VALUES_EMPTY),
+    ]);
+    let mut sensor = measuring(&serial);
+    assert!(matches!(sensor.read_measurement(), Err(Error::EmptyResult)));
+    serial.done();
+}
+
+#[test]
+fn bad_checksum() {
+    let mut serial = mock([command(
+        test_vectors::READ_VERSION.mosi,
+        &[
+            0x7E, 0x00, 0xD1, 0x00, 0x07, 0x02, 0x02, 0x00, 0x07, 0x00, 0x02, 0x00, 0x1B, 0x7E,
+        ],
+    )]);
+    let mut sensor = Sps30::new(serial.clone());
+    assert!(matches!(sensor.version(), Err(Error::ChecksumFailed)));
+    assert_eq!(sensor.link_stats().checksum_failures, 1);
+    serial.done();
+}
+
+#[test]
+fn wrong_command_echo() {
+    // Read Version response to Start Measurement
+    let mut serial = mock([command(
+        test_vectors::START_MEASUREMENT.mosi,
+        test_vectors::READ_VERSION.miso,
+    )]);
+    let result = Sps30::new(serial.clone()).start_measurement(MeasurementFormat::Float);
+    assert!(matches!(result, Err((Error::InvalidRespose, _))));
+    serial.done();
+}
+
+#[test]
+fn short_frame() {
+    // ADR and a valid checksum, nothing else
+    let mut serial = mock([command(
+        test_vectors::READ_VERSION.mosi,
+        &[0x7E, 0x00, 0xFF, 0x7E],
+    )]);
+    let mut sensor = Sps30::new(serial.clone());
+    assert!(matches!(sensor.version(), Err(Error::InvalidRespose)));
+    serial.done();
+}
+
+#[test]
+fn wrong_data_length() {
+    // Read Version response with 6 data bytes
+    let mut serial = mock([command(
+        test_vectors::READ_VERSION.mosi,
+        &[
+            0x7E, 0x00, 0xD1, 0x00, 0x06, 0x02, 0x02, 0x00, 0x07, 0x00, 0x02, 0x1B, 0x7E,
+        ],
+    )]);
+    let mut sensor = Sps30::new(serial.clone());
+    assert!(matches!(sensor.version(), Err(Error::InvalidRespose)));
+    serial.done();
+}
+
+#[test]
+fn non_zero_state() {
+    let mut serial = mock([exchange(test_vectors::START_MEASUREMENT_NOT_ALLOWED)]);
+    let result = Sps30::new(serial.clone()).start_measurement(MeasurementFormat::Float);
+    assert!(matches!(
+        result,
+        Err((Error::StatusError(DeviceError::NotAllowedInState), _))
+    ));
+    serial.done();
+}
+
+#[test]
+fn no_response() {
+    let mut serial = Mock::new(&[
+        Transaction::write_many(test_vectors::READ_VERSION.mosi),
+        Transaction::flush(),
+        Transaction::read_error(nb::Error::WouldBlock),
+    ]);
+    let mut sensor = Sps30::new(serial.clone());
+    assert!(matches!(
+        sensor.version(),
+        Err(Error::SerialR(nb::Error::WouldBlock))
+    ));
+    serial.done();
+}
+
+#[test]
+fn serial_errors() {
+    let mut serial = Mock::new(&[
+        Transaction::write_error(0x7E, nb::Error::Other(ErrorKind::Other)),
+        Transaction::write_many(test_vectors::READ_VERSION.mosi),
+        Transaction::flush(),
+        Transaction::read_error(nb::Error::Other(ErrorKind::Overrun)),
+    ]);
+    let mut sensor = Sps30::new(serial.clone());
+    assert!(matches!(
+        sensor.version(),
+        Err(Error::SerialW(ErrorKind::Other))
+    ));
+    assert!(matches!(
+        sensor.version(),
+        Err(Error::SerialR(nb::Error::Other(ErrorKind::Overrun)))
+    ));
+    serial.done();
+}